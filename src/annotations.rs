@@ -0,0 +1,216 @@
+//! User annotations (`--annotations`)
+//!
+//! Annotations fill in information the analysis can't figure out on its own. They can be written
+//! in TOML or, if the file name ends in `.json`, in JSON:
+//!
+//! ``` toml
+//! # `app::foo` only ever calls `app::bar` and `app::baz`
+//! [functions."app::foo"]
+//! calls = ["app::bar", "app::baz"]
+//!
+//! # `memcpy` (written in assembly) uses 16 bytes of stack
+//! [functions.memcpy]
+//! stack = 16
+//!
+//! # `app::walk` recurses at most 8 times
+//! [functions."app::walk"]
+//! max_recursion = 8
+//!
+//! # calls through `fn() -> i32` pointers only reach `app::quux`
+//! [indirect]
+//! "i32 ()*" = ["app::quux"]
+//! ```
+//!
+//! Functions are named by their symbol name, their demangled name or their demangled path without
+//! hashes. Indirect calls are named like the fictitious nodes in the call graph.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use failure::format_err;
+use log::warn;
+use petgraph::graph::{Graph, NodeIndex};
+use serde::Deserialize;
+
+use crate::{pattern, Call, Local, Node};
+
+/// Annotations loaded from a file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Annotations {
+    #[serde(default)]
+    functions: BTreeMap<String, Function>,
+    // fictitious node -> callees
+    #[serde(default)]
+    indirect: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Function {
+    // all the functions this function calls
+    calls: Option<Vec<String>>,
+    // local stack usage in bytes
+    stack: Option<u64>,
+    // maximum recursion depth of the cycle this function is part of
+    max_recursion: Option<u64>,
+}
+
+impl Annotations {
+    /// Loads an annotations file
+    pub fn load(path: &Path) -> Result<Self, failure::Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format_err!("couldn't read `{}`: {}", path.display(), e))?;
+
+        if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+            serde_json::from_str(&contents).map_err(failure::Error::from)
+        } else {
+            toml::from_str(&contents).map_err(failure::Error::from)
+        }
+        .map_err(|e| format_err!("couldn't parse `{}`: {}", path.display(), e))
+    }
+}
+
+fn find(g: &Graph<Node, Call>, name: &str) -> Vec<NodeIndex> {
+    g.node_indices()
+        .filter(|inode| !g[*inode].dashed && pattern::is(&g[*inode], name))
+        .collect()
+}
+
+// replaces all the outgoing edges of `caller`; callees that were already called keep the kind of
+// call
+fn set_callees(g: &mut Graph<Node, Call>, caller: NodeIndex, callees: &[String]) {
+    let mut calls = BTreeMap::new();
+    while let Some(edge) = g.first_edge(caller, petgraph::Direction::Outgoing) {
+        let callee = g.edge_endpoints(edge).expect("UNREACHABLE").1;
+        calls.insert(callee, g[edge]);
+        g.remove_edge(edge);
+    }
+
+    for name in callees {
+        let hits = find(g, name);
+        if hits.is_empty() {
+            warn!("annotation: function `{}` not found", name);
+        }
+
+        for callee in hits {
+            let call = calls.get(&callee).cloned().unwrap_or(Call::Normal);
+            g.add_edge(caller, callee, call);
+        }
+    }
+}
+
+/// Applies the annotations to the call graph; the maximum stack usage must be computed afterwards
+///
+/// Returns the functions that were annotated
+pub(crate) fn apply(g: &mut Graph<Node, Call>, annotations: &Annotations) -> Vec<NodeIndex> {
+    let mut annotated = vec![];
+    for (name, function) in &annotations.functions {
+        let hits = find(g, name);
+        if hits.is_empty() {
+            warn!("annotation: function `{}` not found", name);
+        }
+
+        for inode in hits {
+            annotated.push(inode);
+
+            if let Some(stack) = function.stack {
+                g[inode].local = Local::Exact(stack);
+            }
+
+            if let Some(depth) = function.max_recursion {
+                g[inode].max_recursion = Some(depth);
+            }
+
+            if let Some(calls) = &function.calls {
+                set_callees(g, inode, calls);
+            }
+        }
+    }
+
+    for (name, callees) in &annotations.indirect {
+        let hits = g
+            .node_indices()
+            .filter(|inode| g[*inode].dashed && g[*inode].name == **name)
+            .collect::<Vec<_>>();
+        if hits.is_empty() {
+            warn!("annotation: indirect call `{}` not found", name);
+        }
+
+        for inode in hits {
+            set_callees(g, inode, callees);
+        }
+    }
+
+    annotated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Annotations;
+    use crate::{Call, Local, Node};
+
+    #[test]
+    fn apply() {
+        let annotations: Annotations = toml::from_str(
+            r#"
+[functions.main]
+calls = ["foo"]
+
+[functions.foo]
+stack = 16
+max_recursion = 2
+
+[indirect]
+"i32 ()*" = ["bar"]
+"#,
+        )
+        .unwrap();
+
+        let (mut g, [main, foo, bar, baz]) = crate::graph(
+            [
+                ("main", Some(8)),
+                ("foo", None),
+                ("bar", Some(4)),
+                ("baz", Some(4)),
+            ],
+            &[],
+        );
+        let fp = g.add_node(Node("i32 ()*", Some(0), true));
+        g.add_edge(main, fp, Call::Normal);
+        g.add_edge(fp, bar, Call::Normal);
+        g.add_edge(fp, baz, Call::Normal);
+        g.add_edge(foo, fp, Call::Normal);
+
+        super::apply(&mut g, &annotations);
+
+        assert!(g.contains_edge(main, foo));
+        assert!(!g.contains_edge(main, fp));
+        assert_eq!(g[foo].local, Local::Exact(16));
+        assert_eq!(g[foo].max_recursion, Some(2));
+        assert!(g.contains_edge(fp, bar));
+        assert!(!g.contains_edge(fp, baz));
+    }
+
+    #[test]
+    fn tail_calls() {
+        let annotations: Annotations = toml::from_str(
+            r#"
+[functions.main]
+calls = ["foo", "bar"]
+"#,
+        )
+        .unwrap();
+
+        let (mut g, [main, foo, bar]) = crate::graph(
+            [("main", Some(8)), ("foo", Some(16)), ("bar", Some(4))],
+            &[],
+        );
+        g.add_edge(main, foo, Call::Tail);
+
+        super::apply(&mut g, &annotations);
+
+        let call = |callee| g[g.find_edge(main, callee).unwrap()];
+        assert_eq!(call(foo), Call::Tail);
+        assert_eq!(call(bar), Call::Normal);
+    }
+}
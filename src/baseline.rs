@@ -0,0 +1,293 @@
+//! Per-function stack usage baseline (`--baseline`)
+//!
+//! The baseline file is meant to be committed to the repository; it records the maximum stack
+//! usage of the functions that are tracked:
+//!
+//! ``` toml
+//! [functions.main]
+//! max = 312
+//!
+//! # the maximum stack usage of `app::recurse` is a lower bound
+//! [functions."app::recurse"]
+//! max = 48
+//! lower_bound = true
+//!
+//! # `app::scratch` has a dynamically sized stack frame
+//! [functions."app::scratch"]
+//! max = 0
+//! unbounded = true
+//! ```
+//!
+//! The check fails if a tracked function uses more stack than recorded, beyond a tolerance, if its
+//! maximum stack usage is no longer exact or if it becomes unbounded.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+use crate::{budget::Verdict, CallGraph, Max, Node};
+
+/// Recorded maximum stack usage of the tracked functions
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Baseline {
+    #[serde(default)]
+    functions: BTreeMap<String, Entry>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    max: u64,
+    #[serde(default, skip_serializing_if = "is_false")]
+    lower_bound: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    unbounded: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+impl From<Max> for Entry {
+    fn from(max: Max) -> Self {
+        match max {
+            Max::Exact(max) => Entry {
+                max,
+                lower_bound: false,
+                unbounded: false,
+            },
+            Max::LowerBound(max) => Entry {
+                max,
+                lower_bound: true,
+                unbounded: false,
+            },
+            Max::Unbounded => Entry {
+                max: 0,
+                lower_bound: false,
+                unbounded: true,
+            },
+        }
+    }
+}
+
+impl From<Entry> for Max {
+    fn from(entry: Entry) -> Self {
+        if entry.unbounded {
+            Max::Unbounded
+        } else if entry.lower_bound {
+            Max::LowerBound(entry.max)
+        } else {
+            Max::Exact(entry.max)
+        }
+    }
+}
+
+/// How much a tracked function may grow before the check fails
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tolerance {
+    /// This many bytes
+    Bytes(u64),
+    /// This percentage of the recorded maximum stack usage
+    Percent(u64),
+}
+
+impl Tolerance {
+    /// Parses a number of bytes, e.g. `16`, or a percentage, e.g. `5%`
+    pub fn parse(s: &str) -> Option<Self> {
+        if let Some(percent) = s.strip_suffix('%') {
+            percent.parse().ok().map(Tolerance::Percent)
+        } else {
+            s.parse().ok().map(Tolerance::Bytes)
+        }
+    }
+
+    // the largest maximum stack usage allowed for a function that used to use `max` bytes
+    fn limit(self, max: u64) -> u64 {
+        match self {
+            Tolerance::Bytes(n) => max.saturating_add(n),
+            Tolerance::Percent(p) => max.saturating_add(max.saturating_mul(p) / 100),
+        }
+    }
+}
+
+impl Baseline {
+    /// Loads a baseline file
+    pub fn load(path: &Path) -> Result<Self, failure::Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format_err!("couldn't read `{}`: {}", path.display(), e))?;
+        toml::from_str(&contents)
+            .map_err(|e| format_err!("couldn't parse `{}`: {}", path.display(), e))
+    }
+
+    /// Writes the baseline to a file
+    pub fn save(&self, path: &Path) -> Result<(), failure::Error> {
+        let contents = toml::to_string(self)
+            .map_err(|e| format_err!("couldn't serialize the baseline: {}", e))?;
+        fs::write(path, contents)
+            .map_err(|e| format_err!("couldn't write `{}`: {}", path.display(), e))
+    }
+
+    /// The names of the tracked functions
+    pub fn functions(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(|function| &**function)
+    }
+
+    /// Records the current maximum stack usage of the `functions`
+    pub fn record<'a>(cg: &CallGraph, functions: impl IntoIterator<Item = &'a Node<'a>>) -> Self {
+        let mut baseline = Baseline::default();
+
+        for node in functions {
+            if let Some(max) = node.max() {
+                baseline.functions.insert(name_of(cg, node), max.into());
+            }
+        }
+
+        baseline
+    }
+
+    /// Checks the current maximum stack usage of the tracked functions against the baseline and
+    /// prints a report to stderr
+    pub fn check(&self, cg: &CallGraph, tolerance: Tolerance) -> Verdict {
+        let mut verdict = Verdict::Pass;
+
+        for (function, entry) in &self.functions {
+            let max = if let Some(max) = cg.max_stack(function) {
+                max
+            } else {
+                eprintln!(
+                    "error: tracked function `{}` not found or without stack usage information",
+                    function
+                );
+                verdict = verdict.max(Verdict::Unknown);
+                continue;
+            };
+
+            let v = compare(*entry, max, tolerance);
+            let status = match v {
+                Verdict::Pass => "ok",
+                Verdict::Unknown => "UNKNOWN",
+                Verdict::OverBudget => "REGRESSION",
+            };
+            eprintln!(
+                "{}: `{}` max {} (baseline: {})",
+                status,
+                function,
+                max.in_bytes(),
+                Max::from(*entry).in_bytes()
+            );
+
+            if v != Verdict::Pass {
+                eprintln!("worst-case call path:");
+                cg.print_worst_path(function);
+            }
+
+            verdict = verdict.max(v);
+        }
+
+        verdict
+    }
+}
+
+fn compare(entry: Entry, max: Max, tolerance: Tolerance) -> Verdict {
+    if entry.unbounded {
+        // nothing to regress from
+        Verdict::Pass
+    } else if max == Max::Unbounded {
+        // the function, or one of its callees, gained a dynamically sized stack frame
+        Verdict::OverBudget
+    } else if max.bytes() > tolerance.limit(entry.max) {
+        Verdict::OverBudget
+    } else if !entry.lower_bound && matches!(max, Max::LowerBound(_)) {
+        // the maximum stack usage can no longer be bounded
+        Verdict::Unknown
+    } else {
+        Verdict::Pass
+    }
+}
+
+// a name that still refers to `node` after the program is rebuilt, if possible
+fn name_of(cg: &CallGraph, node: &Node) -> String {
+    let path = format!("{:#}", rustc_demangle::demangle(node.name()));
+
+    if cg
+        .node(&path)
+        .map(|other| other.name() == node.name())
+        .unwrap_or(false)
+    {
+        path
+    } else {
+        node.name().to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, Tolerance};
+    use crate::{budget::Verdict, Max};
+
+    #[test]
+    fn compare() {
+        let exact = Entry::from(Max::Exact(100));
+        let lower_bound = Entry::from(Max::LowerBound(100));
+        let unbounded = Entry::from(Max::Unbounded);
+        let none = Tolerance::Bytes(0);
+
+        assert_eq!(super::compare(exact, Max::Exact(100), none), Verdict::Pass);
+        assert_eq!(super::compare(exact, Max::Exact(90), none), Verdict::Pass);
+        assert_eq!(
+            super::compare(exact, Max::Exact(101), none),
+            Verdict::OverBudget
+        );
+        assert_eq!(
+            super::compare(exact, Max::Exact(104), Tolerance::Bytes(4)),
+            Verdict::Pass
+        );
+        assert_eq!(
+            super::compare(exact, Max::Exact(106), Tolerance::Percent(5)),
+            Verdict::OverBudget
+        );
+        // a huge tolerance must not overflow
+        assert_eq!(
+            super::compare(exact, Max::Exact(u64::MAX), Tolerance::Bytes(u64::MAX)),
+            Verdict::Pass
+        );
+        assert_eq!(
+            super::compare(exact, Max::Exact(1000), Tolerance::Percent(u64::MAX)),
+            Verdict::Pass
+        );
+        assert_eq!(
+            super::compare(exact, Max::LowerBound(100), none),
+            Verdict::Unknown
+        );
+        assert_eq!(
+            super::compare(lower_bound, Max::LowerBound(100), none),
+            Verdict::Pass
+        );
+        assert_eq!(
+            super::compare(lower_bound, Max::Unbounded, none),
+            Verdict::OverBudget
+        );
+        assert_eq!(
+            super::compare(unbounded, Max::Unbounded, none),
+            Verdict::Pass
+        );
+
+        assert_eq!(Tolerance::parse("16"), Some(Tolerance::Bytes(16)));
+        assert_eq!(Tolerance::parse("5%"), Some(Tolerance::Percent(5)));
+        assert_eq!(Tolerance::parse("five"), None);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let toml = "[functions.\"app::recurse\"]\nmax = 48\nlower_bound = true\n\n[functions.main]\nmax = 312\n";
+        let baseline: super::Baseline = toml::from_str(toml).unwrap();
+
+        assert_eq!(
+            baseline.functions["app::recurse"],
+            Entry::from(Max::LowerBound(48))
+        );
+        assert_eq!(toml::to_string(&baseline).unwrap(), toml);
+    }
+}
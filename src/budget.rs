@@ -0,0 +1,171 @@
+//! Stack budget check (`--max-stack`)
+
+use petgraph::{
+    graph::{Graph, NodeIndex},
+    visit::Dfs,
+};
+
+use crate::{Call, CallGraph, Local, Max, Node};
+
+/// Outcome of checking the worst-case stack usage of the program against a budget
+// NOTE variants are sorted by severity
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Verdict {
+    /// The worst-case stack usage is known and fits in the budget
+    Pass,
+
+    /// The worst-case stack usage could not be bounded (unknown stack usage or cycles)
+    Unknown,
+
+    /// The worst-case stack usage exceeds the budget
+    OverBudget,
+}
+
+impl Verdict {
+    /// Exit code `cargo-call-stack` reports for this verdict
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Verdict::Pass => 0,
+            // NOTE `1` is used for all other errors
+            Verdict::Unknown | Verdict::OverBudget => 2,
+        }
+    }
+}
+
+/// Checks the maximum stack usage of the start point (or of every root of the call graph) against
+/// the `budget` and prints a report to stderr
+///
+/// If `allow_unknown` is set lower bounds of the maximum stack usage that fit in the budget pass
+/// the check
+pub fn check(cg: &CallGraph, budget: u64, allow_unknown: bool) -> Verdict {
+    check_roots(&cg.g, &cg.roots(), &cg.cycles, budget, allow_unknown)
+}
+
+fn check_roots(
+    g: &Graph<Node, Call>,
+    roots: &[NodeIndex],
+    cycles: &[Vec<NodeIndex>],
+    budget: u64,
+    allow_unknown: bool,
+) -> Verdict {
+    let mut verdict = Verdict::Pass;
+
+    for root in roots {
+        let node = &g[*root];
+
+        let v = match node.max {
+            None => {
+                eprintln!(
+                    "error: `{}` has no stack usage information; can't check the stack budget",
+                    node.demangled
+                );
+
+                verdict = verdict.max(Verdict::Unknown);
+                continue;
+            }
+
+            Some(Max::Exact(n)) if n > budget => Verdict::OverBudget,
+            Some(Max::Exact(_)) => Verdict::Pass,
+            Some(Max::LowerBound(n)) if n > budget => Verdict::OverBudget,
+            Some(Max::LowerBound(_)) if allow_unknown => Verdict::Pass,
+            Some(Max::LowerBound(_)) => Verdict::Unknown,
+            Some(Max::Unbounded) => Verdict::OverBudget,
+        };
+
+        let max = node.max.expect("UNREACHABLE");
+        let status = match v {
+            Verdict::Pass => "ok",
+            Verdict::Unknown => "UNKNOWN",
+            Verdict::OverBudget => "OVER BUDGET",
+        };
+        eprintln!(
+            "{}: `{}` max {} (budget: {} bytes)",
+            status,
+            node.demangled,
+            max.in_bytes(),
+            budget
+        );
+
+        if v != Verdict::Pass {
+            eprintln!("worst-case call path:");
+            crate::print_worst_path(g, *root, cycles);
+
+            if let Max::LowerBound(_) = max {
+                let mut unknown = vec![];
+                let mut dfs = Dfs::new(g, *root);
+                while let Some(inode) = dfs.next(g) {
+                    if g[inode].local == Local::Unknown {
+                        unknown.push(&*g[inode].demangled);
+                    }
+                }
+
+                if unknown.is_empty() {
+                    eprintln!("note: the call graph contains cycles that use stack space");
+                } else {
+                    unknown.sort();
+                    unknown.dedup();
+                    eprintln!("note: functions with unknown stack usage: {:?}", unknown);
+                }
+            } else if let Max::Unbounded = max {
+                let mut unbounded = vec![];
+                let mut dfs = Dfs::new(g, *root);
+                while let Some(inode) = dfs.next(g) {
+                    if g[inode].local == Local::Unbounded {
+                        unbounded.push(&*g[inode].demangled);
+                    }
+                }
+
+                unbounded.sort();
+                unbounded.dedup();
+                eprintln!(
+                    "note: functions with dynamically sized stack frames: {:?}",
+                    unbounded
+                );
+            }
+        }
+
+        verdict = verdict.max(v);
+    }
+
+    verdict
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Verdict;
+    use crate::{Local, Max};
+
+    #[test]
+    fn verdict() {
+        let (mut g, [main, foo]) =
+            crate::graph([("main", Some(8)), ("foo", Some(16))], &[("main", "foo")]);
+        g[foo].max = Some(Max::Exact(16));
+        g[main].max = Some(Max::Exact(24));
+
+        assert_eq!(
+            super::check_roots(&g, &[main], &[], 24, false),
+            Verdict::Pass
+        );
+        assert_eq!(
+            super::check_roots(&g, &[main], &[], 16, false),
+            Verdict::OverBudget
+        );
+
+        g[foo].local = Local::Unknown;
+        g[foo].max = Some(Max::LowerBound(0));
+        g[main].max = Some(Max::LowerBound(8));
+
+        assert_eq!(
+            super::check_roots(&g, &[main], &[], 24, false),
+            Verdict::Unknown
+        );
+        assert_eq!(
+            super::check_roots(&g, &[main], &[], 24, true),
+            Verdict::Pass
+        );
+        assert_eq!(
+            super::check_roots(&g, &[main], &[], 4, true),
+            Verdict::OverBudget
+        );
+    }
+}
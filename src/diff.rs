@@ -0,0 +1,505 @@
+//! Comparison of two analyses (`diff` subcommand)
+//!
+//! Analyses are saved with `--format json`. Functions are matched by their demangled path without
+//! hashes so that the comparison survives changes to the symbol hashes between builds.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use failure::format_err;
+
+use crate::{
+    json::{Saved, SavedNode, VERSION},
+    Escaper, Local, Max, FONT,
+};
+
+/// An analysis saved in JSON format
+pub struct Analysis {
+    saved: Saved,
+}
+
+impl Analysis {
+    /// Loads an analysis saved with `--format json`
+    pub fn load(path: &Path) -> Result<Self, failure::Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format_err!("couldn't read `{}`: {}", path.display(), e))?;
+        let saved = parse(&contents)
+            .map_err(|e| format_err!("couldn't parse `{}`: {}", path.display(), e))?;
+
+        if saved.version > VERSION {
+            return Err(format_err!(
+                "`{}` uses version {} of the JSON format but only versions up to {} are supported",
+                path.display(),
+                saved.version,
+                VERSION
+            ));
+        }
+
+        Ok(Analysis { saved })
+    }
+
+    // functions by key; when several functions share a key the one with the largest maximum stack
+    // usage is kept
+    fn functions(&self) -> BTreeMap<String, &SavedNode> {
+        let mut functions = BTreeMap::<_, &SavedNode>::new();
+        for node in &self.saved.nodes {
+            let key = key(node);
+            let bytes = |node: &SavedNode| node.max.map(Max::bytes);
+            if functions
+                .get(&key)
+                .map(|other| bytes(other) < bytes(node))
+                .unwrap_or(true)
+            {
+                functions.insert(key, node);
+            }
+        }
+        functions
+    }
+
+    fn edges(&self) -> BTreeSet<(String, String, bool)> {
+        self.saved
+            .edges
+            .iter()
+            .map(|edge| {
+                (
+                    key(&self.saved.nodes[edge.source]),
+                    key(&self.saved.nodes[edge.target]),
+                    edge.tail,
+                )
+            })
+            .collect()
+    }
+
+    fn cycles(&self) -> BTreeSet<BTreeSet<String>> {
+        self.saved
+            .cycles
+            .iter()
+            .map(|cycle| {
+                cycle
+                    .iter()
+                    .map(|node| key(&self.saved.nodes[*node]))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// parses a saved analysis and checks that edges and cycles only refer to nodes that exist
+fn parse(contents: &str) -> Result<Saved, failure::Error> {
+    let saved: Saved = serde_json::from_str(contents)?;
+
+    let nodes = saved.nodes.len();
+    let ids = saved
+        .edges
+        .iter()
+        .flat_map(|edge| vec![edge.source, edge.target])
+        .chain(saved.cycles.iter().flatten().cloned());
+    for id in ids {
+        if id >= nodes {
+            return Err(format_err!(
+                "node ID {} is out of range; there are {} nodes",
+                id,
+                nodes
+            ));
+        }
+    }
+
+    Ok(saved)
+}
+
+// demangled path without hashes; fictitious nodes keep their signature
+fn key(node: &SavedNode) -> String {
+    format!("{:#}", rustc_demangle::demangle(&node.name))
+}
+
+/// The stack usage of a function before and after
+pub struct Change {
+    /// Demangled path without hashes
+    pub function: String,
+    /// `None` if the function didn't exist
+    pub old: Option<(Local, Option<Max>)>,
+    /// `None` if the function no longer exists
+    pub new: Option<(Local, Option<Max>)>,
+}
+
+impl Change {
+    /// Change in maximum stack usage, in bytes
+    ///
+    /// Becoming unbounded counts as the largest possible increase
+    pub fn delta(&self) -> i64 {
+        let bytes = |usage: Option<(Local, Option<Max>)>| match usage.and_then(|(_, max)| max) {
+            Some(Max::Unbounded) => i64::MAX,
+            Some(max) => max.bytes() as i64,
+            None => 0,
+        };
+
+        bytes(self.new).saturating_sub(bytes(self.old))
+    }
+}
+
+/// Differences between two analyses
+pub struct Diff {
+    /// Functions that appeared, disappeared or whose stack usage changed; sorted by decreasing
+    /// `delta`
+    pub changes: Vec<Change>,
+    /// Calls that appeared: (caller, callee, is a tail call)
+    pub added_edges: Vec<(String, String, bool)>,
+    /// Calls that disappeared: (caller, callee, is a tail call)
+    pub removed_edges: Vec<(String, String, bool)>,
+    /// Cycles that are not present in the old analysis
+    pub new_cycles: Vec<Vec<String>>,
+    /// Functions whose stack usage is now unknown
+    pub new_unknown: Vec<String>,
+}
+
+/// Compares the `old` analysis against the `new` one
+pub fn diff(old: &Analysis, new: &Analysis) -> Diff {
+    let old_functions = old.functions();
+    let new_functions = new.functions();
+
+    let usage = |node: &SavedNode| (node.local, node.max);
+    let mut changes = old_functions
+        .keys()
+        .chain(new_functions.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|function| Change {
+            function: function.clone(),
+            old: old_functions.get(function).map(|node| usage(node)),
+            new: new_functions.get(function).map(|node| usage(node)),
+        })
+        .filter(|change| change.old != change.new)
+        .collect::<Vec<_>>();
+    // NOTE stable sort; ties remain sorted by name
+    changes.sort_by_key(|change| -change.delta());
+
+    let old_edges = old.edges();
+    let new_edges = new.edges();
+
+    let old_cycles = old.cycles();
+
+    let new_unknown = new_functions
+        .iter()
+        .filter(|(function, node)| {
+            node.local == Local::Unknown
+                && old_functions
+                    .get(*function)
+                    .map(|old| old.local != Local::Unknown)
+                    .unwrap_or(true)
+        })
+        .map(|(function, _)| function.clone())
+        .collect();
+
+    Diff {
+        changes,
+        added_edges: new_edges.difference(&old_edges).cloned().collect(),
+        removed_edges: old_edges.difference(&new_edges).cloned().collect(),
+        new_cycles: new
+            .cycles()
+            .into_iter()
+            .filter(|cycle| !old_cycles.contains(cycle))
+            .map(|cycle| cycle.into_iter().collect())
+            .collect(),
+        new_unknown,
+    }
+}
+
+impl Diff {
+    /// Writes a human readable report
+    pub fn report<W>(&self, mut w: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        if !self.changes.is_empty() {
+            writeln!(w, "stack usage changes:")?;
+            for change in &self.changes {
+                write!(w, "  {:>9} {} ", show_delta(change), change.function)?;
+                match (change.old, change.new) {
+                    (None, Some((local, max))) => writeln!(
+                        w,
+                        "(new; max {}, local {})",
+                        show_max(max),
+                        show_local(local)
+                    )?,
+                    (Some((_, max)), None) => writeln!(w, "(removed; max {})", show_max(max))?,
+                    (Some((old_local, old_max)), Some((new_local, new_max))) => writeln!(
+                        w,
+                        "(max {} -> {}, local {} -> {})",
+                        show_max(old_max),
+                        show_max(new_max),
+                        show_local(old_local),
+                        show_local(new_local)
+                    )?,
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+
+        for (title, edges) in &[
+            ("new calls:", &self.added_edges),
+            ("removed calls:", &self.removed_edges),
+        ] {
+            if !edges.is_empty() {
+                writeln!(w, "{}", title)?;
+                for (caller, callee, tail) in edges.iter() {
+                    writeln!(
+                        w,
+                        "  {} -> {}{}",
+                        caller,
+                        callee,
+                        if *tail { " (tail call)" } else { "" }
+                    )?;
+                }
+            }
+        }
+
+        if !self.new_cycles.is_empty() {
+            writeln!(w, "new cycles:")?;
+            for cycle in &self.new_cycles {
+                writeln!(w, "  {}", cycle.join(", "))?;
+            }
+        }
+
+        if !self.new_unknown.is_empty() {
+            writeln!(w, "new functions with unknown stack usage:")?;
+            for function in &self.new_unknown {
+                writeln!(w, "  {}", function)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the call graph of the `new` analysis in dot format; functions whose maximum stack
+    /// usage increased are red and the ones whose maximum stack usage decreased are green
+    pub fn dot<W>(&self, new: &Analysis, mut w: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let changes = self
+            .changes
+            .iter()
+            .map(|change| (&*change.function, change))
+            .collect::<BTreeMap<_, _>>();
+
+        writeln!(w, "digraph {{")?;
+        writeln!(w, "    node [fontname={} shape=box]", FONT)?;
+
+        for (i, node) in new.saved.nodes.iter().enumerate() {
+            let key = key(node);
+
+            write!(w, "    {} [label=\"", i)?;
+
+            let mut escaper = Escaper::new(&mut w);
+            escaper.write_str(&key).ok();
+            escaper.error?;
+
+            if let Some(max) = node.max {
+                write!(w, "\\nmax {}", max)?;
+            }
+
+            write!(w, "\\nlocal = {}", node.local)?;
+
+            let delta = changes.get(&*key).map(|change| change.delta()).unwrap_or(0);
+            if delta != 0 {
+                write!(w, "\\n({})", show_delta(changes[&*key]))?;
+            }
+            write!(w, "\"")?;
+
+            if delta > 0 {
+                write!(w, " color=red fontcolor=red")?;
+            } else if delta < 0 {
+                write!(w, " color=green fontcolor=green")?;
+            }
+
+            if node.fictitious {
+                write!(w, " style=dashed")?;
+            }
+
+            writeln!(w, "]")?;
+        }
+
+        for edge in &new.saved.edges {
+            write!(w, "    {} -> {}", edge.source, edge.target)?;
+
+            if edge.tail {
+                write!(w, " [style=dashed arrowhead=empty]")?;
+            }
+
+            writeln!(w)?;
+        }
+
+        writeln!(w, "}}")
+    }
+}
+
+// `delta`, but changes from or to an unbounded stack usage are spelled out
+fn show_delta(change: &Change) -> String {
+    let unbounded = |usage: Option<(Local, Option<Max>)>| {
+        usage.and_then(|(_, max)| max) == Some(Max::Unbounded)
+    };
+
+    match (unbounded(change.old), unbounded(change.new)) {
+        (false, true) => "unbounded".to_string(),
+        (true, false) => "bounded".to_string(),
+        _ => format!("{:+}", change.delta()),
+    }
+}
+
+fn show_max(max: Option<Max>) -> String {
+    match max {
+        Some(Max::Exact(n)) => n.to_string(),
+        Some(Max::LowerBound(n)) => format!(">= {}", n),
+        Some(Max::Unbounded) => "unbounded".to_string(),
+        None => "?".to_string(),
+    }
+}
+
+fn show_local(local: Local) -> String {
+    match local {
+        Local::Exact(n) => n.to_string(),
+        Local::Unknown => "?".to_string(),
+        Local::Unbounded => "unbounded".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Analysis;
+
+    fn analysis(json: &str) -> Analysis {
+        Analysis {
+            saved: serde_json::from_str(json).unwrap(),
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let old = analysis(
+            r#"{
+  "version": 1,
+  "nodes": [
+    { "id": 0, "name": "_ZN3app4main17h0000000000000000E", "demangled": "app::main",
+      "local": { "kind": "exact", "value": 8 }, "max": { "kind": "exact", "value": 24 },
+      "worst_callee": 1, "fictitious": false },
+    { "id": 1, "name": "_ZN3app3foo17h0000000000000000E", "demangled": "app::foo",
+      "local": { "kind": "exact", "value": 16 }, "max": { "kind": "exact", "value": 16 },
+      "worst_callee": null, "fictitious": false },
+    { "id": 2, "name": "_ZN3app3bar17h0000000000000000E", "demangled": "app::bar",
+      "local": { "kind": "exact", "value": 4 }, "max": { "kind": "exact", "value": 4 },
+      "worst_callee": null, "fictitious": false }
+  ],
+  "edges": [{ "source": 0, "target": 1 }, { "source": 0, "target": 2 }],
+  "cycles": []
+}"#,
+        );
+        let new = analysis(
+            r#"{
+  "version": 1,
+  "nodes": [
+    { "id": 0, "name": "_ZN3app4main17h1111111111111111E", "demangled": "app::main",
+      "local": { "kind": "exact", "value": 8 }, "max": { "kind": "lower_bound", "value": 40 },
+      "worst_callee": 1, "fictitious": false },
+    { "id": 1, "name": "_ZN3app3foo17h1111111111111111E", "demangled": "app::foo",
+      "local": { "kind": "exact", "value": 32 }, "max": { "kind": "lower_bound", "value": 32 },
+      "worst_callee": null, "fictitious": false },
+    { "id": 2, "name": "_ZN3app3baz17h1111111111111111E", "demangled": "app::baz",
+      "local": { "kind": "unknown" }, "max": { "kind": "lower_bound", "value": 0 },
+      "worst_callee": null, "fictitious": false }
+  ],
+  "edges": [
+    { "source": 0, "target": 1, "tail": false },
+    { "source": 1, "target": 2, "tail": false },
+    { "source": 2, "target": 1, "tail": false }
+  ],
+  "cycles": [[1, 2]]
+}"#,
+        );
+
+        let diff = super::diff(&old, &new);
+
+        let changes = diff
+            .changes
+            .iter()
+            .map(|change| (&*change.function, change.delta()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                ("app::foo", 16),
+                ("app::main", 16),
+                ("app::baz", 0),
+                ("app::bar", -4)
+            ]
+        );
+
+        let edge = |caller: &str, callee: &str| (caller.to_string(), callee.to_string(), false);
+        assert_eq!(
+            diff.added_edges,
+            [edge("app::baz", "app::foo"), edge("app::foo", "app::baz")]
+        );
+        assert_eq!(diff.removed_edges, [edge("app::main", "app::bar")]);
+        assert_eq!(diff.new_cycles, [["app::baz", "app::foo"]]);
+        assert_eq!(diff.new_unknown, ["app::baz"]);
+    }
+
+    #[test]
+    fn out_of_range() {
+        let node = r#"{ "id": 0, "name": "main", "demangled": "main",
+            "local": { "kind": "exact", "value": 8 }, "max": { "kind": "exact", "value": 8 },
+            "worst_callee": null, "fictitious": false }"#;
+        let json = |edges: &str, cycles: &str| {
+            format!(
+                r#"{{ "version": 2, "nodes": [{}], "edges": {}, "cycles": {} }}"#,
+                node, edges, cycles
+            )
+        };
+
+        assert!(super::parse(&json("[]", "[]")).is_ok());
+        assert!(super::parse(&json(r#"[{ "source": 0, "target": 1 }]"#, "[]")).is_err());
+        assert!(super::parse(&json("[]", "[[0, 1]]")).is_err());
+    }
+
+    #[test]
+    fn unbounded() {
+        let json = |max: &str| {
+            analysis(&format!(
+                r#"{{
+  "version": 2,
+  "nodes": [
+    {{ "id": 0, "name": "main", "demangled": "main", "local": {{ "kind": "exact", "value": 8 }},
+      "max": {}, "worst_callee": null, "fictitious": false }}
+  ],
+  "edges": [],
+  "cycles": []
+}}"#,
+                max
+            ))
+        };
+        let bounded = json(r#"{ "kind": "exact", "value": 8 }"#);
+        let unbounded = json(r#"{ "kind": "unbounded" }"#);
+
+        let mut report = vec![];
+        super::diff(&bounded, &unbounded)
+            .report(&mut report)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "stack usage changes:\n  unbounded main (max 8 -> unbounded, local 8 -> 8)\n"
+        );
+
+        let mut report = vec![];
+        super::diff(&unbounded, &bounded)
+            .report(&mut report)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "stack usage changes:\n    bounded main (max unbounded -> 8, local 8 -> 8)\n"
+        );
+    }
+}
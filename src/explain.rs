@@ -0,0 +1,162 @@
+//! Breakdown of a function's stack frame (`--explain`)
+
+use std::{
+    cmp::Reverse,
+    io::{self, Write},
+};
+
+use crate::{Local, Node};
+
+// variables of at least this many bytes are flagged
+const LARGE: u64 = 64;
+
+pub fn write<W>(node: &Node, mut w: W) -> io::Result<()>
+where
+    W: Write,
+{
+    if let Local::Exact(local) = node.local {
+        writeln!(
+            w,
+            "stack frame of `{}` (local = {} bytes):",
+            node.demangled, local
+        )?;
+    } else {
+        writeln!(
+            w,
+            "stack frame of `{}` (local = {}):",
+            node.demangled, node.local
+        )?;
+    }
+
+    if node.variables.is_empty() {
+        return writeln!(
+            w,
+            "  no stack slots in the LLVM IR; the frame, if any, only holds spilled registers, \
+             saved registers and outgoing arguments"
+        );
+    }
+
+    let mut variables = node.variables.iter().collect::<Vec<_>>();
+    // NOTE stable sort; slots of unknown size go last
+    variables.sort_by_key(|variable| (variable.size.is_none(), Reverse(variable.size)));
+
+    let rows = variables
+        .iter()
+        .map(|variable| {
+            let note = match variable.size {
+                Some(size) if size >= LARGE && variable.is_array() => "large array",
+                Some(size) if size >= LARGE && variable.is_struct() => "by-value struct",
+                None if variable.is_dynamic() => "dynamically sized",
+                _ => "",
+            };
+
+            [
+                variable
+                    .size
+                    .map(|size| size.to_string())
+                    .unwrap_or_else(|| "?".to_string()),
+                variable.name.clone(),
+                variable.ty.clone().unwrap_or_else(|| "-".to_string()),
+                variable
+                    .location
+                    .as_ref()
+                    .map(|location| location.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                note.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let header = ["SIZE", "VARIABLE", "TYPE", "LOCATION", "NOTE"];
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in Some(header.map(str::to_string)).iter().chain(&rows) {
+        let line = format!(
+            "  {:>w0$}  {:<w1$}  {:<w2$}  {:<w3$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+        );
+        writeln!(w, "{}", line.trim_end())?;
+    }
+
+    let total = node
+        .variables
+        .iter()
+        .map(|variable| variable.size.unwrap_or(0))
+        .sum::<u64>();
+    if let Local::Exact(local) = node.local {
+        if local > total {
+            writeln!(
+                w,
+                "  {} bytes are not attributed to variables: spilled registers, saved registers, \
+                 padding and outgoing arguments",
+                local - total
+            )?;
+        } else if local < total {
+            writeln!(
+                w,
+                "  the variables add up to {} bytes; the code generator overlaps the slots of \
+                 variables that are not live at the same time",
+                total
+            )?;
+        }
+    } else if node.local == Local::Unbounded {
+        writeln!(
+            w,
+            "  the frame is adjusted by a run-time value, e.g. a dynamically sized allocation, so \
+             its size can't be bounded"
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Node, Shape, Variable};
+
+    #[test]
+    fn explain() {
+        let mut node = Node("_ZN3app5parseE", Some(1232), false);
+        let variable = |name: &str, size, shape| Variable {
+            name: name.to_string(),
+            ty: None,
+            size: Some(size),
+            shape,
+            dynamic: false,
+            location: None,
+        };
+        node.variables = vec![
+            variable("%_5", 8, Shape::Other),
+            variable("%buf", 1024, Shape::Array),
+            variable("%big", 168, Shape::Struct),
+        ];
+
+        let mut out = vec![];
+        super::write(&node, &mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+stack frame of `app::parse` (local = 1232 bytes):
+  SIZE  VARIABLE  TYPE  LOCATION  NOTE
+  1024  %buf      -     -         large array
+   168  %big      -     -         by-value struct
+     8  %_5       -     -
+  32 bytes are not attributed to variables: spilled registers, saved registers, padding and outgoing arguments
+"
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! Stack headroom of `cortex-m-rt` programs
+//!
+//! `cortex-m-rt` places the (full descending) stack at the end of the RAM region: it starts at
+//! `_stack_start` and can grow down until it reaches the statically allocated memory (`.data`,
+//! `.bss`, `.uninit`) or the heap.
+
+use std::io::{self, Write};
+
+use xmas_elf::{
+    sections::{SectionData, SHF_ALLOC, SHF_WRITE},
+    symbol_table::Entry,
+    ElfFile,
+};
+
+use crate::Max;
+
+/// Memory available to the stack
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StackRegion {
+    /// Lowest address the stack can grow into
+    pub bottom: u64,
+    /// Initial value of the stack pointer
+    pub top: u64,
+}
+
+impl StackRegion {
+    pub fn size(&self) -> u64 {
+        self.top - self.bottom
+    }
+}
+
+// symbols that mark the end of the statically allocated memory, in order of preference
+// - `_stack_end` was introduced in `cortex-m-rt` v0.7.4
+// - `__sheap` is the start of the heap and it's always placed after `.bss` and `.uninit`
+const BOTTOM_SYMBOLS: &[&str] = &["_stack_end", "__sheap", "__euninit", "_ebss"];
+
+/// Computes the stack region from the linker symbols in the ELF file
+///
+/// Returns `None` if the program was not linked using `cortex-m-rt`'s linker script
+pub fn stack_region(elf: &[u8]) -> Option<StackRegion> {
+    let elf = &ElfFile::new(elf).ok()?;
+    let sect = elf.find_section_by_name(".symtab")?;
+    let mut symbols = vec![];
+    match sect.get_data(elf).ok()? {
+        SectionData::SymbolTable32(entries) => {
+            for entry in entries {
+                if let Ok(name) = entry.get_name(elf) {
+                    symbols.push((name, entry.value()));
+                }
+            }
+        }
+
+        SectionData::SymbolTable64(entries) => {
+            for entry in entries {
+                if let Ok(name) = entry.get_name(elf) {
+                    symbols.push((name, entry.value()));
+                }
+            }
+        }
+
+        _ => return None,
+    }
+
+    let address = |needle: &str| {
+        symbols
+            .iter()
+            .find(|(name, _)| *name == needle)
+            .map(|(_, addr)| *addr)
+    };
+
+    let top = address("_stack_start")?;
+
+    let bottom = BOTTOM_SYMBOLS
+        .iter()
+        .filter_map(|name| address(name))
+        .find(|addr| *addr <= top)
+        .or_else(|| {
+            // fall back to the end of the last RAM section that's below the stack
+            elf.section_iter()
+                .filter(|sect| {
+                    let flags = sect.flags();
+                    flags & SHF_ALLOC != 0 && flags & SHF_WRITE != 0
+                })
+                .map(|sect| sect.address() + sect.size())
+                .filter(|end| *end <= top)
+                .max()
+        })?;
+
+    Some(StackRegion { bottom, top })
+}
+
+/// Writes how much of the stack region the program uses
+///
+/// `what` describes the stack user, e.g. "`Reset`"
+pub fn report<W>(what: &str, max: Max, region: StackRegion, mut w: W) -> io::Result<()>
+where
+    W: Write,
+{
+    let available = region.size();
+    let used = max.bytes();
+
+    writeln!(
+        w,
+        "stack region: {:#010x} - {:#010x} ({} bytes)",
+        region.bottom, region.top, available
+    )?;
+
+    match max {
+        Max::Exact(_) if used <= available => writeln!(
+            w,
+            "{} uses up to {} bytes of stack; headroom: {} bytes",
+            what,
+            used,
+            available - used
+        ),
+
+        Max::LowerBound(_) if used <= available => writeln!(
+            w,
+            "{} uses at least {} bytes of stack; headroom: at most {} bytes",
+            what,
+            used,
+            available - used
+        ),
+
+        Max::Unbounded => writeln!(
+            w,
+            "error: {} may overflow the stack: its stack usage is unbounded",
+            what
+        ),
+
+        _ => writeln!(
+            w,
+            "error: {} may overflow the stack: it uses {} {} bytes of stack but only {} bytes \
+             are available",
+            what,
+            if let Max::Exact(_) = max {
+                "up to"
+            } else {
+                "at least"
+            },
+            used,
+            available
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StackRegion;
+    use crate::Max;
+
+    // a 32-bit ELF file with only the given symbols and `SHT_NOBITS` RAM sections
+    fn elf(symbols: &[(&str, u32)], sections: &[(&str, u32, u32)]) -> Vec<u8> {
+        fn u16s(buf: &mut Vec<u8>, values: &[u16]) {
+            for value in values {
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        fn u32s(buf: &mut Vec<u8>, values: &[u32]) {
+            for value in values {
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        fn name(strtab: &mut Vec<u8>, name: &str) -> u32 {
+            let offset = strtab.len() as u32;
+            strtab.extend_from_slice(name.as_bytes());
+            strtab.push(0);
+            offset
+        }
+
+        let mut strtab = vec![0];
+        let mut symtab = vec![0; 16];
+        for (symbol, value) in symbols {
+            u32s(&mut symtab, &[name(&mut strtab, symbol), *value, 0]);
+            symtab.extend_from_slice(&[0, 0]);
+            u16s(&mut symtab, &[0xfff1]); // SHN_ABS
+        }
+        while strtab.len() % 4 != 0 {
+            strtab.push(0);
+        }
+
+        let mut shstrtab = vec![0];
+        let shstrtab_name = name(&mut shstrtab, ".shstrtab");
+        let strtab_name = name(&mut shstrtab, ".strtab");
+        let symtab_name = name(&mut shstrtab, ".symtab");
+        let section_names = sections
+            .iter()
+            .map(|(section, _, _)| name(&mut shstrtab, section))
+            .collect::<Vec<_>>();
+        while shstrtab.len() % 4 != 0 {
+            shstrtab.push(0);
+        }
+
+        let shstrtab_offset = 52;
+        let strtab_offset = shstrtab_offset + shstrtab.len() as u32;
+        let symtab_offset = strtab_offset + strtab.len() as u32;
+        let shoff = symtab_offset + symtab.len() as u32;
+        let shnum = 4 + sections.len() as u16;
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        u16s(&mut elf, &[2, 40]); // ET_EXEC, EM_ARM
+        u32s(&mut elf, &[1, 0, 0, shoff, 0]);
+        u16s(&mut elf, &[52, 32, 0, 40, shnum, 1]);
+        elf.extend_from_slice(&shstrtab);
+        elf.extend_from_slice(&strtab);
+        elf.extend_from_slice(&symtab);
+
+        // name, type, flags, address, offset, size, link, info, alignment, entry size
+        u32s(&mut elf, &[0; 10]);
+        u32s(
+            &mut elf,
+            &[
+                shstrtab_name,
+                3,
+                0,
+                0,
+                shstrtab_offset,
+                shstrtab.len() as u32,
+                0,
+                0,
+                1,
+                0,
+            ],
+        );
+        u32s(
+            &mut elf,
+            &[
+                strtab_name,
+                3,
+                0,
+                0,
+                strtab_offset,
+                strtab.len() as u32,
+                0,
+                0,
+                1,
+                0,
+            ],
+        );
+        u32s(
+            &mut elf,
+            &[
+                symtab_name,
+                2,
+                0,
+                0,
+                symtab_offset,
+                symtab.len() as u32,
+                2,
+                1,
+                4,
+                16,
+            ],
+        );
+        for (name, (_, address, size)) in section_names.iter().zip(sections) {
+            // SHT_NOBITS, SHF_WRITE | SHF_ALLOC
+            u32s(&mut elf, &[*name, 8, 3, *address, shoff, *size, 0, 0, 4, 0]);
+        }
+
+        elf
+    }
+
+    #[test]
+    fn stack_region() {
+        let bss = [(".bss", 0x2000_0000, 0x40)];
+
+        // `__sheap` marks the end of the static memory
+        let file = elf(
+            &[("_stack_start", 0x2001_0000), ("__sheap", 0x2000_0100)],
+            &bss,
+        );
+        let region = super::stack_region(&file).unwrap();
+        assert_eq!(
+            region,
+            StackRegion {
+                bottom: 0x2000_0100,
+                top: 0x2001_0000
+            }
+        );
+        assert_eq!(region.size(), 0xff00);
+
+        // `_stack_end` takes precedence over `__sheap`
+        let file = elf(
+            &[
+                ("_stack_start", 0x2001_0000),
+                ("__sheap", 0x2000_0100),
+                ("_stack_end", 0x2000_0200),
+            ],
+            &bss,
+        );
+        assert_eq!(super::stack_region(&file).unwrap().bottom, 0x2000_0200);
+
+        // without the symbols that mark the end of the static memory the RAM sections are used
+        let file = elf(&[("_stack_start", 0x2001_0000)], &bss);
+        assert_eq!(super::stack_region(&file).unwrap().bottom, 0x2000_0040);
+
+        // not a `cortex-m-rt` program
+        let file = elf(&[("__sheap", 0x2000_0100)], &bss);
+        assert_eq!(super::stack_region(&file), None);
+        assert_eq!(super::stack_region(b"not an ELF file"), None);
+    }
+
+    #[test]
+    fn report() {
+        let region = StackRegion {
+            bottom: 0x2000_0000,
+            top: 0x2000_0400,
+        };
+        let report = |max| {
+            let mut out = vec![];
+            super::report("`Reset`", max, region, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            report(Max::Exact(1000)),
+            "stack region: 0x20000000 - 0x20000400 (1024 bytes)\n\
+             `Reset` uses up to 1000 bytes of stack; headroom: 24 bytes\n"
+        );
+        assert!(report(Max::LowerBound(1000))
+            .ends_with("`Reset` uses at least 1000 bytes of stack; headroom: at most 24 bytes\n"));
+        assert!(report(Max::Exact(2048)).ends_with(
+            "error: `Reset` may overflow the stack: it uses up to 2048 bytes of stack but only \
+             1024 bytes are available\n"
+        ));
+        assert!(report(Max::Unbounded)
+            .ends_with("error: `Reset` may overflow the stack: its stack usage is unbounded\n"));
+    }
+}
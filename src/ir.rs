@@ -1,4 +1,5 @@
 use core::fmt;
+use std::collections::HashSet;
 
 use nom::{
     branch::alt,
@@ -17,8 +18,8 @@ mod ty;
 
 use crate::ir::ty::type_;
 pub use crate::ir::{
-    define::Stmt,
-    item::{Declare, Item},
+    define::{Alloca, Stmt},
+    item::{DebugInfo, Item},
     ty::Type,
 };
 
@@ -51,7 +52,7 @@ impl<'a> fmt::Display for FnSig<'a> {
     }
 }
 
-pub fn parse(ll: &str) -> Result<Vec<Item>, failure::Error> {
+pub fn parse(ll: &str) -> Result<Vec<Item<'_>>, failure::Error> {
     items(ll).map(|t| t.1).map_err(|e| {
         let e = e.map(|(rest, kind)| {
             let offset = ll.len()-rest.len();
@@ -72,7 +73,50 @@ pub fn parse(ll: &str) -> Result<Vec<Item>, failure::Error> {
     })
 }
 
-fn items(i: &str) -> IResult<&str, Vec<Item>> {
+/// Returns the functions (and other symbols) whose address is taken in the LLVM IR
+///
+/// That's every reference to a symbol that's not the callee of a direct call: `store`s,
+/// initializers of globals and vtables, `bitcast` / `getelementptr` constants, call arguments,
+/// etc. Only these functions can be called through a function pointer.
+pub fn address_taken(ll: &str) -> HashSet<&str> {
+    let mut taken = HashSet::new();
+    for line in ll.lines() {
+        // the symbol that this line defines or calls
+        let skip = if line.starts_with("define") || line.starts_with('@') {
+            // `define void @foo(..)`, `@foo = alias ..`, `@bar = constant ..`
+            refs(line).next()
+        } else if line.starts_with(' ') || line.starts_with('\t') {
+            match define::stmt(line) {
+                Ok((_, Stmt::DirectCall(callee))) | Ok((_, Stmt::BitcastCall(Some(callee)))) => {
+                    Some(callee)
+                }
+                _ => None,
+            }
+        } else {
+            // comments, declarations, metadata, etc.
+            continue;
+        };
+
+        let mut skipped = false;
+        for name in refs(line) {
+            if !skipped && Some(name) == skip {
+                skipped = true;
+            } else {
+                taken.insert(name);
+            }
+        }
+    }
+
+    taken
+}
+
+// all the symbols (`@foo`) referenced in `line`
+fn refs(line: &str) -> impl Iterator<Item = &str> {
+    line.match_indices('@')
+        .filter_map(move |(pos, _)| global(&line[pos..]).ok().and_then(|(_, g)| g.0))
+}
+
+fn items(i: &str) -> IResult<&str, Vec<Item<'_>>> {
     let (i, items) = separated_list(many1(line_ending), crate::ir::item::item)(i)?;
     let i = many0(line_ending)(i)?.0;
     if i.is_empty() {
@@ -95,7 +139,7 @@ fn comment(i: &str) -> IResult<&str, Comment> {
 struct Ident<'a>(&'a str);
 
 // LLVM LangRef: `[-a-zA-Z$._][-a-zA-Z$._0-9]*`
-fn ident(i: &str) -> IResult<&str, Ident> {
+fn ident(i: &str) -> IResult<&str, Ident<'_>> {
     map_res(
         take_while1(|c: char| c.is_alphanumeric() || "-$._".contains(c)),
         |s: &str| {
@@ -111,7 +155,7 @@ fn ident(i: &str) -> IResult<&str, Ident> {
 #[derive(Clone, Copy, Debug, PartialEq)]
 struct Global<'a>(Option<&'a str>);
 
-fn global(i: &str) -> IResult<&str, Global> {
+fn global(i: &str) -> IResult<&str, Global<'_>> {
     let i = char('@')(i)?.0;
     let (i, s) = alt((
         map(string, |s| Some(s.0)),
@@ -157,7 +201,7 @@ fn attribute(i: &str) -> IResult<&str, Attribute> {
         }
 
         // have this branch always error because this is not an attribute but part of a type
-        "double" | "float" | "void" => {
+        "double" | "float" | "void" | "ptr" => {
             return Err(nom::Err::Error((i, ErrorKind::Switch)));
         }
 
@@ -171,17 +215,63 @@ fn attribute(i: &str) -> IResult<&str, Attribute> {
             return Err(nom::Err::Error((i, ErrorKind::Switch)));
         }
 
-        _ => i,
+        // e.g. `captures(none)`, `range(i32 0, 10)`, `initializes((0, 4))`
+        _ => opt(parenthesized)(i)?.0,
     };
 
     Ok((i, Attribute))
 }
 
+// `(..)`, where the contents may contain nested parentheses
+fn parenthesized(i: &str) -> IResult<&str, &str> {
+    let mut depth = 0usize;
+    for (pos, c) in i.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 1 => depth -= 1,
+            ')' if depth == 1 => return Ok((&i[pos + 1..], &i[..pos + 1])),
+            _ if depth == 0 || c == '\n' => break,
+            _ => {}
+        }
+    }
+
+    Err(nom::Err::Error((i, ErrorKind::Char)))
+}
+
+// any other operand: `@global`, `null`, `-1`, `getelementptr inbounds nuw (..)`, etc.
+// NOTE we only need to find where the operand ends: at the first `,` or closing delimiter that's
+// not nested
+fn operand(i: &str) -> IResult<&str, &str> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut end = i.len();
+    for (pos, c) in i.char_indices() {
+        match c {
+            // e.g. `c"(,)"`
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '(' | '[' | '{' | '<' => depth += 1,
+            ')' | ']' | '}' | '>' if depth != 0 => depth -= 1,
+            ',' | ')' | ']' | '}' | '>' | '\r' | '\n' if depth == 0 => {
+                end = pos;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    if end == 0 || depth != 0 || in_string {
+        Err(nom::Err::Error((i, ErrorKind::TakeUntil)))
+    } else {
+        Ok((&i[end..], &i[..end]))
+    }
+}
+
 // NOTE constant operation
 #[derive(Clone, Copy, Debug, PartialEq)]
 struct Bitcast<'a>(Option<&'a str>);
 
-fn bitcast(i: &str) -> IResult<&str, Bitcast> {
+fn bitcast(i: &str) -> IResult<&str, Bitcast<'_>> {
     let i = tag("bitcast")(i)?.0;
     let i = space1(i)?.0;
     delimited(
@@ -209,7 +299,7 @@ fn bitcast(i: &str) -> IResult<&str, Bitcast> {
 #[derive(Clone, Copy, Debug, PartialEq)]
 struct GetElementPtr<'a>(Option<&'a str>);
 
-fn getelementptr(i: &str) -> IResult<&str, GetElementPtr> {
+fn getelementptr(i: &str) -> IResult<&str, GetElementPtr<'_>> {
     let i = tag("getelementptr")(i)?.0;
     let i = space1(i)?.0;
     let i = opt(|i| {
@@ -248,7 +338,7 @@ fn name(i: &str) -> IResult<&str, &str> {
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Function<'a>(pub &'a str);
 
-fn function(i: &str) -> IResult<&str, Function> {
+fn function(i: &str) -> IResult<&str, Function<'_>> {
     let i = char('@')(i)?.0;
     let (i, name) = name(i)?;
     Ok((i, Function(name)))
@@ -257,7 +347,7 @@ fn function(i: &str) -> IResult<&str, Function> {
 #[derive(Clone, Copy, Debug, PartialEq)]
 struct Alias<'a>(&'a str);
 
-fn alias(i: &str) -> IResult<&str, Alias> {
+fn alias(i: &str) -> IResult<&str, Alias<'_>> {
     let i = char('%')(i)?.0;
     let (i, name) = name(i)?;
     Ok((i, Alias(name)))
@@ -268,7 +358,7 @@ struct String<'a>(&'a str);
 
 // NOTE this will accept things that are not valid in LLVM-IR but we are only dealing with
 // well-formed LLVM-IR so this is good enough
-fn string(i: &str) -> IResult<&str, String> {
+fn string(i: &str) -> IResult<&str, String<'_>> {
     let i = char('"')(i)?.0;
     let (i, x) = take_until("\"")(i)?;
     let i = char('"')(i)?.0;
@@ -277,7 +367,29 @@ fn string(i: &str) -> IResult<&str, String> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Alias, Comment, FnSig, GetElementPtr, Ident, Local, String, Type};
+    use super::{Alias, Attribute, Comment, FnSig, GetElementPtr, Ident, Local, String, Type};
+
+    #[test]
+    fn address_taken() {
+        let taken = super::address_taken(
+            r#"@vtable.0 = private constant <{ ptr, [16 x i8], ptr }> <{ ptr @drop, [16 x i8] c"\00", ptr @method }>, align 8
+
+; Function Attrs: nounwind
+define void @main() unnamed_addr #0 {
+start:
+  store ptr @stored, ptr %0, align 8
+  tail call void @direct(ptr nonnull @argument)
+  tail call fastcc i32 bitcast (ptr @bitcasted to ptr)() #6
+  ret void
+}
+
+declare void @declared()"#,
+        );
+
+        let mut taken = taken.into_iter().collect::<Vec<_>>();
+        taken.sort();
+        assert_eq!(taken, ["argument", "drop", "method", "stored"]);
+    }
 
     #[test]
     fn alias() {
@@ -297,6 +409,21 @@ mod tests {
     #[test]
     fn attribute() {
         assert!(super::attribute("void").is_err());
+        assert!(super::attribute("ptr").is_err());
+
+        assert_eq!(super::attribute("captures(none)"), Ok(("", Attribute)));
+        assert_eq!(
+            super::attribute("captures(address, read_provenance) %1"),
+            Ok((" %1", Attribute))
+        );
+        assert_eq!(
+            super::attribute("range(i32 0, 1114112)"),
+            Ok(("", Attribute))
+        );
+        assert_eq!(
+            super::attribute("initializes((0, 4), (12, 14)) %0"),
+            Ok((" %0", Attribute))
+        );
     }
 
     #[test]
@@ -1,8 +1,11 @@
+use std::{collections::HashMap, str::FromStr};
+
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, tag},
+    bytes::complete::{is_not, tag, take_while},
     character::complete::{char, digit1, line_ending, not_line_ending, space1},
-    combinator::{map, map_res, opt},
+    combinator::{map, map_res, not, opt, recognize},
+    error::ErrorKind,
     multi::{many0, many1, separated_list, separated_nonempty_list},
     sequence::delimited,
     IResult,
@@ -14,7 +17,23 @@ use crate::ir::{FnSig, Type};
 pub struct Define<'a> {
     pub name: &'a str,
     pub sig: FnSig<'a>,
-    pub stmts: Vec<Stmt<'a>>,
+    // each statement along with its debug location (`!dbg !N`), if any
+    pub stmts: Vec<(Stmt<'a>, Option<u32>)>,
+    // the `DISubprogram` that describes this function (`!dbg !N`), if any
+    pub dbg: Option<u32>,
+    pub allocas: Vec<Alloca<'a>>,
+}
+
+// `%buf = alloca [1024 x i8], align 1`; a stack slot
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alloca<'a> {
+    // `%buf`
+    pub name: &'a str,
+    pub ty: Type<'a>,
+    // number of elements of type `ty`; `None` if it's only known at run time
+    pub count: Option<u64>,
+    // the `DILocalVariable`s stored in this slot, according to `dbg.declare`
+    pub variables: Vec<u32>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -28,6 +47,9 @@ pub enum Stmt<'a> {
 
     IndirectCall(FnSig<'a>),
 
+    // a call through slot `Slot` of a trait object's vtable; the receiver has been erased
+    DynamicCall(FnSig<'a>, Slot<'a>),
+
     Comment,
 
     // `start:`
@@ -36,10 +58,17 @@ pub enum Stmt<'a> {
     Other,
 }
 
+// `index` elements of type `ty` away from the start of a vtable
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Slot<'a> {
+    pub ty: Type<'a>,
+    pub index: u64,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 struct Parameter<'a>(Type<'a>);
 
-fn parameter(i: &str) -> IResult<&str, Parameter> {
+fn parameter(i: &str) -> IResult<&str, Parameter<'_>> {
     let (i, ty) = super::type_(i)?;
     let i = many0(|i| {
         let i = space1(i)?.0;
@@ -59,7 +88,7 @@ fn parameter(i: &str) -> IResult<&str, Parameter> {
     Ok((i, Parameter(ty)))
 }
 
-pub fn parse(i: &str) -> IResult<&str, Define> {
+pub fn parse(i: &str) -> IResult<&str, Define<'_>> {
     let i = tag("define")(i)?.0;
     let i = space1(i)?.0;
     let i = many0(|i| {
@@ -82,12 +111,83 @@ pub fn parse(i: &str) -> IResult<&str, Define> {
     )(i)?;
     let i = char(')')(i)?.0;
 
-    // TODO we likely want to parse the metadata (`!dbg !0`) that comes after the parameter list
     // NOTE shortcut
-    let i = not_line_ending(i)?.0;
+    let (i, rest) = not_line_ending(i)?;
+    let subprogram = dbg(rest);
     let i = line_ending(i)?.0;
-    let (i, stmts) = separated_nonempty_list(many1(line_ending), super::define::stmt)(i)?;
+    let (i, mut stmts) = separated_nonempty_list(many1(line_ending), |i| {
+        let (rest, stmt) = stmt(i)?;
+        Ok((rest, (&i[..i.len() - rest.len()], stmt)))
+    })(i)?;
     let i = opt(line_ending)(i)?.0;
+
+    // method calls on trait objects load the method from a vtable and then call it. Here we
+    // figure out which slot of the vtable was used. With opaque pointers the receiver of the method
+    // is a plain `ptr` so this is also the only way to tell dynamic dispatch apart from a call
+    // through a function pointer; in that case the receiver gets the "erased" type that typed
+    // pointers use (`{}*`)
+    let geps = stmts
+        .iter()
+        .filter_map(|(text, _)| getelementptr(text).ok().map(|(_, gep)| gep))
+        .map(|(dest, ty, base, index)| (dest, (ty, base, index)))
+        .collect::<HashMap<_, _>>();
+    let vtable_loads = stmts
+        .iter()
+        .filter_map(|(text, _)| vtable_load(text).ok().map(|(_, load)| load))
+        .map(|(dest, src)| {
+            // a load straight from the vtable pointer reads the first slot
+            let slot = geps
+                .get(src)
+                .map(|(ty, _, index)| Slot {
+                    ty: ty.clone(),
+                    index: *index,
+                })
+                .unwrap_or(Slot {
+                    ty: Type::Integer(8),
+                    index: 0,
+                });
+            (dest, slot)
+        })
+        .collect::<HashMap<_, _>>();
+    if !vtable_loads.is_empty() {
+        for (text, stmt) in &mut stmts {
+            let slot = if let Stmt::IndirectCall(sig) = stmt {
+                match sig.inputs.first() {
+                    Some(Type::OpaquePointer) => {}
+                    Some(ty) if ty.has_been_erased() => {}
+                    _ => continue,
+                }
+
+                indirect_callee(text).and_then(|callee| vtable_loads.get(callee))
+            } else {
+                None
+            };
+
+            if let Some(slot) = slot {
+                if let Stmt::IndirectCall(sig) = stmt {
+                    let mut sig = sig.clone();
+                    sig.inputs[0] = Type::erased();
+                    *stmt = Stmt::DynamicCall(sig, slot.clone());
+                }
+            }
+        }
+    }
+    let mut allocas = stmts
+        .iter()
+        .filter_map(|(text, _)| alloca(text).ok().map(|(_, alloca)| alloca))
+        .collect::<Vec<_>>();
+    for (text, _) in &stmts {
+        if let Some((name, variable)) = dbg_declare(text) {
+            if let Some(alloca) = allocas.iter_mut().find(|alloca| alloca.name == name) {
+                alloca.variables.push(variable);
+            }
+        }
+    }
+
+    let stmts = stmts
+        .into_iter()
+        .map(|(text, stmt)| (stmt, dbg(text)))
+        .collect();
     let i = tag("}")(i)?.0;
     Ok((
         i,
@@ -98,11 +198,23 @@ pub fn parse(i: &str) -> IResult<&str, Define> {
                 inputs,
                 output: output.map(Box::new),
             },
+            dbg: subprogram,
+            allocas,
         },
     ))
 }
 
-fn label(i: &str) -> IResult<&str, Stmt> {
+// the debug location attached to a statement, or the subprogram attached to a function: the `!N` in
+// `!dbg !N`
+fn dbg(text: &str) -> Option<u32> {
+    let (_, rest) = text.rsplit_once("!dbg !")?;
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+fn label(i: &str) -> IResult<&str, Stmt<'_>> {
     let i = alt((
         map(super::ident, drop),
         map(super::string, drop),
@@ -118,12 +230,12 @@ fn label(i: &str) -> IResult<&str, Stmt> {
     Ok((i, Stmt::Label))
 }
 
-fn comment(i: &str) -> IResult<&str, Stmt> {
+fn comment(i: &str) -> IResult<&str, Stmt<'_>> {
     let i = super::comment(i)?.0;
     Ok((i, Stmt::Comment))
 }
 
-fn asm(i: &str) -> IResult<&str, Stmt> {
+fn asm(i: &str) -> IResult<&str, Stmt<'_>> {
     let i = opt(|i| {
         let i = tag("tail")(i)?.0;
         space1(i)
@@ -149,7 +261,7 @@ fn asm(i: &str) -> IResult<&str, Stmt> {
 #[derive(Clone, Debug, PartialEq)]
 struct Argument<'a>(Type<'a>);
 
-fn argument(i: &str) -> IResult<&str, Argument> {
+fn argument(i: &str) -> IResult<&str, Argument<'_>> {
     let (i, ty) = super::type_(i)?;
     let i = space1(i)?.0;
     let i = many0(|i| {
@@ -162,12 +274,13 @@ fn argument(i: &str) -> IResult<&str, Argument> {
         map(super::getelementptr, drop),
         map(super::local, drop),
         map(digit1, drop),
+        map(super::operand, drop),
     ))(i)?
     .0;
     Ok((i, Argument(ty)))
 }
 
-fn bitcast_call(i: &str) -> IResult<&str, Stmt> {
+fn bitcast_call(i: &str) -> IResult<&str, Stmt<'_>> {
     let i = opt(|i| {
         let i = tag("tail")(i)?.0;
         space1(i)
@@ -191,7 +304,7 @@ fn bitcast_call(i: &str) -> IResult<&str, Stmt> {
     Ok((i, Stmt::BitcastCall(name.0)))
 }
 
-fn direct_call(i: &str) -> IResult<&str, Stmt> {
+fn direct_call(i: &str) -> IResult<&str, Stmt<'_>> {
     let i = opt(|i| {
         let i = tag("tail")(i)?.0;
         space1(i)
@@ -208,13 +321,17 @@ fn direct_call(i: &str) -> IResult<&str, Stmt> {
     let i = space1(i)?.0;
     let (i, name) = super::function(i)?;
     let i = char('(')(i)?.0;
-    // TODO we likely want to parse the metadata (`!dbg !0`) that comes after the argument list
-    // NOTE shortcut
+    // NOTE shortcut; `parse` extracts the debug location (`!dbg !N`)
     let i = not_line_ending(i)?.0;
     Ok((i, Stmt::DirectCall(name.0)))
 }
 
-fn indirect_call(i: &str) -> IResult<&str, Stmt> {
+fn indirect_call(i: &str) -> IResult<&str, Stmt<'_>> {
+    map(callee_and_sig, |(_, sig)| Stmt::IndirectCall(sig))(i)
+}
+
+// returns the local that holds the function pointer, e.g. `%7` in `call i1 %7(ptr %0)`
+fn callee_and_sig(i: &str) -> IResult<&str, (&str, FnSig<'_>)> {
     let i = opt(|i| {
         let i = tag("tail")(i)?.0;
         space1(i)
@@ -227,10 +344,7 @@ fn indirect_call(i: &str) -> IResult<&str, Stmt> {
     .0;
     let (i, output) = alt((map(super::type_, Some), map(tag("void"), |_| None)))(i)?;
     let i = space1(i)?.0;
-    let i = char('%')(i)?.0;
-    let i = opt(char('_'))(i)?.0;
-    let i = digit1(i)?.0;
-    let i = many0(|i| tag(".i")(i))(i)?.0;
+    let (i, callee) = local_name(i)?;
     let (i, inputs) = delimited(
         char('('),
         separated_list(
@@ -242,19 +356,163 @@ fn indirect_call(i: &str) -> IResult<&str, Stmt> {
         ),
         char(')'),
     )(i)?;
-    // TODO we likely want to parse the metadata (`!dbg !0`) that comes after the argument list
+    // NOTE shortcut; `parse` extracts the debug location (`!dbg !N`)
+    let i = not_line_ending(i)?.0;
+    Ok((
+        i,
+        (
+            callee,
+            FnSig {
+                inputs,
+                output: output.map(Box::new),
+            },
+        ),
+    ))
+}
+
+// `%7`, `%_5`, `%12.i`, etc.
+fn local_name(i: &str) -> IResult<&str, &str> {
+    recognize(|i| {
+        let i = super::local(i)?.0;
+        take_while(|c: char| c.is_alphanumeric() || "-$._".contains(c))(i)
+    })(i)
+}
+
+// `%7 = load ptr, ptr %6, align 8, !invariant.load !43, !nonnull !43`
+//
+// this is how rustc loads a method from a vtable; returns `(%7, %6)`
+fn vtable_load(i: &str) -> IResult<&str, (&str, &str)> {
+    let i = space1(i)?.0;
+    let (i, dest) = local_name(i)?;
+    let i = space1(i)?.0;
+    let i = char('=')(i)?.0;
+    let i = space1(i)?.0;
+    let i = tag("load")(i)?.0;
+    let i = space1(i)?.0;
+    let i = super::type_(i)?.0;
+    let i = char(',')(i)?.0;
+    let i = space1(i)?.0;
+    let i = super::type_(i)?.0;
+    let i = space1(i)?.0;
+    let (i, src) = local_name(i)?;
+    let (i, rest) = not_line_ending(i)?;
+    if rest.contains("!invariant.load") {
+        Ok((i, (dest, src)))
+    } else {
+        Err(nom::Err::Error((i, ErrorKind::Tag)))
+    }
+}
+
+// `%6 = getelementptr inbounds nuw i8, ptr %5, i64 24, !dbg !1638`
+// `%6 = getelementptr inbounds i1 ({}*)*, i1 ({}*)** %5, i32 3`
+//
+// returns `(%6, i8, %5, 24)`; only GEPs with a single, constant index are considered
+fn getelementptr(i: &str) -> IResult<&str, (&str, Type<'_>, &str, u64)> {
+    let i = space1(i)?.0;
+    let (i, dest) = local_name(i)?;
+    let i = space1(i)?.0;
+    let i = char('=')(i)?.0;
+    let i = space1(i)?.0;
+    let i = tag("getelementptr")(i)?.0;
+    let i = space1(i)?.0;
+    let i = many0(|i| {
+        let i = alt((tag("inbounds"), tag("nuw"), tag("nusw")))(i)?.0;
+        space1(i)
+    })(i)?
+    .0;
+    let (i, ty) = super::type_(i)?;
+    let i = char(',')(i)?.0;
+    let i = space1(i)?.0;
+    let i = super::type_(i)?.0;
+    let i = space1(i)?.0;
+    let (i, base) = local_name(i)?;
+    let i = char(',')(i)?.0;
+    let i = space1(i)?.0;
+    let i = super::type_(i)?.0;
+    let i = space1(i)?.0;
+    let (i, index) = map_res(digit1, u64::from_str)(i)?;
+    // only metadata may follow the index
+    let i = not(|i| {
+        let i = char(',')(i)?.0;
+        let i = space1(i)?.0;
+        not(char('!'))(i)
+    })(i)?
+    .0;
+    let i = not_line_ending(i)?.0;
+    Ok((i, (dest, ty, base, index)))
+}
+
+// `%buf = alloca [1024 x i8], align 1`
+// `%2 = alloca i8, i64 16, align 16`
+fn alloca(i: &str) -> IResult<&str, Alloca<'_>> {
+    let i = space1(i)?.0;
+    let (i, name) = local_name(i)?;
+    let i = space1(i)?.0;
+    let i = char('=')(i)?.0;
+    let i = space1(i)?.0;
+    let i = tag("alloca")(i)?.0;
+    let i = space1(i)?.0;
+    let i = opt(|i| {
+        let i = tag("inalloca")(i)?.0;
+        space1(i)
+    })(i)?
+    .0;
+    let (i, ty) = super::type_(i)?;
+    let (i, count) = opt(|i| {
+        let i = char(',')(i)?.0;
+        let i = space1(i)?.0;
+        let i = super::type_(i)?.0;
+        let i = space1(i)?.0;
+        alt((
+            map(map_res(digit1, u64::from_str), Some),
+            map(local_name, |_| None),
+        ))(i)
+    })(i)?;
     // NOTE shortcut
     let i = not_line_ending(i)?.0;
     Ok((
         i,
-        Stmt::IndirectCall(FnSig {
-            inputs,
-            output: output.map(Box::new),
-        }),
+        Alloca {
+            name,
+            ty,
+            count: count.unwrap_or(Some(1)),
+            variables: vec![],
+        },
     ))
 }
 
-fn other(i: &str) -> IResult<&str, Stmt> {
+// `#dbg_declare(ptr %buf, !138, !DIExpression(), !153)`
+// `call void @llvm.dbg.declare(metadata ptr %buf, metadata !138, metadata !DIExpression()), !dbg !153`
+//
+// returns `(%buf, 138)`
+fn dbg_declare(text: &str) -> Option<(&str, u32)> {
+    let (_, args) = text
+        .split_once("#dbg_declare(")
+        .or_else(|| text.split_once("@llvm.dbg.declare("))?;
+    let args = &args[args.find('%')?..];
+    let (rest, name) = local_name(args).ok()?;
+    let (_, rest) = rest.split_once('!')?;
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    Some((name, rest[..end].parse().ok()?))
+}
+
+// returns the callee of an indirect call statement
+fn indirect_callee(i: &str) -> Option<&str> {
+    let i = space1::<_, (&str, ErrorKind)>(i).ok()?.0;
+    let i = opt(|i| {
+        let i = super::local(i)?.0;
+        let i = space1(i)?.0;
+        let i = char('=')(i)?.0;
+        space1(i)
+    })(i)
+    .ok()?
+    .0;
+    callee_and_sig(i).ok().map(|(_, (callee, _))| callee)
+}
+
+fn other(i: &str) -> IResult<&str, Stmt<'_>> {
     let i = separated_nonempty_list(
         space1,
         map_res(is_not(" \t\r\n"), |i| {
@@ -270,7 +528,7 @@ fn other(i: &str) -> IResult<&str, Stmt> {
 }
 
 // NOTE we discard the LHS of assignments
-fn assign(i: &str) -> IResult<&str, Stmt> {
+fn assign(i: &str) -> IResult<&str, Stmt<'_>> {
     let i = super::local(i)?.0;
     let i = space1(i)?.0;
     let i = char('=')(i)?.0;
@@ -278,7 +536,7 @@ fn assign(i: &str) -> IResult<&str, Stmt> {
     alt((asm, bitcast_call, direct_call, indirect_call, other))(i)
 }
 
-fn stmt(i: &str) -> IResult<&str, Stmt> {
+pub fn stmt(i: &str) -> IResult<&str, Stmt<'_>> {
     alt((label, comment, |i| {
         let i = space1(i)?.0;
         alt((assign, asm, bitcast_call, direct_call, indirect_call, other))(i)
@@ -287,7 +545,7 @@ fn stmt(i: &str) -> IResult<&str, Stmt> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Argument, Define, Parameter};
+    use super::{Alloca, Argument, Define, Parameter, Slot};
     use crate::ir::{FnSig, Stmt, Type};
 
     #[test]
@@ -340,6 +598,23 @@ mod tests {
                 ))))
             ))
         );
+
+        assert_eq!(
+            super::argument(r#"ptr noundef nonnull @anon.f060a8fe91113516c6f72b45ea256765.59"#),
+            Ok(("", Argument(Type::OpaquePointer)))
+        );
+
+        assert_eq!(
+            super::argument(
+                r#"ptr getelementptr inbounds nuw (i8, ptr @alloc_0ab120d992479c4cb1e1767c901c8927, i64 16), i64 -1)"#
+            ),
+            Ok((", i64 -1)", Argument(Type::OpaquePointer)))
+        );
+
+        assert_eq!(
+            super::argument(r#"ptr null)"#),
+            Ok((")", Argument(Type::OpaquePointer)))
+        );
     }
 
     #[test]
@@ -514,6 +789,62 @@ mod tests {
                 })
             ))
         );
+
+        // opaque pointers
+        assert_eq!(
+            super::indirect_call(
+                r#"tail call noundef zeroext i1 %27(ptr noundef nonnull %0, ptr noalias noundef nonnull readonly captures(address, read_provenance) %2, i64 noundef %25) #102, !dbg !1671"#
+            ),
+            Ok((
+                "",
+                Stmt::IndirectCall(FnSig {
+                    inputs: vec![Type::OpaquePointer, Type::OpaquePointer, Type::Integer(64)],
+                    output: Some(Box::new(Type::Integer(1)))
+                })
+            ))
+        );
+
+        assert_eq!(
+            super::indirect_call(r#"tail call void %f()"#),
+            Ok((
+                "",
+                Stmt::IndirectCall(FnSig {
+                    inputs: vec![],
+                    output: None,
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn getelementptr() {
+        assert_eq!(
+            super::getelementptr(
+                r#"  %6 = getelementptr inbounds nuw i8, ptr %5, i64 24, !dbg !1638"#
+            ),
+            Ok(("", ("%6", Type::Integer(8), "%5", 24)))
+        );
+
+        // several indices
+        assert!(super::getelementptr(
+            r#"  %6 = getelementptr inbounds { i32, i32 }, ptr %5, i32 0, i32 1"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn vtable_load() {
+        assert_eq!(
+            super::vtable_load(
+                r#"  %7 = load ptr, ptr %6, align 8, !dbg !1638, !invariant.load !43, !nonnull !43"#
+            ),
+            Ok(("", ("%7", "%6")))
+        );
+
+        assert!(super::vtable_load(
+            r#"  %3 = load ptr, ptr %0, align 8, !dbg !1637, !nonnull !43, !noundef !43"#
+        )
+        .is_err());
     }
 
     #[test]
@@ -567,6 +898,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn alloca() {
+        assert_eq!(
+            super::alloca("  %buf = alloca [1024 x i8], align 1"),
+            Ok((
+                "",
+                Alloca {
+                    name: "%buf",
+                    ty: Type::Array(1024, Box::new(Type::Integer(8))),
+                    count: Some(1),
+                    variables: vec![],
+                }
+            ))
+        );
+
+        assert_eq!(
+            super::alloca("  %2 = alloca i8, i64 16, align 16"),
+            Ok((
+                "",
+                Alloca {
+                    name: "%2",
+                    ty: Type::Integer(8),
+                    count: Some(16),
+                    variables: vec![],
+                }
+            ))
+        );
+
+        assert_eq!(
+            super::alloca("  %3 = alloca i8, i64 %n, align 16"),
+            Ok((
+                "",
+                Alloca {
+                    name: "%3",
+                    ty: Type::Integer(8),
+                    count: None,
+                    variables: vec![],
+                }
+            ))
+        );
+
+        assert_eq!(
+            super::dbg_declare("    #dbg_declare(ptr %buf, !138, !DIExpression(), !153)"),
+            Some(("%buf", 138))
+        );
+
+        assert_eq!(
+            super::dbg_declare(
+                "  call void @llvm.dbg.declare(metadata [64 x i8]* %x, metadata !1187, metadata !DIExpression()), !dbg !1188"
+            ),
+            Some(("%x", 1187))
+        );
+    }
+
     #[test]
     fn parse() {
         assert_eq!(
@@ -575,11 +960,13 @@ mod tests {
                 "",
                 Define {
                     name: "_ZN4core3ptr18real_drop_in_place17h10d0d6d6b26fb8afE",
-                    stmts: vec![Stmt::Label, Stmt::Other],
+                    stmts: vec![(Stmt::Label, None), (Stmt::Other, None)],
                     sig: FnSig {
                         inputs: vec![Type::Pointer(Box::new(Type::Alias("blue_pill::ItmLogger")))],
                         output: None,
                     },
+                    dbg: Some(2105),
+                    allocas: vec![],
                 }
             ))
         );
@@ -591,7 +978,7 @@ mod tests {
                 "",
                 Define {
                     name,
-                    stmts: vec![Stmt::Label, Stmt::Other],
+                    stmts: vec![(Stmt::Label, None), (Stmt::Other, None)],
                     sig: FnSig {
                         inputs: vec![
                             Type::Pointer(Box::new(Type::Integer(8))),
@@ -602,6 +989,8 @@ mod tests {
                         ],
                         output: None,
                     },
+                    dbg: Some(5158),
+                    allocas: vec![],
                 }
             ))
         );
@@ -612,7 +1001,7 @@ mod tests {
                 "",
                 Define {
                     name: "_ZN3std9panicking20rust_panic_with_hook17hac9cf78024704ab4E",
-                    stmts: vec![Stmt::Label, Stmt::Other],
+                    stmts: vec![(Stmt::Label, None), (Stmt::Other, None)],
                     sig: FnSig {
                         inputs: vec![
                             Type::Pointer(Box::new(Type::Struct(vec![]))),
@@ -636,6 +1025,8 @@ mod tests {
                         ],
                         output: None,
                     },
+                    dbg: Some(6634),
+                    allocas: vec![],
                 }
             ))
         );
@@ -646,7 +1037,7 @@ mod tests {
                 "",
                 Define {
                     name: "foo",
-                    stmts: vec![Stmt::Label, Stmt::Other],
+                    stmts: vec![(Stmt::Label, None), (Stmt::Other, Some(1278))],
                     sig: FnSig {
                         inputs: vec![],
                         output: Some(Box::new(Type::Pointer(Box::new(Type::Pointer(Box::new(
@@ -656,6 +1047,8 @@ mod tests {
                             })
                         )))))),
                     },
+                    dbg: Some(1272),
+                    allocas: vec![],
                 }
             ))
         );
@@ -667,15 +1060,17 @@ mod tests {
                 Define {
                     name: "_ZN3app3foo17h3337355bfdc88d96E",
                     stmts: vec![
-                        Stmt::Label,
-                        Stmt::DirectCall("llvm.dbg.value"),
-                        Stmt::Other,
-                        Stmt::Other,
+                        (Stmt::Label, None),
+                        (Stmt::DirectCall("llvm.dbg.value"), Some(1188)),
+                        (Stmt::Other, Some(1189)),
+                        (Stmt::Other, Some(1190))
                     ],
                     sig: FnSig {
                         inputs: vec![Type::Float],
                         output: Some(Box::new(Type::Float)),
                     },
+                    dbg: Some(1183),
+                    allocas: vec![],
                 }
             ))
         );
@@ -686,13 +1081,15 @@ mod tests {
                 "",
                 Define {
                     name: "_defmt_acquire",
-                    stmts: vec![Stmt::Other],
+                    stmts: vec![(Stmt::Other, Some(7208))],
                     sig: FnSig {
                         inputs: vec![Type::Pointer(Box::new(Type::Alias(
                             "core::option::Option<defmt::InternalFormatter>"
                         )))],
                         output: None,
                     },
+                    dbg: Some(5972),
+                    allocas: vec![],
                 }
             ))
         );
@@ -703,11 +1100,97 @@ mod tests {
                 "",
                 Define {
                     name: "__aeabi_uidivmod",
-                    stmts: vec![Stmt::Label, Stmt::Asm("push {lr}"), Stmt::Other],
+                    stmts: vec![
+                        (Stmt::Label, None),
+                        (Stmt::Asm("push {lr}"), Some(263)),
+                        (Stmt::Other, Some(263))
+                    ],
+                    sig: FnSig {
+                        inputs: vec![],
+                        output: None,
+                    },
+                    dbg: Some(258),
+                    allocas: vec![],
+                }
+            ))
+        );
+
+        // typed pointers: the vtable is indexed as an array of function pointers
+        assert_eq!(
+            super::parse(include_str!("define/parse9.ll").trim()),
+            Ok((
+                "",
+                Define {
+                    name: "_ZN2to4main17h5b0b6a5b8c1dd6b4E",
+                    stmts: vec![
+                        (Stmt::Label, None),
+                        (Stmt::Other, Some(1195)),
+                        (Stmt::Other, Some(1195)),
+                        (Stmt::Other, Some(1196)),
+                        (Stmt::Other, Some(1196)),
+                        (Stmt::Other, Some(1196)),
+                        (
+                            Stmt::DynamicCall(
+                                FnSig {
+                                    inputs: vec![Type::erased()],
+                                    output: Some(Box::new(Type::Integer(1))),
+                                },
+                                Slot {
+                                    ty: Type::Pointer(Box::new(Type::Fn(FnSig {
+                                        inputs: vec![Type::erased()],
+                                        output: Some(Box::new(Type::Integer(1))),
+                                    }))),
+                                    index: 3
+                                }
+                            ),
+                            Some(1196)
+                        ),
+                        (Stmt::Other, Some(1197))
+                    ],
                     sig: FnSig {
                         inputs: vec![],
                         output: None,
                     },
+                    dbg: Some(1191),
+                    allocas: vec![],
+                }
+            ))
+        );
+
+        // opaque pointers: the call through the vtable gets an erased receiver
+        assert_eq!(
+            super::parse(include_str!("define/parse8.ll").trim()),
+            Ok((
+                "",
+                Define {
+                    name: "_RNvXs1g_NtCs8NwYtU1Mohg_4core3fmtRDNtB6_5DebugEL_Bx_3fmtB8_",
+                    stmts: vec![
+                        (Stmt::Other, Some(1637)),
+                        (Stmt::Other, Some(1637)),
+                        (Stmt::Other, Some(1637)),
+                        (Stmt::Other, Some(1638)),
+                        (Stmt::Other, Some(1638)),
+                        (
+                            Stmt::DynamicCall(
+                                FnSig {
+                                    inputs: vec![Type::erased(), Type::OpaquePointer],
+                                    output: Some(Box::new(Type::Integer(1))),
+                                },
+                                Slot {
+                                    ty: Type::Integer(8),
+                                    index: 24
+                                }
+                            ),
+                            Some(1638)
+                        ),
+                        (Stmt::Other, Some(1639))
+                    ],
+                    sig: FnSig {
+                        inputs: vec![Type::OpaquePointer, Type::OpaquePointer],
+                        output: Some(Box::new(Type::Integer(1))),
+                    },
+                    dbg: Some(1635),
+                    allocas: vec![],
                 }
             ))
         );
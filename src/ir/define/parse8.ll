@@ -0,0 +1,9 @@
+define internal noundef zeroext i1 @_RNvXs1g_NtCs8NwYtU1Mohg_4core3fmtRDNtB6_5DebugEL_Bx_3fmtB8_(ptr noalias noundef readonly align 8 captures(none) dereferenceable(16) %0, ptr noalias noundef align 8 dereferenceable(24) %1) unnamed_addr #23 !dbg !1635 {
+  %3 = load ptr, ptr %0, align 8, !dbg !1637, !nonnull !43, !noundef !43
+  %4 = getelementptr inbounds nuw i8, ptr %0, i64 8, !dbg !1637
+  %5 = load ptr, ptr %4, align 8, !dbg !1637, !nonnull !43, !align !1415, !noundef !43
+  %6 = getelementptr inbounds nuw i8, ptr %5, i64 24, !dbg !1638
+  %7 = load ptr, ptr %6, align 8, !dbg !1638, !invariant.load !43, !nonnull !43
+  %8 = tail call noundef zeroext i1 %7(ptr noundef nonnull %3, ptr noalias noundef nonnull align 8 dereferenceable(24) %1) #102, !dbg !1638
+  ret i1 %8, !dbg !1639
+}
@@ -0,0 +1,10 @@
+define void @_ZN2to4main17h5b0b6a5b8c1dd6b4E() unnamed_addr #2 !dbg !1191 {
+start:
+  %0 = load {}*, {}** bitcast (<{ [8 x i8] }>* @_ZN2to2TO17h7dfc35b60b0b28b0E to {}**), align 4, !dbg !1195
+  %1 = load [3 x i32]*, [3 x i32]** bitcast (i8* getelementptr inbounds (<{ [8 x i8] }>, <{ [8 x i8] }>* @_ZN2to2TO17h7dfc35b60b0b28b0E, i32 0, i32 0, i32 4) to [3 x i32]**), align 4, !dbg !1195
+  %2 = bitcast [3 x i32]* %1 to i1 ({}*)**, !dbg !1196
+  %3 = getelementptr inbounds i1 ({}*)*, i1 ({}*)** %2, i32 3, !dbg !1196
+  %4 = load i1 ({}*)*, i1 ({}*)** %3, align 4, !dbg !1196, !invariant.load !2, !nonnull !2
+  %5 = tail call zeroext i1 %4({}* nonnull align 1 %0) #8, !dbg !1196
+  ret void, !dbg !1197
+}
@@ -1,13 +1,16 @@
+use std::str::FromStr;
+
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::{char, not_line_ending, space0, space1},
-    combinator::map,
+    character::complete::{alpha1, char, digit1, not_line_ending, space0, space1},
+    combinator::{map, map_res, opt},
+    error::ErrorKind,
     multi::{many0, separated_list},
     IResult,
 };
 
-use crate::ir::{define::Define, FnSig};
+use crate::ir::{define::Define, FnSig, Type};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Item<'a> {
@@ -27,6 +30,9 @@ pub enum Item<'a> {
     // `@__sbss = external global i32`
     Global,
 
+    // `@vtable.0 = private constant { void (%Bar*)*, i32, i32, i1 (%Bar*)* } { .. }`
+    Vtable(Vtable<'a>),
+
     // `%Struct = type { i8, i16 }` ("new type")
     Type,
 
@@ -39,6 +45,9 @@ pub enum Item<'a> {
     // `attributes #0 = { norecurse nounwind readnone "target-cpu"="generic" }`
     Attributes,
 
+    // `!12 = !DIFile(filename: "src/main.rs", directory: "/home/user/app")`
+    DebugInfo(u32, DebugInfo<'a>),
+
     // `!0 = !DIGlobalVariableExpression(var: !1, expr: !DIExpression())`
     Metadata,
 
@@ -52,12 +61,62 @@ pub struct Declare<'a> {
     pub sig: Option<FnSig<'a>>,
 }
 
-fn comment(i: &str) -> IResult<&str, Item> {
+// the debug info metadata nodes needed to map functions and calls to source locations
+#[derive(Clone, Debug, PartialEq)]
+pub enum DebugInfo<'a> {
+    // `!DIFile(filename: "src/main.rs", directory: "/home/user/app")`
+    File {
+        filename: &'a str,
+        directory: &'a str,
+    },
+
+    // `!DISubprogram`, `!DILexicalBlock` or `!DILexicalBlockFile`
+    Scope {
+        file: Option<u32>,
+        line: Option<u32>,
+    },
+
+    // `!DILocation(line: 5, column: 9, scope: !15, inlinedAt: !21)`
+    Location {
+        line: u32,
+        column: u32,
+        scope: u32,
+        inlined_at: Option<u32>,
+    },
+
+    // `!DILocalVariable(name: "buf", scope: !139, file: !34, line: 4, type: !140, align: 8)`
+    Variable {
+        name: &'a str,
+        file: Option<u32>,
+        line: Option<u32>,
+        ty: Option<u32>,
+    },
+
+    // `!DIBasicType`, `!DICompositeType` or `!DIDerivedType`
+    Type {
+        // e.g. `DW_TAG_array_type`; `None` for basic types
+        tag: Option<&'a str>,
+        name: Option<&'a str>,
+        // the element type of arrays
+        base: Option<u32>,
+        // in bits
+        size: Option<u64>,
+    },
+}
+
+// a constant that contains function pointers, e.g. a trait object's vtable
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vtable<'a> {
+    // the type of each field and the function it points to, if any
+    pub entries: Vec<(Type<'a>, Option<&'a str>)>,
+}
+
+fn comment(i: &str) -> IResult<&str, Item<'_>> {
     let i = super::comment(i)?.0;
     Ok((i, Item::Comment))
 }
 
-fn source_filename(i: &str) -> IResult<&str, Item> {
+fn source_filename(i: &str) -> IResult<&str, Item<'_>> {
     let i = tag("source_filename")(i)?.0;
     let i = space1(i)?.0;
     let i = char('=')(i)?.0;
@@ -66,7 +125,7 @@ fn source_filename(i: &str) -> IResult<&str, Item> {
     Ok((i, Item::SourceFilename))
 }
 
-fn target(i: &str) -> IResult<&str, Item> {
+fn target(i: &str) -> IResult<&str, Item<'_>> {
     let i = tag("target")(i)?.0;
     let i = space1(i)?.0;
     let i = alt((tag("datalayout"), tag("triple")))(i)?.0;
@@ -77,7 +136,7 @@ fn target(i: &str) -> IResult<&str, Item> {
     Ok((i, Item::Target))
 }
 
-fn alias(i: &str) -> IResult<&str, Item> {
+fn alias(i: &str) -> IResult<&str, Item<'_>> {
     let (i, name) = super::function(i)?;
     let i = space1(i)?.0;
     let i = char('=')(i)?.0;
@@ -99,7 +158,7 @@ fn alias(i: &str) -> IResult<&str, Item> {
     Ok((i, Item::Alias(name.0, alias.0)))
 }
 
-fn global(i: &str) -> IResult<&str, Item> {
+fn global(i: &str) -> IResult<&str, Item<'_>> {
     let i = super::global(i)?.0;
     let i = space1(i)?.0;
     let i = char('=')(i)?.0;
@@ -116,7 +175,55 @@ fn global(i: &str) -> IResult<&str, Item> {
     Ok((i, Item::Global))
 }
 
-fn type_(i: &str) -> IResult<&str, Item> {
+// `@anon.1 = private unnamed_addr constant <{ ptr, [16 x i8], ptr }> <{ ptr @drop, [16 x i8] c"..", ptr @foo }>, align 8`
+fn vtable(i: &str) -> IResult<&str, Item<'_>> {
+    let i = super::global(i)?.0;
+    let i = space1(i)?.0;
+    let i = char('=')(i)?.0;
+    let i = space1(i)?.0;
+    let i = many0(|i| {
+        let i = super::attribute(i)?.0;
+        space1(i)
+    })(i)?
+    .0;
+    let i = tag("constant")(i)?.0;
+    let i = space1(i)?.0;
+    let i = super::type_(i)?.0;
+    let i = space1(i)?.0;
+    let (i, packed) = opt(char('<'))(i)?;
+    let i = char('{')(i)?.0;
+    let i = space0(i)?.0;
+    let (i, entries) = separated_list(
+        |i| {
+            let i = char(',')(i)?.0;
+            space1(i)
+        },
+        |i| {
+            let (i, ty) = super::type_(i)?;
+            let i = space1(i)?.0;
+            let (i, name) = alt((
+                map(super::bitcast, |b| b.0),
+                map(super::global, |g| g.0),
+                map(super::operand, |_| None),
+            ))(i)?;
+            Ok((i, (ty, name)))
+        },
+    )(i)?;
+    let i = space0(i)?.0;
+    let i = char('}')(i)?.0;
+    let i = if packed.is_some() { char('>')(i)?.0 } else { i };
+
+    if entries.iter().all(|(_, name)| name.is_none()) {
+        // not a vtable
+        return Err(nom::Err::Error((i, ErrorKind::Verify)));
+    }
+
+    // NOTE shortcut
+    let i = not_line_ending(i)?.0;
+    Ok((i, Item::Vtable(Vtable { entries })))
+}
+
+fn type_(i: &str) -> IResult<&str, Item<'_>> {
     let i = super::alias(i)?.0;
     let i = space1(i)?.0;
     let i = char('=')(i)?.0;
@@ -125,7 +232,7 @@ fn type_(i: &str) -> IResult<&str, Item> {
     Ok((i, Item::Type))
 }
 
-fn declare(i: &str) -> IResult<&str, Item> {
+fn declare(i: &str) -> IResult<&str, Item<'_>> {
     let i = tag("declare")(i)?.0;
     let i = space1(i)?.0;
     let i = many0(|i| {
@@ -174,7 +281,7 @@ fn declare(i: &str) -> IResult<&str, Item> {
     }
 }
 
-fn attributes(i: &str) -> IResult<&str, Item> {
+fn attributes(i: &str) -> IResult<&str, Item<'_>> {
     let i = tag("attributes")(i)?.0;
     let i = space1(i)?.0;
     let i = char('#')(i)?.0;
@@ -183,14 +290,115 @@ fn attributes(i: &str) -> IResult<&str, Item> {
     Ok((i, Item::Attributes))
 }
 
-fn metadata(i: &str) -> IResult<&str, Item> {
+// `!15 = distinct !DISubprogram(name: "foo", scope: !13, file: !12, line: 3, type: !14, ..)`
+fn debug_info(i: &str) -> IResult<&str, Item<'_>> {
+    let (i, id) = metadata_id(i)?;
+    let i = space1(i)?.0;
+    let i = char('=')(i)?.0;
+    let i = space1(i)?.0;
+    let i = opt(|i| {
+        let i = tag("distinct")(i)?.0;
+        space1(i)
+    })(i)?
+    .0;
+    let i = char('!')(i)?.0;
+    let (i, kind) = alt((
+        tag("DIFile"),
+        tag("DISubprogram"),
+        tag("DILexicalBlockFile"),
+        tag("DILexicalBlock"),
+        tag("DILocation"),
+        tag("DILocalVariable"),
+        tag("DIBasicType"),
+        tag("DICompositeType"),
+        tag("DIDerivedType"),
+    ))(i)?;
+    let i = char('(')(i)?.0;
+    let (i, fields) = separated_list(
+        |i| {
+            let i = char(',')(i)?.0;
+            space1(i)
+        },
+        |i| {
+            let (i, key) = alpha1(i)?;
+            let i = char(':')(i)?.0;
+            let i = space1(i)?.0;
+            let (i, value) = super::operand(i)?;
+            Ok((i, (key, value)))
+        },
+    )(i)?;
+    let i = char(')')(i)?.0;
+    // NOTE shortcut
+    let i = not_line_ending(i)?.0;
+
+    let field = |key| {
+        fields
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| *value)
+    };
+    let string = |key| field(key).map(|value: &str| value.trim_matches('"'));
+    let number = |key| field(key).and_then(|value| value.parse::<u32>().ok());
+    let reference = |key| {
+        field(key)
+            .and_then(|value: &str| metadata_id(value).ok())
+            .map(|(_, id)| id)
+    };
+
+    let di = match kind {
+        "DIFile" => DebugInfo::File {
+            filename: string("filename").unwrap_or(""),
+            directory: string("directory").unwrap_or(""),
+        },
+
+        "DILocation" => match (number("line"), reference("scope")) {
+            (Some(line), Some(scope)) => DebugInfo::Location {
+                line,
+                column: number("column").unwrap_or(0),
+                scope,
+                inlined_at: reference("inlinedAt"),
+            },
+
+            _ => return Err(nom::Err::Error((i, ErrorKind::Verify))),
+        },
+
+        "DILocalVariable" => DebugInfo::Variable {
+            name: string("name").unwrap_or(""),
+            file: reference("file"),
+            line: number("line"),
+            ty: reference("type"),
+        },
+
+        "DIBasicType" | "DICompositeType" | "DIDerivedType" => DebugInfo::Type {
+            tag: field("tag"),
+            name: string("name"),
+            base: reference("baseType"),
+            size: number("size").map(u64::from),
+        },
+
+        _ => DebugInfo::Scope {
+            file: reference("file"),
+            line: number("line"),
+        },
+    };
+
+    Ok((i, Item::DebugInfo(id, di)))
+}
+
+// `!12`
+fn metadata_id(i: &str) -> IResult<&str, u32> {
+    let i = char('!')(i)?.0;
+    map_res(digit1, u32::from_str)(i)
+}
+
+fn metadata(i: &str) -> IResult<&str, Item<'_>> {
     let i = tag("!")(i)?.0;
     // NOTE shortcut
     let i = not_line_ending(i)?.0;
     Ok((i, Item::Metadata))
 }
 
-fn module_asm(i: &str) -> IResult<&str, Item> {
+fn module_asm(i: &str) -> IResult<&str, Item<'_>> {
     let i = tag("module")(i)?.0;
     let i = space1(i)?.0;
     let i = tag("asm")(i)?.0;
@@ -199,17 +407,19 @@ fn module_asm(i: &str) -> IResult<&str, Item> {
     Ok((i, Item::ModuleAsm))
 }
 
-pub fn item(i: &str) -> IResult<&str, Item> {
+pub fn item(i: &str) -> IResult<&str, Item<'_>> {
     alt((
         comment,
         source_filename,
         target,
         type_,
+        vtable,
         global,
         alias,
         map(super::define::parse, Item::Define),
         declare,
         attributes,
+        debug_info,
         metadata,
         module_asm,
     ))(i)
@@ -217,7 +427,8 @@ pub fn item(i: &str) -> IResult<&str, Item> {
 
 #[cfg(test)]
 mod tests {
-    use crate::ir::{Declare, FnSig, Item, Type};
+    use super::{DebugInfo, Declare, Vtable};
+    use crate::ir::{FnSig, Item, Type};
 
     #[test]
     fn alias() {
@@ -227,6 +438,93 @@ mod tests {
         );
     }
 
+    #[test]
+    fn debug_info() {
+        assert_eq!(
+            super::item(r#"!9 = !DIFile(filename: "src/main.rs", directory: "/home/user/app")"#),
+            Ok((
+                "",
+                Item::DebugInfo(
+                    9,
+                    DebugInfo::File {
+                        filename: "src/main.rs",
+                        directory: "/home/user/app"
+                    }
+                )
+            ))
+        );
+
+        assert_eq!(
+            super::item(
+                r#"!48 = distinct !DISubprogram(name: "dealloc", linkageName: "_ZN3app7dealloc17h0123456789abcdefE", scope: !50, file: !49, line: 47, type: !55, scopeLine: 47, flags: DIFlagPrototyped, spFlags: DISPFlagLocalToUnit | DISPFlagDefinition | DISPFlagOptimized, unit: !24, templateParams: !43)"#
+            ),
+            Ok((
+                "",
+                Item::DebugInfo(
+                    48,
+                    DebugInfo::Scope {
+                        file: Some(49),
+                        line: Some(47)
+                    }
+                )
+            ))
+        );
+
+        assert_eq!(
+            super::item(r#"!47 = !DILocation(line: 48, column: 18, scope: !48, inlinedAt: !56)"#),
+            Ok((
+                "",
+                Item::DebugInfo(
+                    47,
+                    DebugInfo::Location {
+                        line: 48,
+                        column: 18,
+                        scope: 48,
+                        inlined_at: Some(56)
+                    }
+                )
+            ))
+        );
+
+        assert_eq!(
+            super::item(
+                r#"!138 = !DILocalVariable(name: "buf", scope: !139, file: !34, line: 4, type: !140, align: 8)"#
+            ),
+            Ok((
+                "",
+                Item::DebugInfo(
+                    138,
+                    DebugInfo::Variable {
+                        name: "buf",
+                        file: Some(34),
+                        line: Some(4),
+                        ty: Some(140)
+                    }
+                )
+            ))
+        );
+
+        assert_eq!(
+            super::item(
+                r#"!140 = !DICompositeType(tag: DW_TAG_array_type, baseType: !92, size: 8192, align: 8, elements: !141)"#
+            ),
+            Ok((
+                "",
+                Item::DebugInfo(
+                    140,
+                    DebugInfo::Type {
+                        tag: Some("DW_TAG_array_type"),
+                        name: None,
+                        base: Some(92),
+                        size: Some(8192)
+                    }
+                )
+            ))
+        );
+
+        assert_eq!(super::item(r#"!141 = !{!142}"#), Ok(("", Item::Metadata)));
+    }
+
     #[test]
     fn declare() {
         assert_eq!(
@@ -257,6 +555,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn vtable() {
+        assert_eq!(
+            super::vtable(
+                r#"@vtable.0 = private unnamed_addr constant { void (%Bar*)*, i32, i32, i1 (%Bar*)* } { void (%Bar*)* @_ZN4core3ptr18real_drop_in_place17h7b6ae16b0d4a2bf5E, i32 0, i32 1, i1 (%Bar*)* @_ZN2to3Foo3foo17h4c1ec7b4b43a3c8bE }, align 4"#
+            ),
+            Ok((
+                "",
+                Item::Vtable(Vtable {
+                    entries: vec![
+                        (
+                            Type::Pointer(Box::new(Type::Fn(FnSig {
+                                inputs: vec![Type::Pointer(Box::new(Type::Alias("Bar")))],
+                                output: None,
+                            }))),
+                            Some("_ZN4core3ptr18real_drop_in_place17h7b6ae16b0d4a2bf5E")
+                        ),
+                        (Type::Integer(32), None),
+                        (Type::Integer(32), None),
+                        (
+                            Type::Pointer(Box::new(Type::Fn(FnSig {
+                                inputs: vec![Type::Pointer(Box::new(Type::Alias("Bar")))],
+                                output: Some(Box::new(Type::Integer(1))),
+                            }))),
+                            Some("_ZN2to3Foo3foo17h4c1ec7b4b43a3c8bE")
+                        ),
+                    ]
+                })
+            ))
+        );
+
+        // opaque pointers; the drop glue is missing so its slot is merged into the byte array
+        assert_eq!(
+            super::vtable(
+                r#"@anon.8d4a802a96d56a98f0392272e8b62d2f.6 = private unnamed_addr constant <{ [24 x i8], ptr, ptr }> <{ [24 x i8] c"\00\00\00\00\00\00\00\00,\00\00\00\00\00\00\00\08\00\00\00\00\00\00\00", ptr @foo, ptr @bar }>, align 8"#
+            ),
+            Ok((
+                "",
+                Item::Vtable(Vtable {
+                    entries: vec![
+                        (Type::Array(24, Box::new(Type::Integer(8))), None),
+                        (Type::OpaquePointer, Some("foo")),
+                        (Type::OpaquePointer, Some("bar")),
+                    ]
+                })
+            ))
+        );
+
+        // no function pointers
+        assert!(super::vtable(r#"@alloc_1 = private unnamed_addr constant <{ [5 x i8] }> <{ [5 x i8] c"hello" }>, align 1"#).is_err());
+    }
+
     #[test]
     fn module_asm() {
         assert_eq!(super::item(r#"module asm """#), Ok(("", Item::ModuleAsm)));
@@ -42,6 +42,9 @@ pub enum Type<'a> {
     // `i8*`
     Pointer(Box<Type<'a>>),
 
+    // `ptr`; newer LLVM versions no longer record the pointee type
+    OpaquePointer,
+
     // `...`
     Varargs,
 
@@ -64,6 +67,60 @@ impl<'a> Type<'a> {
             _ => false,
         }
     }
+
+    // size in bytes, including padding, on a target with pointers of `ptr_size` bytes
+    pub fn size(&self, ptr_size: u64) -> Option<u64> {
+        Some(match self {
+            Type::Array(count, ty) => *count as u64 * ty.size(ptr_size)?,
+            Type::Double => 8,
+            Type::Float => 4,
+            Type::Integer(bits) => (*bits as u64).div_ceil(8),
+            Type::PackedStruct(fields) => {
+                let mut size = 0;
+                for field in fields {
+                    size += field.size(ptr_size)?;
+                }
+                size
+            }
+            Type::Struct(fields) => {
+                let mut size = 0;
+                let mut align = 1;
+                for field in fields {
+                    let field_align = field.align(ptr_size)?;
+                    align = align.max(field_align);
+                    size = round_up(size, field_align) + field.size(ptr_size)?;
+                }
+                round_up(size, align)
+            }
+            Type::Pointer(_) | Type::OpaquePointer => ptr_size,
+            Type::MVTVector(count, ty) => *count as u64 * ty.size(ptr_size)?,
+            Type::Alias(_) | Type::Fn(_) | Type::Varargs => return None,
+        })
+    }
+
+    // NOTE approximation: the real value is given by the target's data layout
+    fn align(&self, ptr_size: u64) -> Option<u64> {
+        Some(match self {
+            Type::Array(_, ty) => ty.align(ptr_size)?,
+            Type::PackedStruct(_) => 1,
+            Type::Struct(fields) => {
+                let mut align = 1;
+                for field in fields {
+                    align = align.max(field.align(ptr_size)?);
+                }
+                align
+            }
+            Type::MVTVector(..) => self.size(ptr_size)?,
+            _ => self
+                .size(ptr_size)?
+                .next_power_of_two()
+                .min(ptr_size.max(8)),
+        })
+    }
+}
+
+fn round_up(n: u64, align: u64) -> u64 {
+    n.div_ceil(align) * align
 }
 
 fn fmt_struct(f: &mut fmt::Formatter, fields: &[Type]) -> fmt::Result {
@@ -135,6 +192,9 @@ impl<'a> fmt::Display for Type<'a> {
                 write!(f, "{}", ty)?;
                 f.write_str("*")?;
             }
+            Type::OpaquePointer => {
+                f.write_str("ptr")?;
+            }
             Type::Varargs => {
                 f.write_str("...")?;
             }
@@ -151,7 +211,7 @@ impl<'a> fmt::Display for Type<'a> {
     }
 }
 
-fn array(i: &str) -> IResult<&str, Type> {
+fn array(i: &str) -> IResult<&str, Type<'_>> {
     delimited(
         char('['),
         |i| {
@@ -166,7 +226,7 @@ fn array(i: &str) -> IResult<&str, Type> {
     )(i)
 }
 
-fn mvt_vector(i: &str) -> IResult<&str, Type> {
+fn mvt_vector(i: &str) -> IResult<&str, Type<'_>> {
     delimited(
         char('<'),
         |i| {
@@ -181,29 +241,41 @@ fn mvt_vector(i: &str) -> IResult<&str, Type> {
     )(i)
 }
 
-fn double(i: &str) -> IResult<&str, Type> {
+fn double(i: &str) -> IResult<&str, Type<'_>> {
     Ok((tag("double")(i)?.0, Type::Double))
 }
 
-fn float(i: &str) -> IResult<&str, Type> {
+fn float(i: &str) -> IResult<&str, Type<'_>> {
     Ok((tag("float")(i)?.0, Type::Float))
 }
 
-fn integer(i: &str) -> IResult<&str, Type> {
+fn integer(i: &str) -> IResult<&str, Type<'_>> {
     let i = char('i')(i)?.0;
     let (i, count) = map_res(digit1, usize::from_str)(i)?;
     Ok((i, Type::Integer(count)))
 }
 
-fn alias(i: &str) -> IResult<&str, Type> {
+// `ptr` or `ptr addrspace(1)`
+fn opaque_pointer(i: &str) -> IResult<&str, Type<'_>> {
+    let i = tag("ptr")(i)?.0;
+    let i = opt(|i| {
+        let i = space1(i)?.0;
+        let i = tag("addrspace")(i)?.0;
+        delimited(char('('), digit1, char(')'))(i)
+    })(i)?
+    .0;
+    Ok((i, Type::OpaquePointer))
+}
+
+fn alias(i: &str) -> IResult<&str, Type<'_>> {
     map(super::alias, |a| Type::Alias(a.0))(i)
 }
 
-fn varargs(i: &str) -> IResult<&str, Type> {
+fn varargs(i: &str) -> IResult<&str, Type<'_>> {
     Ok((tag("...")(i)?.0, Type::Varargs))
 }
 
-fn _struct(i: &str) -> IResult<&str, Vec<Type>> {
+fn _struct(i: &str) -> IResult<&str, Vec<Type<'_>>> {
     let i = char('{')(i)?.0;
     let i = space0(i)?.0;
     let (i, fields) = separated_list(
@@ -218,18 +290,18 @@ fn _struct(i: &str) -> IResult<&str, Vec<Type>> {
     Ok((i, fields))
 }
 
-fn packed_struct(i: &str) -> IResult<&str, Type> {
+fn packed_struct(i: &str) -> IResult<&str, Type<'_>> {
     let i = char('<')(i)?.0;
     let (i, fields) = _struct(i)?;
     let i = char('>')(i)?.0;
     Ok((i, Type::PackedStruct(fields)))
 }
 
-fn struct_(i: &str) -> IResult<&str, Type> {
+fn struct_(i: &str) -> IResult<&str, Type<'_>> {
     map(_struct, Type::Struct)(i)
 }
 
-pub fn type_(i: &str) -> IResult<&str, Type> {
+pub fn type_(i: &str) -> IResult<&str, Type<'_>> {
     let (i, void) = opt(tag("void"))(i)?;
 
     if void.is_some() {
@@ -263,6 +335,7 @@ pub fn type_(i: &str) -> IResult<&str, Type> {
             double,
             float,
             integer,
+            opaque_pointer,
             varargs,
             mvt_vector,
         ))(i)?;
@@ -313,7 +386,7 @@ pub fn type_(i: &str) -> IResult<&str, Type> {
     }
 }
 
-fn fn_inputs(i: &str) -> IResult<&str, Vec<Type>> {
+fn fn_inputs(i: &str) -> IResult<&str, Vec<Type<'_>>> {
     let i = char('(')(i)?.0;
     let i = space0(i)?.0;
     let (i, inputs) = separated_list(
@@ -425,6 +498,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn opaque_pointer() {
+        assert_eq!(super::type_("ptr"), Ok(("", Type::OpaquePointer)));
+        assert_eq!(
+            super::type_("ptr addrspace(1)"),
+            Ok(("", Type::OpaquePointer))
+        );
+
+        assert_eq!(
+            super::type_("{ ptr, i64 }"),
+            Ok((
+                "",
+                Type::Struct(vec![Type::OpaquePointer, Type::Integer(64)])
+            ))
+        );
+
+        assert_eq!(
+            super::type_("i1 (ptr, ptr)"),
+            Ok((
+                "",
+                Type::Fn(FnSig {
+                    inputs: vec![Type::OpaquePointer, Type::OpaquePointer],
+                    output: Some(Box::new(Type::Integer(1))),
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(Type::OpaquePointer.size(4), Some(4));
+        assert_eq!(Type::Integer(1).size(4), Some(1));
+        assert_eq!(
+            Type::Array(16, Box::new(Type::Integer(8))).size(8),
+            Some(16)
+        );
+        assert_eq!(
+            Type::Struct(vec![Type::Integer(8), Type::Integer(32)]).size(4),
+            Some(8)
+        );
+        assert_eq!(
+            Type::PackedStruct(vec![Type::Integer(8), Type::Integer(32)]).size(4),
+            Some(5)
+        );
+        assert_eq!(Type::Alias("Foo").size(4), None);
+    }
+
     #[test]
     fn varargs() {
         assert_eq!(super::varargs(r#"..."#), Ok(("", Type::Varargs)));
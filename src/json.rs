@@ -0,0 +1,124 @@
+//! Machine-readable output format
+//!
+//! The schema is versioned: any change that could break a consumer (renaming or removing a field,
+//! changing the meaning of a value, adding a new `kind` of value) must bump `VERSION`. Adding
+//! new fields does not require a bump.
+//!
+//! - version 2: `local` and `max` can be `{ "kind": "unbounded" }`
+
+use std::io::{self, Write};
+
+use petgraph::graph::{Graph, NodeIndex};
+use serde::{Deserialize, Serialize};
+
+use crate::{Call, Local, Location, Max, Node};
+
+/// Version of the JSON schema
+pub const VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct CallGraph<'a> {
+    version: u32,
+    nodes: Vec<JsonNode<'a>>,
+    edges: Vec<Edge<'a>>,
+    // strongly connected components, i.e. cycles, as lists of node IDs
+    cycles: Vec<Vec<usize>>,
+}
+
+#[derive(Serialize)]
+struct JsonNode<'a> {
+    id: usize,
+    // symbol name as it appears in the binary (or a signature for fictitious nodes)
+    name: &'a str,
+    demangled: &'a str,
+    local: Local,
+    // `null` when the program has zero stack usage information
+    max: Option<Max>,
+    // ID of the callee that contributes the most to `max`
+    worst_callee: Option<usize>,
+    // fictitious nodes are used to represent indirect function calls and dynamic dispatch
+    fictitious: bool,
+    // where the function is defined; `null` without debug info
+    location: Option<&'a Location>,
+}
+
+#[derive(Serialize)]
+struct Edge<'a> {
+    source: usize,
+    target: usize,
+    // the caller's stack frame is popped before the callee runs
+    tail: bool,
+    // where the call appears in the source code; `null` without debug info
+    location: Option<&'a Location>,
+}
+
+/// A call graph read back from the JSON output
+#[derive(Deserialize)]
+pub(crate) struct Saved {
+    pub(crate) version: u32,
+    pub(crate) nodes: Vec<SavedNode>,
+    pub(crate) edges: Vec<SavedEdge>,
+    pub(crate) cycles: Vec<Vec<usize>>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SavedNode {
+    pub(crate) name: String,
+    pub(crate) local: Local,
+    pub(crate) max: Option<Max>,
+    pub(crate) fictitious: bool,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SavedEdge {
+    pub(crate) source: usize,
+    pub(crate) target: usize,
+    // not present in the output of older versions
+    #[serde(default)]
+    pub(crate) tail: bool,
+}
+
+pub fn write<W>(g: &Graph<Node, Call>, cycles: &[Vec<NodeIndex>], mut w: W) -> io::Result<()>
+where
+    W: Write,
+{
+    let cg = CallGraph {
+        version: VERSION,
+        nodes: g
+            .raw_nodes()
+            .iter()
+            .enumerate()
+            .map(|(id, node)| {
+                let node = &node.weight;
+
+                JsonNode {
+                    id,
+                    name: &node.name,
+                    demangled: &node.demangled,
+                    local: node.local,
+                    max: node.max,
+                    worst_callee: node.worst_callee.map(|callee| callee.index()),
+                    fictitious: node.dashed,
+                    location: node.location.as_ref(),
+                }
+            })
+            .collect(),
+        edges: g
+            .raw_edges()
+            .iter()
+            .map(|edge| Edge {
+                source: edge.source().index(),
+                target: edge.target().index(),
+                tail: edge.weight == Call::Tail,
+                location: g[edge.source()].call_sites.get(&edge.target()),
+            })
+            .collect(),
+        cycles: cycles
+            .iter()
+            .map(|cycle| cycle.iter().map(|node| node.index()).collect())
+            .collect(),
+    };
+
+    serde_json::to_writer_pretty(&mut w, &cg)?;
+    writeln!(w)
+}
@@ -0,0 +1,3063 @@
+//! Whole program static stack analysis
+//!
+//! This crate builds the call graph of a program from the artifacts produced by the compiler and
+//! computes the maximum stack usage of each function. The `cargo-call-stack` tool is built on top
+//! of it.
+//!
+//! ``` no_run
+//! use std::fs;
+//!
+//! use cargo_call_stack::{Artifacts, CallGraph};
+//!
+//! # fn main() -> Result<(), failure::Error> {
+//! let elf = fs::read("app")?;
+//! let ll = fs::read_to_string("app.ll")?;
+//! let obj = fs::read("app.o")?;
+//!
+//! let cg = CallGraph::from_artifacts(&Artifacts {
+//!     elf: &elf,
+//!     ll: &ll,
+//!     obj: Some(&obj),
+//!     compiler_builtins_rlib: None,
+//!     compiler_builtins_ll: None,
+//!     target: "thumbv7m-none-eabi",
+//! })?;
+//!
+//! if let Some(max) = cg.max_stack("main") {
+//!     println!("`main` uses {} bytes of stack", max);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#![deny(warnings)]
+
+use core::{
+    cmp,
+    fmt::{self, Write as _},
+    mem, ops, str,
+};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    io::{self, Read},
+    iter,
+    path::Path,
+};
+
+use ar::Archive;
+use failure::format_err;
+use log::{error, warn};
+use petgraph::{
+    algo,
+    graph::{DiGraph, NodeIndex},
+    visit::{Dfs, EdgeRef, Reversed, Topo, VisitMap},
+    Direction, Graph,
+};
+use serde::{Deserialize, Serialize};
+use xmas_elf::{header::Class, sections::SectionData, symbol_table::Entry, ElfFile};
+
+use crate::{
+    annotations::Annotations,
+    ir::{Alloca, DebugInfo, FnSig, Item, Stmt, Type},
+    pattern::Pattern,
+    thumb::Tag,
+};
+
+pub mod annotations;
+pub mod baseline;
+pub mod budget;
+pub mod diff;
+mod explain;
+pub mod headroom;
+mod ir;
+mod json;
+pub mod pattern;
+pub mod preemption;
+pub mod rtic;
+mod table;
+mod thumb;
+mod tree;
+
+// Font used in the dot graphs
+pub(crate) const FONT: &str = "monospace";
+
+/// The artifacts produced by the compiler
+///
+/// The program must have been compiled with `-C lto=fat` so that all its code is in a single LLVM
+/// IR file, and with `-Z emit-stack-sizes`
+pub struct Artifacts<'a> {
+    /// The linked program
+    pub elf: &'a [u8],
+    /// LLVM IR of the program
+    pub ll: &'a str,
+    /// Object file with stack usage information; if `None` the `.stack_sizes` section of the ELF
+    /// file is used
+    pub obj: Option<&'a [u8]>,
+    /// `compiler_builtins` rlib, for the stack usage of its functions
+    pub compiler_builtins_rlib: Option<&'a [u8]>,
+    /// LLVM IR of `compiler_builtins`
+    pub compiler_builtins_ll: Option<&'a str>,
+    /// Target triple for which the program was compiled
+    pub target: &'a str,
+}
+
+/// The call graph of a program, annotated with stack usage information
+#[derive(Clone)]
+pub struct CallGraph<'a> {
+    g: Graph<Node<'a>, Call>,
+    // strongly connected components, i.e. cycles
+    cycles: Vec<Vec<NodeIndex>>,
+    indices: BTreeMap<Cow<'a, str>, NodeIndex>,
+    addr2name: BTreeMap<u64, &'a str>,
+    elf: &'a [u8],
+    target: &'a str,
+    // set when the graph has been filtered
+    starts: Vec<NodeIndex>,
+    diagnostics: Vec<Diagnostic<'a>>,
+}
+
+impl<'a> CallGraph<'a> {
+    /// Builds the call graph of a program and computes the maximum stack usage of its functions
+    #[allow(deprecated)]
+    pub fn from_artifacts(artifacts: &Artifacts<'a>) -> Result<Self, failure::Error> {
+        let elf = artifacts.elf;
+        let target = artifacts.target;
+        let items = crate::ir::parse(artifacts.ll)
+            .map_err(|e| format_err!("failed to parse application's LLVM IR: {}", e))?;
+        let compiler_builtins_items = if let Some(ll) = artifacts.compiler_builtins_ll {
+            crate::ir::parse(ll)
+                .map_err(|e| format_err!("failed to parse `compiler_builtins` LLVM IR: {}", e))?
+                .into_iter()
+                // NOTE metadata IDs are local to each module so we only use the debug info of the
+                // application
+                .filter_map(|item| match item {
+                    Item::DebugInfo(..) => None,
+                    Item::Define(mut def) => {
+                        def.dbg = None;
+                        for (_, dbg) in &mut def.stmts {
+                            *dbg = None;
+                        }
+                        Some(Item::Define(def))
+                    }
+                    item => Some(item),
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        // only these functions can be called through a function pointer
+        let mut address_taken = crate::ir::address_taken(artifacts.ll);
+        if let Some(ll) = artifacts.compiler_builtins_ll {
+            address_taken.extend(crate::ir::address_taken(ll));
+        }
+        let mut defines = HashMap::new();
+        let mut declares = HashMap::new();
+        let mut vtables = vec![];
+        let mut locator = Locator::default();
+        for item in items.into_iter().chain(compiler_builtins_items) {
+            match item {
+                Item::Vtable(vtable) => vtables.push(vtable),
+
+                Item::DebugInfo(id, di) => {
+                    locator.nodes.insert(id, di);
+                }
+
+                Item::Define(def) => {
+                    defines.insert(def.name, def);
+                }
+
+                Item::Declare(decl) => {
+                    declares.insert(decl.name, decl);
+                }
+
+                _ => {}
+            }
+        }
+
+        // we know how to analyze the machine code in the ELF file for these targets thus we have more
+        // information and need less LLVM-IR hacks
+        let target_ = match target {
+            "thumbv6m-none-eabi" => Target::Thumbv6m,
+            "thumbv7m-none-eabi" | "thumbv7em-none-eabi" | "thumbv7em-none-eabihf" => {
+                Target::Thumbv7m
+            }
+            _ => Target::Other,
+        };
+
+        // extract stack size information
+        let mut stack_sizes: HashMap<_, _> = if let Some(obj) = artifacts.obj {
+            // the `.o` file doesn't have address information so we just keep the stack usage
+            // information
+            stack_sizes::analyze_object(obj)?
+                .into_iter()
+                .map(|(name, stack)| (name.to_owned(), stack))
+                .collect()
+        } else {
+            // the linker may have kept the `.stack_sizes` section (e.g. `cortex-m-rt`'s linker script
+            // does)
+            let mut stack_sizes = HashMap::new();
+            for function in stack_sizes::analyze_executable(elf)?.defined.values() {
+                if let Some(stack) = function.stack() {
+                    for name in function.names() {
+                        stack_sizes.insert(name.to_string(), stack);
+                    }
+                }
+            }
+            stack_sizes
+        };
+
+        if let Some(rlib) = artifacts.compiler_builtins_rlib {
+            let mut ar = Archive::new(rlib);
+
+            let mut buf = vec![];
+            while let Some(entry) = ar.next_entry() {
+                let mut entry = entry?;
+                let header = entry.header();
+
+                if str::from_utf8(header.identifier())
+                    .map(|id| id.contains("compiler_builtins") && id.ends_with(".o"))
+                    .unwrap_or(false)
+                {
+                    buf.clear();
+                    entry.read_to_end(&mut buf)?;
+                    stack_sizes.extend(
+                        stack_sizes::analyze_object(&buf)?
+                            .into_iter()
+                            .map(|(name, stack)| (name.to_owned(), stack)),
+                    );
+                }
+            }
+        }
+
+        // extract list of "live" symbols (symbols that have not been GC-ed by the linker)
+        // this time we use the ELF and not the object file
+        let mut symbols = stack_sizes::analyze_executable(elf)?;
+
+        // clear the thumb bit
+        if target_.is_thumb() {
+            symbols.defined = symbols
+                .defined
+                .into_iter()
+                .map(|(k, v)| (k & !1, v))
+                .collect();
+        }
+
+        // remove version strings from undefined symbols
+        symbols.undefined = symbols
+            .undefined
+            .into_iter()
+            .map(|sym| {
+                if let Some(name) = sym.rsplit("@@").nth(1) {
+                    name
+                } else {
+                    sym
+                }
+            })
+            .collect();
+
+        let mut g = DiGraph::<Node, Call>::new();
+        let mut indices = BTreeMap::<Cow<str>, _>::new();
+
+        let mut indirects: HashMap<FnSig, Indirect> = HashMap::new();
+        let mut dynamics: HashMap<FnSig, Dynamic> = HashMap::new();
+        // functions that could be called by `ArgumentV1.formatter`
+        let mut fmts = HashSet::new();
+        // signatures of functions whose first argument is a pointer, with the pointer erased
+        let mut erased_sigs = HashMap::new();
+
+        // Some functions may be aliased; we map aliases to a single name. For example, if `foo`,
+        // `bar` and `baz` all have the same address then this maps contains: `foo -> foo`, `bar -> foo`
+        // and `baz -> foo`.
+        let mut aliases = HashMap::new();
+        // whether a symbol name is ambiguous after removing the hash
+        let mut ambiguous = HashMap::<String, u32>::new();
+
+        // we do a first pass over all the definitions to collect methods in `impl Trait for Type`
+        let mut default_methods = HashSet::new();
+        for name in defines.keys() {
+            let demangled = rustc_demangle::demangle(name).to_string();
+
+            // `<crate::module::Type as crate::module::Trait>::method::hdeadbeef`
+            if demangled.starts_with("<") {
+                if let Some(rhs) = demangled.splitn(2, " as ").nth(1) {
+                    // rhs = `crate::module::Trait>::method::hdeadbeef`
+                    let mut parts = rhs.splitn(2, ">::");
+
+                    if let (Some(trait_), Some(rhs)) = (parts.next(), parts.next()) {
+                        // trait_ = `crate::module::Trait`, rhs = `method::hdeadbeef`
+
+                        if let Some(method) = dehash(rhs) {
+                            default_methods.insert(format!("{}::{}", trait_, method));
+                        }
+                    }
+                }
+            }
+        }
+
+        // add all real nodes
+        let mut has_stack_usage_info = false;
+        let mut has_untyped_symbols = false;
+        let mut addr2name = BTreeMap::new();
+        // problems that the analysis recovered from
+        let mut diagnostics = vec![];
+        for (address, sym) in &symbols.defined {
+            let names = sym.names();
+
+            let canonical_name = if names.len() > 1 {
+                // if one of the aliases appears in the `stack_sizes` dictionary, use that
+                if let Some(needle) = names.iter().find(|name| stack_sizes.contains_key(&***name)) {
+                    needle
+                } else {
+                    // otherwise, pick the first name that's not a tag
+                    names
+                        .iter()
+                        .filter_map(|&name| {
+                            if name == "$a" || name.starts_with("$a.") {
+                                None
+                            } else {
+                                Some(name)
+                            }
+                        })
+                        .next()
+                        .unwrap_or(names[0])
+                }
+            } else {
+                names[0]
+            };
+
+            for name in names {
+                aliases.insert(name, canonical_name);
+            }
+
+            let _out = addr2name.insert(*address, canonical_name);
+            debug_assert!(_out.is_none());
+
+            let stack = stack_sizes.get(canonical_name).cloned();
+            if stack.is_none() {
+                if !target_.is_thumb() {
+                    warn!("no stack usage information for `{}`", canonical_name);
+                }
+            } else {
+                has_stack_usage_info = true;
+            }
+
+            let demangled = rustc_demangle::demangle(canonical_name).to_string();
+            if let Some(dehashed) = dehash(&demangled) {
+                *ambiguous.entry(dehashed.to_string()).or_insert(0) += 1;
+            }
+
+            let idx = g.add_node(Node(canonical_name, stack, false));
+            indices.insert(canonical_name.into(), idx);
+
+            // trait methods look like `<crate::module::Type as crate::module::Trait>::method::h$hash`
+            // default trait methods look like `crate::module::Trait::method::h$hash`
+            let is_trait_method = demangled.starts_with("<") && demangled.contains(" as ") || {
+                dehash(&demangled)
+                    .map(|path| default_methods.contains(path))
+                    .unwrap_or(false)
+            };
+
+            let is_address_taken = names.iter().any(|name| address_taken.contains(name));
+
+            if let Some(def) = names.iter().filter_map(|name| defines.get(name)).next() {
+                // the signature this function would have if it was called through a vtable
+                match def.sig.inputs.first() {
+                    Some(Type::Pointer(_)) | Some(Type::OpaquePointer) => {
+                        let mut sig = def.sig.clone();
+                        sig.inputs[0] = Type::erased();
+                        erased_sigs.insert(idx, sig);
+                    }
+                    _ => {}
+                }
+
+                // if the signature is `fn(&_, &mut fmt::Formatter) -> fmt::Result`
+                match (&def.sig.inputs[..], def.sig.output.as_ref()) {
+                    ([Type::Pointer(..), Type::Pointer(fmt)], Some(output))
+                        if **fmt == Type::Alias("core::fmt::Formatter")
+                            && **output == Type::Integer(1)
+                            && is_address_taken =>
+                    {
+                        fmts.insert(idx);
+                    }
+
+                    // with opaque pointers all we can go by is the name of the method
+                    ([Type::OpaquePointer, Type::OpaquePointer], Some(output))
+                        if **output == Type::Integer(1)
+                            && is_address_taken
+                            && is_trait_method
+                            && dehash(&demangled).unwrap_or(&demangled).ends_with("::fmt") =>
+                    {
+                        fmts.insert(idx);
+                    }
+
+                    _ => {}
+                }
+
+                let is_object_safe = is_trait_method && {
+                    match def.sig.inputs.first().as_ref() {
+                        Some(Type::Pointer(ty)) => match **ty {
+                            // XXX can the receiver be a *specific* function? (e.g. `fn() {foo}`)
+                            Type::Fn(_) => false,
+
+                            _ => true,
+                        },
+                        Some(Type::OpaquePointer) => true,
+                        _ => false,
+                    }
+                };
+
+                if is_object_safe {
+                    let mut sig = def.sig.clone();
+
+                    // erase the type of the reciver
+                    sig.inputs[0] = Type::erased();
+
+                    dynamics.entry(sig).or_default().callees.insert(idx);
+                } else if is_address_taken {
+                    indirects
+                        .entry(def.sig.clone())
+                        .or_default()
+                        .callees
+                        .insert(idx);
+                }
+            } else if let Some(sig) = names
+                .iter()
+                .filter_map(|name| declares.get(name).and_then(|decl| decl.sig.clone()))
+                .next()
+            {
+                // sanity check (?)
+                if is_trait_method {
+                    diagnose(
+                        &mut diagnostics,
+                        canonical_name,
+                        Some(*address),
+                        "trait method is declared but not defined in the LLVM IR".to_string(),
+                    );
+                }
+
+                if is_address_taken {
+                    indirects.entry(sig).or_default().callees.insert(idx);
+                }
+            } else {
+                has_untyped_symbols = true;
+                warn!("no type information for `{}`", canonical_name);
+            }
+        }
+
+        // functions that appear in a vtable, indexed by their offset (in bytes) into the vtable
+        let ptr_size = match ElfFile::new(elf)
+            .map_err(failure::err_msg)?
+            .header
+            .pt1
+            .class()
+        {
+            Class::ThirtyTwo => 4,
+            _ => 8,
+        };
+        let mut slots: HashMap<u64, HashSet<NodeIndex>> = HashMap::new();
+        for vtable in &vtables {
+            let mut offset = 0;
+            for (ty, name) in &vtable.entries {
+                if let Some(canonical_name) = name.and_then(|name| aliases.get(&name)) {
+                    slots
+                        .entry(offset)
+                        .or_default()
+                        .insert(indices[*canonical_name]);
+                }
+
+                if let Some(size) = ty.size(ptr_size) {
+                    offset += size;
+                } else {
+                    // can't tell where the next entries are
+                    break;
+                }
+            }
+        }
+
+        // to avoid printing several warnings about the same thing
+        let mut fns_containing_asm = HashSet::new();
+        let mut llvm_seen = HashSet::new();
+        // add edges
+        let mut edges: HashMap<_, HashSet<_>> = HashMap::new(); // NodeIdx -> [NodeIdx]
+        let mut defined = HashSet::new(); // functions that are `define`-d in the LLVM-IR
+        let mut call_sites = HashMap::new(); // (caller, callee) -> debug location of the call
+        for define in defines.values() {
+            let canonical_name = match aliases.get(&define.name) {
+                Some(canonical_name) => canonical_name,
+                None => {
+                    // this symbol was GC-ed by the linker, skip
+                    continue;
+                }
+            };
+            defined.insert(*canonical_name);
+            let caller = indices[*canonical_name];
+            let callees_seen = edges.entry(caller).or_default();
+
+            if let Some(location) = define.dbg.and_then(|dbg| locator.function(dbg)) {
+                g[caller].location = Some(location);
+            }
+            g[caller].variables = define
+                .allocas
+                .iter()
+                .map(|alloca| locator.variable(alloca, ptr_size))
+                .collect();
+
+            if define.allocas.iter().any(|alloca| alloca.count.is_none()) {
+                // `-Z emit-stack-sizes` only reports the fixed part of the frame
+                warn!(
+                    "`{}` allocates a dynamically sized stack slot; its stack usage is unbounded",
+                    canonical_name
+                );
+                g[caller].local = Local::Unbounded;
+            }
+
+            for (stmt, dbg) in &define.stmts {
+                let mut call_site = |callee| {
+                    if let Some(dbg) = dbg {
+                        call_sites.entry((caller, callee)).or_insert(*dbg);
+                    }
+                };
+
+                match stmt {
+                    Stmt::Asm(expr) => {
+                        if fns_containing_asm.insert(*canonical_name) {
+                            // NB: we only print the first inline asm statement in a function
+                            warn!(
+                                "assuming that asm!(\"{}\") does *not* use the stack in `{}`",
+                                expr, canonical_name
+                            );
+                        }
+                    }
+
+                    // this is basically `(mem::transmute<*const u8, fn()>(&__some_symbol))()`
+                    Stmt::BitcastCall(sym) => {
+                        // XXX we have some type information for this call but it's unclear if we should
+                        // try harder -- does this ever occur in pure Rust programs?
+
+                        let sym = if let Some(sym) = *sym {
+                            sym
+                        } else {
+                            diagnose(
+                                &mut diagnostics,
+                                canonical_name,
+                                address_of(&addr2name, canonical_name),
+                                "an unnamed symbol is being invoked".to_string(),
+                            );
+
+                            let callee = g.add_node(Node("?", None, false));
+                            g.add_edge(caller, callee, Call::Normal);
+                            continue;
+                        };
+                        let callee = if let Some(idx) = indices.get(sym) {
+                            *idx
+                        } else {
+                            warn!("no stack information for `{}`", sym);
+
+                            let idx = g.add_node(Node(sym, None, false));
+                            indices.insert(Cow::Borrowed(sym), idx);
+                            idx
+                        };
+
+                        g.add_edge(caller, callee, Call::Normal);
+                        call_site(callee);
+                    }
+
+                    Stmt::DirectCall(func) => {
+                        match *func {
+                            // no-op / debug-info
+                            "llvm.dbg.value" => continue,
+                            "llvm.dbg.declare" => continue,
+
+                            // no-op / compiler-hint
+                            "llvm.assume" => continue,
+                            "llvm.experimental.noalias.scope.decl" => continue,
+                            "llvm.is.constant.i1" => continue,
+
+                            // lowers to a single instruction
+                            "llvm.trap" => continue,
+
+                            _ => {}
+                        }
+
+                        // no-op / compiler-hint
+                        if func.starts_with("llvm.lifetime.start")
+                            || func.starts_with("llvm.lifetime.end")
+                        {
+                            continue;
+                        }
+
+                        let mut call = |callee| {
+                            if !callees_seen.contains(&callee) {
+                                g.add_edge(caller, callee, Call::Normal);
+                                callees_seen.insert(callee);
+                            }
+                            call_site(callee);
+                        };
+
+                        if target_.is_thumb() && func.starts_with("llvm.") {
+                            // we'll analyze the machine code in the ELF file to figure out what these
+                            // lower to
+                            continue;
+                        }
+
+                        // TODO? consider alignment and `value` argument to only include one edge
+                        // TODO? consider the `len` argument to elide the call to `*mem*`
+                        if func.starts_with("llvm.memcpy.") {
+                            if let Some(callee) = indices.get("memcpy") {
+                                call(*callee);
+                            }
+
+                            // ARMv7-R and the like use these
+                            if let Some(callee) = indices.get("__aeabi_memcpy") {
+                                call(*callee);
+                            }
+
+                            if let Some(callee) = indices.get("__aeabi_memcpy4") {
+                                call(*callee);
+                            }
+
+                            continue;
+                        }
+
+                        // TODO? consider alignment and `value` argument to only include one edge
+                        // TODO? consider the `len` argument to elide the call to `*mem*`
+                        if func.starts_with("llvm.memset.") || func.starts_with("llvm.memmove.") {
+                            if let Some(callee) = indices.get("memset") {
+                                call(*callee);
+                            }
+
+                            // ARMv7-R and the like use these
+                            if let Some(callee) = indices.get("__aeabi_memset") {
+                                call(*callee);
+                            }
+
+                            if let Some(callee) = indices.get("__aeabi_memset4") {
+                                call(*callee);
+                            }
+
+                            if let Some(callee) = indices.get("memclr") {
+                                call(*callee);
+                            }
+
+                            if let Some(callee) = indices.get("__aeabi_memclr") {
+                                call(*callee);
+                            }
+
+                            if let Some(callee) = indices.get("__aeabi_memclr4") {
+                                call(*callee);
+                            }
+
+                            continue;
+                        }
+
+                        // XXX unclear whether these produce library calls on some platforms or not
+                        if func.starts_with("llvm.abs.")
+                            | func.starts_with("llvm.bitreverse.")
+                            | func.starts_with("llvm.bswap.")
+                            | func.starts_with("llvm.ctlz.")
+                            | func.starts_with("llvm.ctpop.")
+                            | func.starts_with("llvm.cttz.")
+                            | func.starts_with("llvm.fshl.")
+                            | func.starts_with("llvm.fshr.")
+                            | func.starts_with("llvm.load.relative.")
+                            | func.starts_with("llvm.sadd.with.overflow.")
+                            | func.starts_with("llvm.smul.with.overflow.")
+                            | func.starts_with("llvm.ssub.with.overflow.")
+                            | func.starts_with("llvm.threadlocal.address.")
+                            | func.starts_with("llvm.uadd.with.overflow.")
+                            | func.starts_with("llvm.ucmp.")
+                            | func.starts_with("llvm.umax.")
+                            | func.starts_with("llvm.umin.")
+                            | func.starts_with("llvm.umul.with.overflow.")
+                            | func.starts_with("llvm.usub.sat.")
+                            | func.starts_with("llvm.usub.with.overflow.")
+                            | func.starts_with("llvm.vector.reduce.")
+                            | func.starts_with("llvm.x86.sse2.pause")
+                            | func.starts_with("llvm.x86.sse2.pmovmskb.")
+                        {
+                            if !llvm_seen.contains(func) {
+                                llvm_seen.insert(func);
+                                warn!("assuming that `{}` directly lowers to machine code", func);
+                            }
+
+                            continue;
+                        }
+
+                        if func.starts_with("llvm.") {
+                            diagnose(
+                                &mut diagnostics,
+                                canonical_name,
+                                address_of(&addr2name, canonical_name),
+                                format!("unhandled LLVM intrinsic `{}`", func),
+                            );
+
+                            // it may lower to a call to some function
+                            let callee = g.add_node(Node("?", None, false));
+                            g.add_edge(caller, callee, Call::Normal);
+                            continue;
+                        }
+
+                        // use canonical name
+                        let callee = if let Some(canon) = aliases.get(func) {
+                            indices[*canon]
+                        } else {
+                            if !symbols.undefined.contains(func) {
+                                diagnose(
+                                    &mut diagnostics,
+                                    canonical_name,
+                                    address_of(&addr2name, canonical_name),
+                                    format!("callee `{}` is unknown", func),
+                                );
+                            }
+
+                            if let Some(idx) = indices.get(*func) {
+                                *idx
+                            } else {
+                                let idx = g.add_node(Node(*func, None, false));
+                                indices.insert((*func).into(), idx);
+
+                                idx
+                            }
+                        };
+
+                        if !callees_seen.contains(&callee) {
+                            callees_seen.insert(callee);
+                            g.add_edge(caller, callee, Call::Normal);
+                        }
+                        call_site(callee);
+                    }
+
+                    Stmt::IndirectCall(sig) => {
+                        if sig
+                            .inputs
+                            .first()
+                            .map(|ty| ty.has_been_erased())
+                            .unwrap_or(false)
+                        {
+                            // dynamic dispatch
+                            let dynamic = dynamics.entry(sig.clone()).or_default();
+
+                            dynamic.called = true;
+                            dynamic.callers.insert(caller);
+                        } else {
+                            let indirect = indirects.entry(sig.clone()).or_default();
+
+                            indirect.called = true;
+                            indirect.callers.insert(caller);
+                        }
+                    }
+
+                    Stmt::DynamicCall(sig, slot) => {
+                        let dynamic = dynamics.entry(sig.clone()).or_default();
+
+                        dynamic.called = true;
+                        if let Some(size) = slot.ty.size(ptr_size) {
+                            dynamic
+                                .slots
+                                .entry(size * slot.index)
+                                .or_default()
+                                .insert(caller);
+                        } else {
+                            dynamic.callers.insert(caller);
+                        }
+                    }
+
+                    Stmt::Label | Stmt::Comment | Stmt::Other => {}
+                }
+            }
+        }
+
+        for ((caller, callee), dbg) in call_sites {
+            if let Some(location) = locator.statement(dbg) {
+                g[caller].call_sites.insert(callee, location);
+            }
+        }
+
+        // here we parse the machine code in the ELF file to find out edges that don't appear in the
+        // LLVM-IR (e.g. `fadd` operation, `call llvm.umul.with.overflow`, etc.) or are difficult to
+        // disambiguate from the LLVM-IR (e.g. does this `llvm.memcpy` lower to a call to
+        // `__aebi_memcpy`, a call to `__aebi_memcpy4` or machine instructions?)
+        if target_.is_thumb() {
+            let elf = ElfFile::new(elf).map_err(failure::err_msg)?;
+            let sect = elf
+                .find_section_by_name(".symtab")
+                .ok_or_else(|| format_err!(".symtab section not found"))?;
+            let mut tags: Vec<_> = match sect.get_data(&elf).map_err(failure::err_msg)? {
+                SectionData::SymbolTable32(entries) => entries
+                    .iter()
+                    .filter_map(|entry| {
+                        let addr = entry.value() as u32;
+                        entry.get_name(&elf).ok().and_then(|name| {
+                            if name.starts_with("$d") {
+                                Some((addr, Tag::Data))
+                            } else if name.starts_with("$t") {
+                                Some((addr, Tag::Thumb))
+                            } else {
+                                None
+                            }
+                        })
+                    })
+                    .collect(),
+                _ => return Err(format_err!("expected a 32-bit symbol table")),
+            };
+
+            tags.sort_by(|a, b| a.0.cmp(&b.0));
+
+            if let Some(sect) = elf.find_section_by_name(".text") {
+                let stext = sect.address() as u32;
+                let text = sect.raw_data(&elf);
+
+                for (address, sym) in &symbols.defined {
+                    let address = *address as u32;
+                    let canonical_name = aliases[&sym.names()[0]];
+                    let mut size = sym.size() as u32;
+
+                    if size == 0 {
+                        // try harder at finding out the size of this symbol
+                        if let Ok(needle) = tags.binary_search_by(|tag| tag.0.cmp(&address)) {
+                            let start = tags[needle];
+                            if start.1 == Tag::Thumb {
+                                if let Some(end) = tags.get(needle + 1) {
+                                    if end.1 == Tag::Thumb {
+                                        size = end.0 - start.0;
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    // NOTE not all functions live in `.text`; some may have been placed in RAM
+                    let code = address
+                        .checked_sub(stext)
+                        .and_then(|start| {
+                            let start = start as usize;
+                            text.get(start..start + size as usize)
+                        })
+                        .ok_or_else(|| {
+                            format_err!("the function is not fully contained in `.text`")
+                        });
+                    let caller = indices[canonical_name];
+                    let analysis = code.and_then(|code| {
+                        thumb::analyze(code, address, target_ == Target::Thumbv7m, &tags)
+                    });
+                    let (bls, bs, indirect, modifies_sp, our_stack, dynamic_sp) = match analysis {
+                        Ok(analysis) => analysis,
+                        Err(e) => {
+                            diagnose(
+                                &mut diagnostics,
+                                canonical_name,
+                                Some(address.into()),
+                                format!("couldn't analyze the machine code: {}", e),
+                            );
+
+                            // neither the stack usage nor the callees can be checked
+                            if g[caller].local != Local::Unbounded {
+                                g[caller].local = Local::Unknown;
+                            }
+                            let callee = g.add_node(Node("?", None, false));
+                            g.add_edge(caller, callee, Call::Normal);
+                            continue;
+                        }
+                    };
+
+                    // check the correctness of `modifies_sp` and `our_stack`
+                    // also override LLVM's results when they appear to be wrong
+                    if our_stack.map(|stack| (stack != 0) != modifies_sp) == Some(true) {
+                        // sanity check
+                        diagnose(
+                            &mut diagnostics,
+                            canonical_name,
+                            Some(address.into()),
+                            format!(
+                                "our analysis reported that it both uses {} bytes of stack and it \
+                                 does{} modify SP",
+                                our_stack.unwrap_or(0),
+                                if !modifies_sp { " not" } else { "" }
+                            ),
+                        );
+
+                        g[caller].local = Local::Unknown;
+                    } else if dynamic_sp {
+                        // neither LLVM's result nor ours accounts for the run-time adjustment
+                        if g[caller].local != Local::Unbounded {
+                            warn!(
+                                "`{}` adjusts the stack pointer by a run-time value; \
+                                 its stack usage is unbounded",
+                                canonical_name
+                            );
+                        }
+
+                        g[caller].local = Local::Unbounded;
+                    } else if g[caller].local == Local::Unbounded {
+                        // dynamically sized `alloca` in the LLVM IR; nothing to check
+                    } else if let Local::Exact(llvm_stack) = g[caller].local {
+                        // NOTE at this point `our_stack` agrees with `modifies_sp`
+                        let ours = match our_stack {
+                            Some(stack) if llvm_stack != stack => {
+                                if fns_containing_asm.contains(&canonical_name) {
+                                    // LLVM's stack usage analysis ignores inline asm, so its
+                                    // results can be wrong here
+
+                                    warn!(
+                                        "LLVM reported that `{}` uses {} bytes of stack but \
+                                         our analysis reported {} bytes; overriding LLVM's result \
+                                         (function uses inline assembly)",
+                                        canonical_name, llvm_stack, stack
+                                    );
+
+                                    g[caller].local = Local::Exact(stack);
+                                    None
+                                } else {
+                                    // in all other cases our results should match
+                                    Some(format!("{} bytes", stack))
+                                }
+                            }
+                            Some(_) => None,
+                            None if (llvm_stack != 0) != modifies_sp => Some(format!(
+                                "that it does{} modify SP",
+                                if !modifies_sp { " not" } else { "" }
+                            )),
+                            None => None,
+                        };
+
+                        if let Some(ours) = ours {
+                            diagnose(
+                                &mut diagnostics,
+                                canonical_name,
+                                Some(address.into()),
+                                format!(
+                                    "LLVM reported that it uses {} bytes of stack but our \
+                                     analysis reported {}",
+                                    llvm_stack, ours
+                                ),
+                            );
+
+                            g[caller].local = Local::Unknown;
+                        }
+                    } else if let Some(stack) = our_stack {
+                        g[caller].local = Local::Exact(stack);
+                    } else if !modifies_sp {
+                        // this happens when our analysis can't track the SP through the control flow
+                        // graph, e.g. because of a jump table (`our_stack == None`)
+                        g[caller].local = Local::Exact(0);
+                    }
+
+                    if g[caller].local == Local::Unknown {
+                        warn!("no stack usage information for `{}`", canonical_name);
+                    }
+
+                    if !defined.contains(canonical_name) && indirect {
+                        // this function performs an indirect function call and we have no type
+                        // information to narrow down the list of callees so inject the uncertainty
+                        // in the form of a call to an unknown function with unknown stack usage
+
+                        warn!(
+                            "`{}` performs an indirect function call and there's \
+                             no type information about the operation",
+                            canonical_name,
+                        );
+                        let callee = g.add_node(Node("?", None, false));
+                        g.add_edge(caller, callee, Call::Normal);
+                    }
+
+                    let callees_seen = edges.entry(caller).or_default();
+                    let mut called = HashSet::new();
+                    for offset in bls {
+                        let addr = (address as i64 + i64::from(offset)) as u64;
+                        // address may be off by one due to the thumb bit being set
+                        let callee = if let Some(name) = addr2name.get(&addr) {
+                            indices[*name]
+                        } else {
+                            diagnose(
+                                &mut diagnostics,
+                                canonical_name,
+                                Some(address.into()),
+                                format!(
+                                    "no symbol at address {:#010x}, the target of a `BL`",
+                                    addr
+                                ),
+                            );
+
+                            g.add_node(Node("?", None, false))
+                        };
+
+                        called.insert(callee);
+                        if !callees_seen.contains(&callee) {
+                            g.add_edge(caller, callee, Call::Normal);
+                            callees_seen.insert(callee);
+                        }
+                    }
+
+                    for offset in bs {
+                        let addr = (address as i32 + offset) as u32;
+
+                        if addr >= address && addr < (address + size) {
+                            // intra-function B branches are not function calls
+                        } else {
+                            // address may be off by one due to the thumb bit being set
+                            let callee = if let Some(name) = addr2name.get(&(addr as u64)) {
+                                indices[*name]
+                            } else {
+                                diagnose(
+                                    &mut diagnostics,
+                                    canonical_name,
+                                    Some(address.into()),
+                                    format!(
+                                        "no symbol at address {:#010x}, the target of a `B`",
+                                        addr
+                                    ),
+                                );
+
+                                g.add_node(Node("?", None, false))
+                            };
+
+                            if called.contains(&callee) {
+                                // also called with `BL`; the edge must remain a normal call
+                            } else if callees_seen.contains(&callee) {
+                                // the call found in the LLVM IR was lowered to a tail call
+                                let tail_calls = g
+                                    .edges(caller)
+                                    .filter(|edge| edge.target() == callee)
+                                    .map(|edge| edge.id())
+                                    .collect::<Vec<_>>();
+                                for edge in tail_calls {
+                                    g[edge] = Call::Tail;
+                                }
+                            } else {
+                                g.add_edge(caller, callee, Call::Tail);
+                                callees_seen.insert(callee);
+                            }
+                        }
+                    }
+                }
+            } else {
+                error!(".text section not found")
+            }
+        }
+
+        // add fictitious nodes for indirect function calls
+        if has_untyped_symbols {
+            warn!(
+                "the program contains untyped, external symbols (e.g. linked in from binary blobs); \
+                 indirect function calls can not be bounded"
+            );
+        }
+
+        // this is a bit weird but for some reason `ArgumentV1.formatter` sometimes lowers to different
+        // LLVM types. In theory it should always be: `i1 (*%fmt::Void, *&core::fmt::Formatter)*` but
+        // sometimes the type of the first argument is `%fmt::Void`, sometimes it's `%core::fmt::Void`,
+        // sometimes is `%core::fmt::Void.12` and on occasion it's even `%SomeRandomType`
+        //
+        // To cope with this weird fact the following piece of code will try to find the right LLVM
+        // type.
+        let all_maybe_void = indirects
+            .keys()
+            .filter_map(|sig| match (&sig.inputs[..], sig.output.as_ref()) {
+                ([Type::Pointer(receiver), Type::Pointer(formatter)], Some(output))
+                    if **formatter == Type::Alias("core::fmt::Formatter")
+                        && **output == Type::Integer(1) =>
+                {
+                    if let Type::Alias(receiver) = **receiver {
+                        Some(receiver)
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let one_true_void = if all_maybe_void.contains(&"fmt::Void") {
+            Some("fmt::Void")
+        } else {
+            all_maybe_void
+                .iter()
+                .filter_map(|maybe_void| {
+                    // this could be `core::fmt::Void` or `core::fmt::Void.12`
+                    if maybe_void.starts_with("core::fmt::Void") {
+                        Some(*maybe_void)
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .or_else(|| {
+                    if all_maybe_void.len() == 1 {
+                        // we got a random type!
+                        Some(all_maybe_void[0])
+                    } else {
+                        None
+                    }
+                })
+        };
+
+        let mut opaque_fmts = HashSet::new();
+        for (mut sig, indirect) in indirects {
+            if !indirect.called {
+                continue;
+            }
+
+            let callees = if let Some(one_true_void) = one_true_void {
+                match (&sig.inputs[..], sig.output.as_ref()) {
+                    // special case: this is `ArgumentV1.formatter` a pseudo trait object
+                    ([Type::Pointer(void), Type::Pointer(fmt)], Some(output))
+                        if **void == Type::Alias(one_true_void)
+                            && **fmt == Type::Alias("core::fmt::Formatter")
+                            && **output == Type::Integer(1) =>
+                    {
+                        if fmts.is_empty() {
+                            error!("BUG? no callees for `{}`", sig.to_string());
+                        }
+
+                        // canonicalize the signature
+                        if one_true_void != "fmt::Void" {
+                            sig.inputs[0] = Type::Alias("fmt::Void");
+                        }
+
+                        &fmts
+                    }
+
+                    _ => &indirect.callees,
+                }
+            } else if is_opaque_formatter(&sig) {
+                // `fmt::rt::Argument`'s formatter: `fn(&_, &mut fmt::Formatter) -> fmt::Result`
+                opaque_fmts.extend(indirect.callees.iter().cloned());
+                opaque_fmts.extend(fmts.iter().cloned());
+                &opaque_fmts
+            } else {
+                &indirect.callees
+            };
+
+            let mut name = sig.to_string();
+            // append '*' to denote that this is a function pointer
+            name.push('*');
+
+            let call = g.add_node(Node(name.clone(), Some(0), true));
+
+            for caller in &indirect.callers {
+                g.add_edge(*caller, call, Call::Normal);
+            }
+
+            if has_untyped_symbols {
+                // add an edge between this and a potential extern / untyped symbol
+                let extern_sym = g.add_node(Node("?", None, false));
+                g.add_edge(call, extern_sym, Call::Normal);
+            } else {
+                if callees.is_empty() {
+                    error!("BUG? no callees for `{}`", name);
+                }
+            }
+
+            for callee in callees {
+                g.add_edge(call, *callee, Call::Normal);
+            }
+        }
+
+        // add fictitious nodes for dynamic dispatch
+        for (sig, dynamic) in dynamics {
+            if !dynamic.called {
+                continue;
+            }
+
+            let name = sig.to_string();
+
+            // one node per vtable slot; the slot is only included in the name when needed to tell
+            // the nodes apart
+            let nodes = dynamic.slots.len() + if dynamic.callers.is_empty() { 0 } else { 1 };
+            for (offset, callers) in dynamic
+                .slots
+                .iter()
+                .map(|(offset, callers)| (Some(*offset), callers))
+                .chain(Some((None, &dynamic.callers)).filter(|(_, callers)| !callers.is_empty()))
+            {
+                // only the functions that appear in this slot of some vtable can be called
+                let in_slot = offset
+                    .and_then(|offset| slots.get(&offset))
+                    .map(|in_slot| {
+                        in_slot
+                            .iter()
+                            .filter(|callee| erased_sigs.get(*callee) == Some(&sig))
+                            .cloned()
+                            .collect::<HashSet<_>>()
+                    })
+                    .unwrap_or_default();
+                let callees = if in_slot.is_empty() {
+                    if let Some(offset) = offset {
+                        warn!(
+                            "no vtable contains a `{}` method at offset {}; \
+                             falling back to signature matching",
+                            name, offset
+                        );
+                    }
+
+                    &dynamic.callees
+                } else {
+                    &in_slot
+                };
+
+                let name = match offset {
+                    Some(offset) if nodes > 1 => format!("{} [vtable+{}]", name, offset),
+                    _ => name.clone(),
+                };
+
+                if callees.is_empty() {
+                    error!("BUG? no callees for `{}`", name);
+                }
+
+                let call = g.add_node(Node(name, Some(0), true));
+                for caller in callers {
+                    g.add_edge(*caller, call, Call::Normal);
+                }
+
+                for callee in callees {
+                    g.add_edge(call, *callee, Call::Normal);
+                }
+            }
+        }
+
+        let cycles = if has_stack_usage_info {
+            compute_max(&mut g)
+        } else {
+            error!("The graph has zero stack usage information; skipping max stack usage analysis");
+            vec![]
+        };
+
+        // here we try to shorten the name of the symbol if it doesn't result in ambiguity
+        for node in g.node_weights_mut() {
+            if let Some(dehashed) = dehash(&node.demangled) {
+                if ambiguous[dehashed] == 1 {
+                    node.demangled = dehashed.to_owned();
+                }
+            }
+        }
+
+        Ok(CallGraph {
+            g,
+            cycles,
+            indices,
+            addr2name,
+            elf,
+            target,
+            starts: vec![],
+            diagnostics,
+        })
+    }
+
+    /// Applies user annotations and recomputes the maximum stack usage of every function
+    ///
+    /// The diagnostics about annotated functions that no longer have an unknown stack usage or
+    /// call an unknown function are dropped. This must be done before the graph is filtered
+    pub fn annotate(&mut self, annotations: &Annotations) {
+        let annotated = annotations::apply(&mut self.g, annotations);
+
+        let g = &self.g;
+        let indices = &self.indices;
+        self.diagnostics
+            .retain(|diagnostic| match indices.get(diagnostic.function) {
+                Some(node) if annotated.contains(node) => {
+                    g[*node].local == Local::Unknown
+                        || g.neighbors(*node).any(|callee| g[callee].name == "?")
+                }
+                _ => true,
+            });
+
+        if self
+            .g
+            .raw_nodes()
+            .iter()
+            .any(|node| node.weight.max.is_some())
+        {
+            for node in self.g.node_weights_mut() {
+                node.max = None;
+                node.worst_callee = None;
+            }
+
+            self.cycles = compute_max(&mut self.g);
+        }
+    }
+
+    /// Returns the subgraph that contains only the functions reachable from `start`
+    ///
+    /// Returns `None` if `start` could not be found
+    pub fn filter(&self, start: &str) -> Option<CallGraph<'a>> {
+        self.filter_all(&[Pattern::Name(start.to_owned())])
+    }
+
+    /// Returns the subgraph that contains only the functions reachable from the functions that
+    /// match any of the `starts`
+    ///
+    /// Patterns that don't match any function are ignored; returns `None` if none matched
+    pub fn filter_all(&self, starts: &[Pattern]) -> Option<CallGraph<'a>> {
+        let mut roots = vec![];
+        for start in starts {
+            let hits = match start {
+                Pattern::Name(name) => self.find(name).into_iter().collect(),
+                Pattern::Regex(_) => self
+                    .g
+                    .node_indices()
+                    .filter(|inode| !self.g[*inode].dashed && start.matches(&self.g[*inode]))
+                    .collect::<Vec<_>>(),
+            };
+
+            for hit in hits {
+                if !roots.contains(&hit) {
+                    roots.push(hit);
+                }
+            }
+        }
+
+        if roots.is_empty() {
+            None
+        } else {
+            Some(self.filter_indices(&roots))
+        }
+    }
+
+    /// Returns the subgraph that contains only the functions reachable from the roots of the call
+    /// graph, i.e. the functions that are not called by other functions, with all of them as start
+    /// points
+    pub fn filter_roots(&self) -> CallGraph<'a> {
+        let roots = self
+            .g
+            .externals(Direction::Incoming)
+            .filter(|root| !self.g[*root].dashed)
+            .collect::<Vec<_>>();
+
+        self.filter_indices(&roots)
+    }
+
+    /// Returns the call graph without the functions that match any of the `patterns` and the
+    /// functions that can only be reached through them
+    ///
+    /// The maximum stack usage of the remaining functions still accounts for the hidden ones
+    pub fn hide(&self, patterns: &[Pattern]) -> CallGraph<'a> {
+        let g = &self.g;
+
+        let hidden = g
+            .node_indices()
+            .filter(|inode| patterns.iter().any(|pattern| pattern.matches(&g[*inode])))
+            .collect::<HashSet<_>>();
+
+        // the hidden functions and everything they call
+        let mut behind = HashSet::new();
+        let mut dfs = Dfs::empty(g);
+        for inode in &hidden {
+            dfs.move_to(*inode);
+            while let Some(callee) = dfs.next(g) {
+                behind.insert(callee);
+            }
+        }
+
+        // what can be reached without going through a hidden function remains visible
+        let mut visible = HashSet::new();
+        let mut stack = g
+            .node_indices()
+            .filter(|inode| !behind.contains(inode))
+            .collect::<Vec<_>>();
+        while let Some(inode) = stack.pop() {
+            if !hidden.contains(&inode) && visible.insert(inode) {
+                stack.extend(g.neighbors(inode));
+            }
+        }
+
+        let keep = g
+            .node_indices()
+            .filter(|inode| visible.contains(inode))
+            .collect::<Vec<_>>();
+        self.subgraph(&keep, &self.starts)
+    }
+
+    fn filter_indices(&self, starts: &[NodeIndex]) -> CallGraph<'a> {
+        let g = &self.g;
+
+        // the nodes reachable from `starts`
+        let mut keep = vec![];
+        let mut dfs = Dfs::empty(g);
+        for start in starts {
+            // NOTE `move_to` visits `start` again even if it has already been discovered
+            if !dfs.discovered.is_visited(start) {
+                dfs.move_to(*start);
+                keep.extend(iter::from_fn(|| dfs.next(g)));
+            }
+        }
+
+        self.subgraph(&keep, starts)
+    }
+
+    // creates a new graph that only contains the nodes in `keep`
+    fn subgraph(&self, keep: &[NodeIndex], starts: &[NodeIndex]) -> CallGraph<'a> {
+        let g = &self.g;
+        let mut g2 = DiGraph::<Node, Call>::new();
+
+        // maps `g`'s `NodeIndex`-es to `g2`'s `NodeIndex`-es
+        let mut one2two = BTreeMap::new();
+        for i1 in keep {
+            one2two.insert(*i1, g2.add_node(g[*i1].clone()));
+        }
+
+        for caller1 in keep {
+            for edge in g.edges(*caller1) {
+                if let Some(callee2) = one2two.get(&edge.target()) {
+                    g2.add_edge(one2two[caller1], *callee2, *edge.weight());
+                }
+            }
+        }
+
+        for node in g2.node_weights_mut() {
+            node.worst_callee = node
+                .worst_callee
+                .and_then(|callee| one2two.get(&callee).cloned());
+            node.call_sites = mem::take(&mut node.call_sites)
+                .into_iter()
+                .filter_map(|(callee, location)| {
+                    one2two.get(&callee).map(|callee| (*callee, location))
+                })
+                .collect();
+        }
+
+        let cycles = self
+            .cycles
+            .iter()
+            .map(|cycle| {
+                cycle
+                    .iter()
+                    .filter_map(|node| one2two.get(node).cloned())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|cycle| !cycle.is_empty())
+            .collect();
+
+        let indices = self
+            .indices
+            .iter()
+            .filter_map(|(name, i1)| one2two.get(i1).map(|i2| (name.clone(), *i2)))
+            .collect();
+
+        CallGraph {
+            g: g2,
+            cycles,
+            indices,
+            addr2name: self.addr2name.clone(),
+            elf: self.elf,
+            target: self.target,
+            starts: starts
+                .iter()
+                .filter_map(|start| one2two.get(start).cloned())
+                .collect(),
+            diagnostics: self.diagnostics.clone(),
+        }
+    }
+
+    /// The (first) start point of a filtered graph
+    pub fn start(&self) -> Option<&Node<'a>> {
+        self.starts.first().map(|start| &self.g[*start])
+    }
+
+    /// The start points of a filtered graph
+    pub fn starts(&self) -> Vec<&Node<'a>> {
+        self.starts.iter().map(|start| &self.g[*start]).collect()
+    }
+
+    /// The start points of a filtered graph and their maximum stack usage, largest first
+    ///
+    /// Start points without stack usage information are left out
+    pub fn starts_by_max(&self) -> Vec<(&Node<'a>, Max)> {
+        starts_by_max(&self.g, &self.starts)
+            .into_iter()
+            .map(|(start, max)| (&self.g[start], max))
+            .collect()
+    }
+
+    /// The problems that the analysis recovered from while building the call graph
+    ///
+    /// The functions involved usually have an unknown stack usage or call an unknown function
+    pub fn diagnostics(&self) -> &[Diagnostic<'a>] {
+        &self.diagnostics
+    }
+
+    /// All the nodes in the call graph
+    pub fn nodes(&self) -> impl Iterator<Item = &Node<'a>> {
+        self.g.raw_nodes().iter().map(|node| &node.weight)
+    }
+
+    /// Looks up a function by its symbol name or by its demangled name without the hash
+    pub fn node(&self, function: &str) -> Option<&Node<'a>> {
+        self.find(function).map(|inode| &self.g[inode])
+    }
+
+    /// Maximum stack usage of `function`
+    ///
+    /// Returns `None` if the function could not be found or if the program has no stack usage
+    /// information
+    pub fn max_stack(&self, function: &str) -> Option<Max> {
+        self.node(function).and_then(|node| node.max)
+    }
+
+    /// The functions called by `function`
+    pub fn callees(&self, function: &str) -> Option<Vec<&Node<'a>>> {
+        self.neighbors(function, Direction::Outgoing)
+    }
+
+    /// The functions that call `function`
+    pub fn callers(&self, function: &str) -> Option<Vec<&Node<'a>>> {
+        self.neighbors(function, Direction::Incoming)
+    }
+
+    /// Where `caller` calls `callee` in the source code, if the program was compiled with debug info
+    ///
+    /// Only direct calls have a call site
+    pub fn call_site(&self, caller: &str, callee: &str) -> Option<&Location> {
+        let caller = self.find(caller)?;
+        let callee = self.find(callee)?;
+        self.g[caller].call_sites.get(&callee)
+    }
+
+    /// The cycles, i.e. strongly connected components, in the call graph
+    pub fn cycles(&self) -> Vec<Vec<&Node<'a>>> {
+        self.cycles
+            .iter()
+            .map(|cycle| cycle.iter().map(|inode| &self.g[*inode]).collect())
+            .collect()
+    }
+
+    /// The chain of calls, starting at `function`, that produces its maximum stack usage
+    pub fn worst_path(&self, function: &str) -> Option<Vec<&Node<'a>>> {
+        self.find(function).map(|root| {
+            worst_path(&self.g, root)
+                .into_iter()
+                .map(|inode| &self.g[inode])
+                .collect()
+        })
+    }
+
+    /// Prints the worst-case call path of `function` to stderr, one function per line
+    pub fn print_worst_path(&self, function: &str) {
+        if let Some(root) = self.find(function) {
+            print_worst_path(&self.g, root, &self.cycles)
+        }
+    }
+
+    /// Writes the call graph in dot format
+    ///
+    /// If the graph has been filtered, the worst-case call paths of the start points are highlighted
+    pub fn dot<W>(&self, w: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        dot(&self.g, &self.cycles, &self.starts, w)
+    }
+
+    /// Writes the call graph in JSON format
+    pub fn json<W>(&self, w: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        json::write(&self.g, &self.cycles, w)
+    }
+
+    /// Writes the `top` functions with the largest local stack usage and the `top` functions with
+    /// the largest maximum stack usage as text tables
+    pub fn table<W>(&self, top: usize, w: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        table::write(&self.g, top, w)
+    }
+
+    /// Writes the call graph as an indented tree rooted at the start points or, if the graph has
+    /// not been filtered, at the functions that are not called by other functions
+    ///
+    /// Functions whose callees have already been listed are marked with `(*)`; calls that close a
+    /// cycle are marked with `(cycle)`
+    pub fn tree<W>(&self, w: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        tree::write(&self.g, &self.roots(), w)
+    }
+
+    // the start points, if filtered, or else the functions that are not called by other functions
+    fn roots(&self) -> Vec<NodeIndex> {
+        if !self.starts.is_empty() {
+            self.starts.clone()
+        } else {
+            self.g.externals(Direction::Incoming).collect()
+        }
+    }
+
+    fn neighbors(&self, function: &str, dir: Direction) -> Option<Vec<&Node<'a>>> {
+        self.find(function).map(|inode| {
+            self.g
+                .neighbors_directed(inode, dir)
+                .map(|neighbor| &self.g[neighbor])
+                .collect()
+        })
+    }
+
+    fn find(&self, name: &str) -> Option<NodeIndex> {
+        let indices = &self.indices;
+        indices.get(name).cloned().or_else(|| {
+            let name_ = name.to_owned() + "::h";
+            let hits = indices
+                .keys()
+                .filter_map(|key| {
+                    let demangled = rustc_demangle::demangle(key);
+                    if demangled.to_string().starts_with(&name_)
+                        // `v0` names contain several hashes, e.g. `app[ebcb87bccc768a1e]::foo`
+                        || format!("{:#}", demangled) == name
+                    {
+                        Some(key)
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            if hits.len() > 1 {
+                error!("multiple matches for `{}`: {:?}", name, hits);
+                None
+            } else {
+                hits.first().map(|key| indices[*key])
+            }
+        })
+    }
+}
+
+// computes the maximum stack usage of every node; returns the cycles found in the graph
+fn compute_max(g: &mut Graph<Node, Call>) -> Vec<Vec<NodeIndex>> {
+    let mut cycles = vec![];
+    if algo::is_cyclic_directed(&*g) {
+        let sccs = algo::kosaraju_scc(&*g);
+
+        // iterate over SCCs (Strongly Connected Components) in reverse topological order
+        for scc in &sccs {
+            let first = scc[0];
+
+            let is_a_cycle = scc.len() > 1
+                || g.neighbors_directed(first, Direction::Outgoing)
+                    .any(|n| n == first);
+
+            if is_a_cycle {
+                cycles.push(scc.clone());
+
+                // a cycle closed only by tail calls doesn't grow the stack: each call replaces the
+                // stack frame of the caller with the one of the callee
+                let tail_calls_only = scc.iter().all(|inode| {
+                    g.edges(*inode)
+                        .filter(|edge| scc.contains(&edge.target()))
+                        .all(|edge| *edge.weight() == Call::Tail)
+                });
+
+                if tail_calls_only {
+                    let members = scc
+                        .iter()
+                        .map(|inode| (*inode, frame_max(g, *inode, scc)))
+                        .collect::<Vec<_>>();
+                    let scc_max =
+                        max_of(members.iter().map(|(_, (max, _))| *max)).expect("UNREACHABLE");
+                    // the member of the SCC that uses the most stack
+                    let (owner, (_, exit)) = *members
+                        .iter()
+                        .max_by_key(|(_, (max, _))| max.bytes())
+                        .expect("UNREACHABLE");
+
+                    for inode in scc {
+                        g[*inode].max = Some(scc_max);
+                    }
+
+                    g[owner].worst_callee = exit;
+                    route_to(g, scc, owner);
+
+                    continue;
+                }
+
+                let scc_local = if let Some(depth) = recursion_depth(g, scc) {
+                    // each trip around the cycle uses, at most, the stack of all its members
+                    match scc
+                        .iter()
+                        .fold(Max::Exact(0), |acc, node| acc + g[*node].local)
+                    {
+                        Max::Exact(n) => Max::Exact(depth * n),
+                        Max::LowerBound(n) => Max::LowerBound(depth * n),
+                        Max::Unbounded => Max::Unbounded,
+                    }
+                } else {
+                    let scc_local =
+                        max_of(scc.iter().map(|node| g[*node].local.into())).expect("UNREACHABLE");
+
+                    // the cumulative stack usage is only exact when all nodes do *not* use the
+                    // stack
+                    match scc_local {
+                        Max::Exact(n) if n != 0 => Max::LowerBound(n),
+                        _ => scc_local,
+                    }
+                };
+
+                // we only care about the neighbors of the SCC
+                let neighbors = scc
+                    .iter()
+                    .flat_map(|inode| g.neighbors_directed(*inode, Direction::Outgoing))
+                    .filter(|neighbor| !scc.contains(neighbor))
+                    .collect::<Vec<_>>();
+                let neighbors_max = max_of(
+                    neighbors
+                        .iter()
+                        .map(|neighbor| g[*neighbor].max.expect("UNREACHABLE")),
+                );
+
+                for inode in scc {
+                    let node = &mut g[*inode];
+                    if let Some(max) = neighbors_max {
+                        node.max = Some(max + scc_local);
+                    } else {
+                        node.max = Some(scc_local);
+                    }
+                }
+
+                if let Some(exit) = worst_of(g, neighbors.into_iter()) {
+                    // the member of the SCC that calls `exit`
+                    let owner = *scc
+                        .iter()
+                        .find(|inode| g.contains_edge(**inode, exit))
+                        .expect("UNREACHABLE");
+                    g[owner].worst_callee = Some(exit);
+
+                    route_to(g, scc, owner);
+                }
+            } else {
+                let (max, worst_callee) = frame_max(g, first, &[]);
+
+                let node = &mut g[first];
+                node.max = Some(max);
+                node.worst_callee = worst_callee;
+            }
+        }
+    } else {
+        // compute max stack usage
+        let mut topo = Topo::new(Reversed(&*g));
+        while let Some(node) = topo.next(Reversed(&*g)) {
+            debug_assert!(g[node].max.is_none());
+
+            let (max, worst_callee) = frame_max(g, node, &[]);
+
+            g[node].max = Some(max);
+            g[node].worst_callee = worst_callee;
+        }
+    }
+
+    cycles
+}
+
+// the maximum stack usage of each of the `starts`, largest first; start points without stack usage
+// information are left out. `compute_max` must have been called beforehand
+fn starts_by_max(g: &Graph<Node, Call>, starts: &[NodeIndex]) -> Vec<(NodeIndex, Max)> {
+    let mut maxes = starts
+        .iter()
+        .filter_map(|start| g[*start].max.map(|max| (*start, max)))
+        .collect::<Vec<_>>();
+    // NOTE stable sort; ties remain in the order the start points were given
+    maxes.sort_by_key(|(_, max)| cmp::Reverse(max.bytes()));
+    maxes
+}
+
+// the maximum stack usage of `caller` and the callee that contributes the most to it; calls into
+// `skip` are ignored
+fn frame_max(
+    g: &Graph<Node, Call>,
+    caller: NodeIndex,
+    skip: &[NodeIndex],
+) -> (Max, Option<NodeIndex>) {
+    let local = g[caller].local;
+    let calls = g
+        .edges(caller)
+        .filter(|edge| !skip.contains(&edge.target()))
+        .map(|edge| {
+            let callee = g[edge.target()].max.expect("UNREACHABLE");
+            let max = match edge.weight() {
+                Call::Normal => callee + local,
+                // the caller's stack frame is gone by the time the callee runs
+                Call::Tail => callee,
+            };
+            (edge.target(), max)
+        })
+        .collect::<Vec<_>>();
+
+    let worst_callee = calls
+        .iter()
+        .max_by_key(|(_, max)| max.bytes())
+        .map(|(callee, _)| *callee);
+    let calls_max = max_of(calls.into_iter().map(|(_, max)| max));
+
+    (
+        calls_max
+            .map(|calls_max| max(calls_max, local.into()))
+            .unwrap_or_else(|| local.into()),
+        worst_callee,
+    )
+}
+
+// makes the other members of the SCC reach `owner` through the shortest path that stays within the
+// SCC
+fn route_to(g: &mut Graph<Node, Call>, scc: &[NodeIndex], owner: NodeIndex) {
+    let mut visited = HashSet::new();
+    visited.insert(owner);
+    let mut queue = VecDeque::new();
+    queue.push_back(owner);
+    while let Some(callee) = queue.pop_front() {
+        let callers = g
+            .neighbors_directed(callee, Direction::Incoming)
+            .filter(|caller| scc.contains(caller))
+            .collect::<Vec<_>>();
+
+        for caller in callers {
+            if visited.insert(caller) {
+                g[caller].worst_callee = Some(callee);
+                queue.push_back(caller);
+            }
+        }
+    }
+}
+
+// the maximum recursion depth of a cycle; the largest depth annotated on any of its members
+fn recursion_depth(g: &Graph<Node, Call>, scc: &[NodeIndex]) -> Option<u64> {
+    // the functions are on the stack at least once, even if they never recurse
+    scc.iter()
+        .filter_map(|node| g[*node].max_recursion)
+        .max()
+        .map(|depth| cmp::max(depth, 1))
+}
+
+fn dot<W>(
+    g: &Graph<Node, Call>,
+    cycles: &[Vec<NodeIndex>],
+    roots: &[NodeIndex],
+    mut stdout: W,
+) -> io::Result<()>
+where
+    W: io::Write,
+{
+    writeln!(stdout, "digraph {{")?;
+    writeln!(stdout, "    node [fontname={} shape=box]", FONT)?;
+
+    for (i, node) in g.raw_nodes().iter().enumerate() {
+        let node = &node.weight;
+
+        write!(stdout, "    {} [label=\"", i,)?;
+
+        let mut escaper = Escaper::new(&mut stdout);
+        escaper.write_str(&node.demangled).ok();
+        escaper.error?;
+
+        if let Some(max) = node.max {
+            write!(stdout, "\\nmax {}", max)?;
+        }
+
+        write!(stdout, "\\nlocal = {}\"", node.local,)?;
+
+        if node.dashed {
+            write!(stdout, " style=dashed")?;
+        }
+
+        if let Some(location) = &node.location {
+            write!(stdout, " ")?;
+            write_location(&mut stdout, location)?;
+        }
+
+        writeln!(stdout, "]")?;
+    }
+
+    // highlight the worst-case call path of the start point
+    let mut worst_edges = HashSet::new();
+    for root in roots {
+        let path = worst_path(g, *root);
+        worst_edges.extend(path.windows(2).map(|pair| (pair[0], pair[1])));
+    }
+
+    for edge in g.raw_edges() {
+        write!(
+            stdout,
+            "    {} -> {}",
+            edge.source().index(),
+            edge.target().index()
+        )?;
+
+        let worst = worst_edges.contains(&(edge.source(), edge.target()));
+        let style = match (edge.weight, worst) {
+            (Call::Normal, false) => "",
+            (Call::Normal, true) => "color=red style=bold",
+            // tail calls don't grow the stack
+            (Call::Tail, false) => "style=dashed arrowhead=empty",
+            (Call::Tail, true) => "color=red style=\"bold,dashed\" arrowhead=empty",
+        };
+        let call_site = g[edge.source()].call_sites.get(&edge.target());
+
+        if !style.is_empty() || call_site.is_some() {
+            write!(stdout, " [{}", style)?;
+
+            if let Some(location) = call_site {
+                if !style.is_empty() {
+                    write!(stdout, " ")?;
+                }
+                write_location(&mut stdout, location)?;
+            }
+
+            write!(stdout, "]")?;
+        }
+
+        writeln!(stdout)?;
+    }
+
+    for (i, cycle) in cycles.iter().enumerate() {
+        writeln!(stdout, "\n    subgraph cluster_{} {{", i)?;
+        writeln!(stdout, "        style=dashed")?;
+        writeln!(stdout, "        fontname={}", FONT)?;
+        if let Some(depth) = recursion_depth(g, cycle) {
+            writeln!(stdout, "        label=\"SCC{} (depth <= {})\"", i, depth)?;
+        } else {
+            writeln!(stdout, "        label=\"SCC{}\"", i)?;
+        }
+
+        for node in cycle {
+            writeln!(stdout, "        {}", node.index())?;
+        }
+
+        writeln!(stdout, "    }}")?;
+    }
+
+    writeln!(stdout, "}}")
+}
+
+// writes the `tooltip=".." URL=".."` attributes of a node or edge
+fn write_location<W>(w: &mut W, location: &Location) -> io::Result<()>
+where
+    W: io::Write,
+{
+    write!(w, "tooltip=\"")?;
+    let mut escaper = Escaper::new(&mut *w);
+    write!(escaper, "{}", location).ok();
+    escaper.error?;
+    write!(w, "\"")?;
+
+    if Path::new(&location.file).is_absolute() {
+        write!(w, " URL=\"file://")?;
+        let mut escaper = Escaper::new(&mut *w);
+        escaper.write_str(&location.file).ok();
+        escaper.error?;
+        write!(w, "\"")?;
+    }
+
+    Ok(())
+}
+
+pub(crate) struct Escaper<W>
+where
+    W: io::Write,
+{
+    writer: W,
+    pub(crate) error: io::Result<()>,
+}
+
+impl<W> Escaper<W>
+where
+    W: io::Write,
+{
+    pub(crate) fn new(writer: W) -> Self {
+        Escaper {
+            writer,
+            error: Ok(()),
+        }
+    }
+}
+
+impl<W> fmt::Write for Escaper<W>
+where
+    W: io::Write,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.write_char(c)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        match (|| -> io::Result<()> {
+            match c {
+                '"' => write!(self.writer, "\\")?,
+                _ => {}
+            }
+
+            write!(self.writer, "{}", c)
+        })() {
+            Err(e) => {
+                self.error = Err(e);
+
+                Err(fmt::Error)
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+}
+
+/// A function in the call graph
+#[derive(Clone)]
+pub struct Node<'a> {
+    pub(crate) name: Cow<'a, str>,
+    // demangled name, possibly with its hash removed
+    pub(crate) demangled: String,
+    pub(crate) local: Local,
+    pub(crate) max: Option<Max>,
+    // the callee that contributes the most to `max`
+    pub(crate) worst_callee: Option<NodeIndex>,
+    pub(crate) dashed: bool,
+    // maximum recursion depth of the cycle this function is part of; set by an annotation
+    pub(crate) max_recursion: Option<u64>,
+    // where the function is defined; requires debug info
+    pub(crate) location: Option<Location>,
+    // callee -> where (the first call to) the callee appears in the source code
+    pub(crate) call_sites: BTreeMap<NodeIndex, Location>,
+    // the stack slots allocated in the LLVM IR
+    pub(crate) variables: Vec<Variable>,
+}
+
+impl<'a> Node<'a> {
+    /// Symbol name, or a signature for fictitious nodes
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Demangled name; the hash is removed when that doesn't make the name ambiguous
+    pub fn demangled(&self) -> &str {
+        &self.demangled
+    }
+
+    /// Stack usage of the function itself
+    pub fn local(&self) -> Local {
+        self.local
+    }
+
+    /// Maximum stack usage of the function, including the functions it calls
+    ///
+    /// `None` if the program has no stack usage information
+    pub fn max(&self) -> Option<Max> {
+        self.max
+    }
+
+    /// Whether this node represents an indirect function call or dynamic dispatch rather than a
+    /// real function
+    pub fn is_fictitious(&self) -> bool {
+        self.dashed
+    }
+
+    /// Maximum recursion depth of the cycle this function is part of, if one was annotated
+    pub fn max_recursion(&self) -> Option<u64> {
+        self.max_recursion
+    }
+
+    /// Where the function is defined, if the program was compiled with debug info
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+
+    /// The local variables and compiler temporaries that the LLVM IR allocates in the function's
+    /// stack frame
+    pub fn variables(&self) -> &[Variable] {
+        &self.variables
+    }
+
+    /// Writes a breakdown of the function's stack frame, largest variables first
+    pub fn explain<W>(&self, w: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        explain::write(self, w)
+    }
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn Node<'a, S>(name: S, stack: Option<u64>, dashed: bool) -> Node<'a>
+where
+    S: Into<Cow<'a, str>>,
+{
+    let name = name.into();
+    Node {
+        demangled: rustc_demangle::demangle(&name).to_string(),
+        name,
+        local: stack.map(Local::Exact).unwrap_or(Local::Unknown),
+        max: None,
+        worst_callee: None,
+        dashed,
+        max_recursion: None,
+        location: None,
+        call_sites: BTreeMap::new(),
+        variables: vec![],
+    }
+}
+
+// a graph made of `functions`, given as `(name, local stack usage)`, that make the normal `calls`,
+// given as `(caller, callee)`; returns the node indices of `functions` in order
+#[cfg(test)]
+pub(crate) fn graph<'a, const N: usize>(
+    functions: [(&'a str, Option<u64>); N],
+    calls: &[(&str, &str)],
+) -> (Graph<Node<'a>, Call>, [NodeIndex; N]) {
+    let mut g = Graph::new();
+    let nodes = functions.map(|(name, stack)| g.add_node(Node(name, stack, false)));
+    let index = |name| {
+        nodes[functions
+            .iter()
+            .position(|f| f.0 == name)
+            .expect("UNREACHABLE")]
+    };
+    for (caller, callee) in calls {
+        g.add_edge(index(*caller), index(*callee), Call::Normal);
+    }
+    (g, nodes)
+}
+
+#[cfg(test)]
+impl<'a> CallGraph<'a> {
+    // a call graph that's not backed by an ELF file; computes the maximum stack usage of `g`
+    pub(crate) fn from_graph(mut g: Graph<Node<'a>, Call>) -> Self {
+        let cycles = compute_max(&mut g);
+        CallGraph {
+            indices: g
+                .node_indices()
+                .map(|inode| (Cow::Owned(g[inode].name.to_string()), inode))
+                .collect(),
+            g,
+            cycles,
+            addr2name: BTreeMap::new(),
+            elf: &[],
+            target: "x86_64-unknown-linux-gnu",
+            starts: vec![],
+            diagnostics: vec![],
+        }
+    }
+}
+
+/// How a function calls another function
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Call {
+    /// The callee's stack frame is pushed on top of the caller's (e.g. `BL`)
+    Normal,
+    /// The caller's stack frame is popped before jumping to the callee (e.g. `B`)
+    Tail,
+}
+
+/// A position in the source code
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Location {
+    file: String,
+    line: u32,
+    column: Option<u32>,
+}
+
+impl Location {
+    /// Path to the source file
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// Line number, starting at 1
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Column number, starting at 1; `None` if unknown
+    pub fn column(&self) -> Option<u32> {
+        self.column
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A problem with a function that the analysis recovered from, e.g. machine code it couldn't
+/// decode
+#[derive(Clone, Debug)]
+pub struct Diagnostic<'a> {
+    function: &'a str,
+    address: Option<u64>,
+    message: String,
+}
+
+impl<'a> Diagnostic<'a> {
+    /// Symbol name of the function
+    pub fn function(&self) -> &'a str {
+        self.function
+    }
+
+    /// Address of the function; `None` if it's not known
+    pub fn address(&self) -> Option<u64> {
+        self.address
+    }
+
+    /// What went wrong
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}`", self.function)?;
+
+        if let Some(address) = self.address {
+            write!(f, " ({:#010x})", address)?;
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
+
+// logs a problem that the analysis recovered from and keeps it for `CallGraph::diagnostics`
+fn diagnose<'a>(
+    diagnostics: &mut Vec<Diagnostic<'a>>,
+    function: &'a str,
+    address: Option<u64>,
+    message: String,
+) {
+    let diagnostic = Diagnostic {
+        function,
+        address,
+        message,
+    };
+    error!("{}", diagnostic);
+    diagnostics.push(diagnostic);
+}
+
+// the address of a function, for diagnostics
+fn address_of(addr2name: &BTreeMap<u64, &str>, function: &str) -> Option<u64> {
+    addr2name
+        .iter()
+        .find(|(_, name)| **name == function)
+        .map(|(address, _)| *address)
+}
+
+/// A local variable or compiler temporary in a function's stack frame
+#[derive(Clone, Debug)]
+pub struct Variable {
+    name: String,
+    ty: Option<String>,
+    size: Option<u64>,
+    shape: Shape,
+    dynamic: bool,
+    location: Option<Location>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Shape {
+    Array,
+    // a struct, enum or union
+    Struct,
+    Other,
+}
+
+impl Variable {
+    /// Name in the source code or, without debug info, in the LLVM IR (e.g. `%_5`)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Type in the source code; requires debug info
+    pub fn ty(&self) -> Option<&str> {
+        self.ty.as_deref()
+    }
+
+    /// Size in bytes; `None` if unknown or only known at run time
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Whether the number of elements in the stack slot is only known at run time
+    pub fn is_dynamic(&self) -> bool {
+        self.dynamic
+    }
+
+    /// Whether the variable is an array
+    pub fn is_array(&self) -> bool {
+        self.shape == Shape::Array
+    }
+
+    /// Whether the variable is a struct, enum or union stored by value
+    pub fn is_struct(&self) -> bool {
+        self.shape == Shape::Struct
+    }
+
+    /// Where the variable is declared; requires debug info
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+}
+
+// maps debug info metadata nodes (`!N`) to source locations
+#[derive(Default)]
+struct Locator<'a> {
+    nodes: HashMap<u32, DebugInfo<'a>>,
+}
+
+impl<'a> Locator<'a> {
+    // where the function described by a `DISubprogram` is defined
+    fn function(&self, subprogram: u32) -> Option<Location> {
+        match self.nodes.get(&subprogram)? {
+            DebugInfo::Scope {
+                file: Some(file),
+                line: Some(line),
+            } => Some(Location {
+                file: self.file(*file)?,
+                line: *line,
+                column: None,
+            }),
+
+            _ => None,
+        }
+    }
+
+    // where the statement with debug location `dbg` appears in the source code
+    fn statement(&self, mut dbg: u32) -> Option<Location> {
+        loop {
+            match self.nodes.get(&dbg)? {
+                // the statement was inlined into another function; we report the location of the
+                // outermost call so that it points into the function that contains the statement
+                DebugInfo::Location {
+                    inlined_at: Some(at),
+                    ..
+                } => dbg = *at,
+
+                DebugInfo::Location {
+                    line,
+                    column,
+                    scope,
+                    inlined_at: None,
+                } => {
+                    let file = match self.nodes.get(scope)? {
+                        DebugInfo::Scope {
+                            file: Some(file), ..
+                        } => *file,
+                        _ => return None,
+                    };
+
+                    return Some(Location {
+                        file: self.file(file)?,
+                        line: *line,
+                        // LLVM uses 0 when the column is unknown
+                        column: Some(*column).filter(|column| *column != 0),
+                    });
+                }
+
+                _ => return None,
+            }
+        }
+    }
+
+    // the variable (or temporary) that lives in the stack slot `alloca`
+    fn variable(&self, alloca: &Alloca, ptr_size: u64) -> Variable {
+        let size = alloca
+            .count
+            .and_then(|count| alloca.ty.size(ptr_size).map(|size| size * count));
+        let shape = match &alloca.ty {
+            // NOTE with opaque pointers rustc allocates everything as byte arrays
+            Type::Array(_, ty) if **ty != Type::Integer(8) => Shape::Array,
+            Type::Alias(_) | Type::PackedStruct(_) | Type::Struct(_) => Shape::Struct,
+            _ => Shape::Other,
+        };
+
+        // NOTE several variables can share a slot, e.g. the arguments of inlined functions
+        let mut variables = alloca
+            .variables
+            .iter()
+            .filter_map(|variable| match self.nodes.get(variable) {
+                Some(DebugInfo::Variable {
+                    name,
+                    file,
+                    line,
+                    ty,
+                }) => Some((*name, *file, *line, *ty)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        variables.dedup_by_key(|(name, ..)| *name);
+
+        if let Some((_, file, line, ty)) = variables.first() {
+            Variable {
+                name: variables
+                    .iter()
+                    .map(|(name, ..)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                ty: ty.and_then(|ty| self.type_name(ty)),
+                size,
+                shape: ty.map(|ty| self.shape(ty)).unwrap_or(shape),
+                dynamic: alloca.count.is_none(),
+                location: file.zip(*line).and_then(|(file, line)| {
+                    Some(Location {
+                        file: self.file(file)?,
+                        line,
+                        column: None,
+                    })
+                }),
+            }
+        } else {
+            Variable {
+                name: alloca.name.to_owned(),
+                ty: None,
+                size,
+                shape,
+                dynamic: alloca.count.is_none(),
+                location: None,
+            }
+        }
+    }
+
+    // `[u8; 1024]`
+    fn type_name(&self, ty: u32) -> Option<String> {
+        match self.nodes.get(&ty)? {
+            DebugInfo::Type {
+                name: Some(name), ..
+            } => Some((*name).to_owned()),
+
+            DebugInfo::Type {
+                tag: Some("DW_TAG_array_type"),
+                base: Some(base),
+                size: Some(size),
+                ..
+            } => {
+                let element = self.type_name(*base)?;
+                let count = match self.nodes.get(base)? {
+                    DebugInfo::Type {
+                        size: Some(element_size),
+                        ..
+                    } if *element_size != 0 => size / element_size,
+                    _ => return None,
+                };
+                Some(format!("[{}; {}]", element, count))
+            }
+
+            _ => None,
+        }
+    }
+
+    fn shape(&self, ty: u32) -> Shape {
+        match self.nodes.get(&ty) {
+            Some(DebugInfo::Type { tag: Some(tag), .. }) => match *tag {
+                "DW_TAG_array_type" => Shape::Array,
+                "DW_TAG_structure_type" | "DW_TAG_union_type" | "DW_TAG_class_type" => {
+                    Shape::Struct
+                }
+                _ => Shape::Other,
+            },
+            _ => Shape::Other,
+        }
+    }
+
+    fn file(&self, file: u32) -> Option<String> {
+        match self.nodes.get(&file)? {
+            DebugInfo::File {
+                filename,
+                directory,
+            } => Some(
+                Path::new(directory)
+                    .join(filename)
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+
+            _ => None,
+        }
+    }
+}
+
+/// Local stack usage
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum Local {
+    /// Exactly this many bytes
+    Exact(u64),
+    /// Unknown
+    Unknown,
+    /// Not statically bounded; the function adjusts the stack pointer by a run-time value
+    Unbounded,
+}
+
+impl fmt::Display for Local {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Local::Exact(n) => write!(f, "{}", n),
+            Local::Unknown => f.write_str("?"),
+            Local::Unbounded => f.write_str("unbounded"),
+        }
+    }
+}
+
+impl Into<Max> for Local {
+    fn into(self) -> Max {
+        match self {
+            Local::Exact(n) => Max::Exact(n),
+            Local::Unknown => Max::LowerBound(0),
+            Local::Unbounded => Max::Unbounded,
+        }
+    }
+}
+
+/// Maximum stack usage
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum Max {
+    /// Exactly this many bytes
+    Exact(u64),
+    /// At least this many bytes
+    LowerBound(u64),
+    /// Not statically bounded; some function in the call chain has a dynamically sized stack
+    /// frame
+    Unbounded,
+}
+
+impl ops::Add<Local> for Max {
+    type Output = Max;
+
+    fn add(self, rhs: Local) -> Max {
+        match (self, rhs) {
+            (Max::Exact(lhs), Local::Exact(rhs)) => Max::Exact(lhs + rhs),
+            (Max::Exact(lhs), Local::Unknown) => Max::LowerBound(lhs),
+            (Max::LowerBound(lhs), Local::Exact(rhs)) => Max::LowerBound(lhs + rhs),
+            (Max::LowerBound(lhs), Local::Unknown) => Max::LowerBound(lhs),
+            (Max::Unbounded, _) | (_, Local::Unbounded) => Max::Unbounded,
+        }
+    }
+}
+
+impl ops::Add<Max> for Max {
+    type Output = Max;
+
+    fn add(self, rhs: Max) -> Max {
+        match (self, rhs) {
+            (Max::Exact(lhs), Max::Exact(rhs)) => Max::Exact(lhs + rhs),
+            (Max::Exact(lhs), Max::LowerBound(rhs)) => Max::LowerBound(lhs + rhs),
+            (Max::LowerBound(lhs), Max::Exact(rhs)) => Max::LowerBound(lhs + rhs),
+            (Max::LowerBound(lhs), Max::LowerBound(rhs)) => Max::LowerBound(lhs + rhs),
+            (Max::Unbounded, _) | (_, Max::Unbounded) => Max::Unbounded,
+        }
+    }
+}
+
+impl Max {
+    /// The number of bytes, regardless of whether this is an exact value or a lower bound
+    ///
+    /// `Unbounded` is reported as `u64::MAX` so that it compares greater than any other value
+    pub fn bytes(self) -> u64 {
+        match self {
+            Max::Exact(n) | Max::LowerBound(n) => n,
+            Max::Unbounded => u64::MAX,
+        }
+    }
+
+    /// Formats the value for reports: `= N bytes`, `>= N bytes` or `unbounded`
+    pub fn in_bytes(self) -> String {
+        match self {
+            Max::Unbounded => self.to_string(),
+            _ => format!("{} bytes", self),
+        }
+    }
+}
+
+fn max_of(mut iter: impl Iterator<Item = Max>) -> Option<Max> {
+    iter.next().map(|first| iter.fold(first, max))
+}
+
+pub(crate) fn max(lhs: Max, rhs: Max) -> Max {
+    match (lhs, rhs) {
+        (Max::Exact(lhs), Max::Exact(rhs)) => Max::Exact(cmp::max(lhs, rhs)),
+        (Max::Exact(lhs), Max::LowerBound(rhs)) => Max::LowerBound(cmp::max(lhs, rhs)),
+        (Max::LowerBound(lhs), Max::Exact(rhs)) => Max::LowerBound(cmp::max(lhs, rhs)),
+        (Max::LowerBound(lhs), Max::LowerBound(rhs)) => Max::LowerBound(cmp::max(lhs, rhs)),
+        (Max::Unbounded, _) | (_, Max::Unbounded) => Max::Unbounded,
+    }
+}
+
+impl fmt::Display for Max {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Max::Exact(n) => write!(f, "= {}", n),
+            Max::LowerBound(n) => write!(f, ">= {}", n),
+            Max::Unbounded => f.write_str("unbounded"),
+        }
+    }
+}
+
+// the neighbor with the largest maximum stack usage
+fn worst_of(
+    g: &Graph<Node, Call>,
+    neighbors: impl Iterator<Item = NodeIndex>,
+) -> Option<NodeIndex> {
+    neighbors.max_by_key(|neighbor| g[*neighbor].max.expect("UNREACHABLE").bytes())
+}
+
+// the chain of calls, starting at `root`, that produces its maximum stack usage
+fn worst_path(g: &Graph<Node, Call>, root: NodeIndex) -> Vec<NodeIndex> {
+    let mut path = vec![root];
+    let mut seen = HashSet::new();
+    seen.insert(root);
+
+    let mut node = root;
+    while let Some(callee) = g[node].worst_callee {
+        if !seen.insert(callee) {
+            break;
+        }
+
+        path.push(callee);
+        node = callee;
+    }
+
+    path
+}
+
+// prints the worst-case call path of `root` to stderr, one function per line
+pub(crate) fn print_worst_path(g: &Graph<Node, Call>, root: NodeIndex, cycles: &[Vec<NodeIndex>]) {
+    for inode in worst_path(g, root) {
+        let node = &g[inode];
+        let in_cycle = cycles.iter().any(|cycle| cycle.contains(&inode));
+
+        eprintln!(
+            "  {:>9} {}{}{}",
+            node.local.to_string(),
+            node.demangled,
+            if in_cycle { " (cycle)" } else { "" },
+            node.location
+                .as_ref()
+                .map(|location| format!(" at {}", location))
+                .unwrap_or_default()
+        );
+    }
+}
+
+// `i1 (ptr, ptr)`; the opaque pointer version of `ArgumentV1.formatter`
+fn is_opaque_formatter(sig: &FnSig) -> bool {
+    match (&sig.inputs[..], sig.output.as_ref()) {
+        ([Type::OpaquePointer, Type::OpaquePointer], Some(output)) => **output == Type::Integer(1),
+        _ => false,
+    }
+}
+
+// used to track indirect function calls (`fn` pointers)
+#[derive(Default)]
+struct Indirect {
+    called: bool,
+    callers: HashSet<NodeIndex>,
+    callees: HashSet<NodeIndex>,
+}
+
+// used to track dynamic dispatch (trait objects)
+#[derive(Debug, Default)]
+struct Dynamic {
+    called: bool,
+    // callers that use an unknown vtable slot
+    callers: HashSet<NodeIndex>,
+    // vtable offset -> callers
+    slots: BTreeMap<u64, HashSet<NodeIndex>>,
+    callees: HashSet<NodeIndex>,
+}
+
+// removes hashes like `::hfc5adc5d79855638`, if present
+pub(crate) fn dehash(demangled: &str) -> Option<&str> {
+    const HASH_LENGTH: usize = 19;
+
+    let len = demangled.as_bytes().len();
+    if len > HASH_LENGTH {
+        if demangled
+            .get(len - HASH_LENGTH..)
+            .map(|hash| hash.starts_with("::h"))
+            .unwrap_or(false)
+        {
+            Some(&demangled[..len - HASH_LENGTH])
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Target {
+    Other,
+    Thumbv6m,
+    Thumbv7m,
+}
+
+impl Target {
+    fn is_thumb(&self) -> bool {
+        match *self {
+            Target::Thumbv6m | Target::Thumbv7m => true,
+            Target::Other => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::graph::DiGraph;
+
+    use super::{Annotations, Call, CallGraph, DebugInfo, Local, Locator, Max, Node, Pattern};
+
+    #[test]
+    fn queries() {
+        let (g, _) = crate::graph(
+            [
+                ("main", Some(8)),
+                ("foo", Some(16)),
+                ("bar", Some(0)),
+                ("baz", Some(4)),
+            ],
+            &[
+                ("main", "foo"),
+                ("main", "bar"),
+                ("bar", "baz"),
+                ("baz", "bar"),
+            ],
+        );
+        let cg = CallGraph::from_graph(g);
+
+        assert_eq!(cg.max_stack("foo"), Some(Max::Exact(16)));
+        assert_eq!(cg.max_stack("main"), Some(Max::LowerBound(24)));
+        assert_eq!(cg.max_stack("bar"), Some(Max::LowerBound(4)));
+        assert_eq!(cg.max_stack("quux"), None);
+
+        let mut callees = cg
+            .callees("main")
+            .unwrap()
+            .iter()
+            .map(|node| node.name())
+            .collect::<Vec<_>>();
+        callees.sort();
+        assert_eq!(callees, ["bar", "foo"]);
+
+        let callers = cg.callers("bar").unwrap();
+        assert_eq!(callers.len(), 2);
+
+        let cycles = cg.cycles();
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].len(), 2);
+
+        let filtered = cg.filter("bar").unwrap();
+        assert_eq!(filtered.nodes().count(), 2);
+        assert_eq!(filtered.max_stack("bar"), Some(Max::LowerBound(4)));
+        assert_eq!(filtered.start().map(|node| node.name()), Some("bar"));
+
+        let filtered = cg
+            .filter_all(&[
+                Pattern::glob("foo"),
+                Pattern::glob("ba?"),
+                Pattern::glob("quux"),
+            ])
+            .unwrap();
+        assert_eq!(filtered.nodes().count(), 3);
+        let starts = filtered
+            .starts()
+            .iter()
+            .map(|node| node.name())
+            .collect::<Vec<_>>();
+        assert_eq!(starts, ["foo", "bar", "baz"]);
+        assert!(cg.filter_all(&[Pattern::glob("quux")]).is_none());
+
+        let filtered = cg.filter_roots();
+        assert_eq!(filtered.nodes().count(), 4);
+        assert_eq!(filtered.start().map(|node| node.name()), Some("main"));
+
+        // `baz` is only reachable through `bar`
+        let shown = cg.hide(&[Pattern::glob("b?r")]);
+        assert_eq!(shown.nodes().count(), 2);
+        assert_eq!(shown.max_stack("main"), Some(Max::LowerBound(24)));
+    }
+
+    #[test]
+    fn worst_path() {
+        let (g, _) = crate::graph(
+            [
+                ("main", Some(8)),
+                ("foo", Some(16)),
+                ("bar", Some(0)),
+                ("baz", Some(32)),
+            ],
+            &[("main", "foo"), ("main", "bar"), ("bar", "baz")],
+        );
+        let cg = CallGraph::from_graph(g);
+
+        let path = cg
+            .worst_path("main")
+            .unwrap()
+            .iter()
+            .map(|node| node.name())
+            .collect::<Vec<_>>();
+        assert_eq!(path, ["main", "bar", "baz"]);
+        assert_eq!(cg.max_stack("main"), Some(Max::Exact(40)));
+        assert!(cg.worst_path("quux").is_none());
+
+        // only the edges on the worst-case path of the start point are highlighted
+        let mut dot = vec![];
+        cg.filter_roots().dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        let highlighted = dot
+            .lines()
+            .filter(|line| line.contains("color=red"))
+            .map(|line| line.trim().split(' ').take(3).collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>();
+        assert_eq!(highlighted, ["0 -> 2", "2 -> 3"]);
+    }
+
+    #[test]
+    fn max_recursion() {
+        let mut g = DiGraph::new();
+        let main = g.add_node(Node("main", Some(8), false));
+        let bar = g.add_node(Node("bar", Some(16), false));
+        let baz = g.add_node(Node("baz", Some(4), false));
+        let quux = g.add_node(Node("quux", Some(32), false));
+        g.add_edge(main, bar, Call::Normal);
+        g.add_edge(bar, baz, Call::Normal);
+        g.add_edge(baz, bar, Call::Normal);
+        g.add_edge(baz, quux, Call::Normal);
+        g[baz].max_recursion = Some(3);
+
+        super::compute_max(&mut g);
+
+        // 3 * (16 + 4) + 32
+        assert_eq!(g[bar].max, Some(Max::Exact(92)));
+        assert_eq!(g[main].max, Some(Max::Exact(100)));
+    }
+
+    #[test]
+    fn unbounded() {
+        let mut g = DiGraph::new();
+        let main = g.add_node(Node("main", Some(8), false));
+        let foo = g.add_node(Node("foo", Some(16), false));
+        let bar = g.add_node(Node("bar", None, false));
+        let baz = g.add_node(Node("baz", Some(4), false));
+        g.add_edge(main, foo, Call::Normal);
+        g.add_edge(main, baz, Call::Normal);
+        g.add_edge(foo, bar, Call::Normal);
+        g[bar].local = Local::Unbounded;
+
+        super::compute_max(&mut g);
+
+        assert_eq!(g[bar].max, Some(Max::Unbounded));
+        assert_eq!(g[foo].max, Some(Max::Unbounded));
+        assert_eq!(g[main].max, Some(Max::Unbounded));
+        assert_eq!(g[baz].max, Some(Max::Exact(4)));
+        assert_eq!(super::worst_path(&g, main), [main, foo, bar]);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut diagnostics = vec![];
+        super::diagnose(
+            &mut diagnostics,
+            "foo",
+            Some(0x800),
+            "unhandled LLVM intrinsic `llvm.foo`".to_string(),
+        );
+        super::diagnose(
+            &mut diagnostics,
+            "bar",
+            None,
+            "callee `baz` is unknown".to_string(),
+        );
+
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "`foo` (0x00000800): unhandled LLVM intrinsic `llvm.foo`"
+        );
+        assert_eq!(diagnostics[1].to_string(), "`bar`: callee `baz` is unknown");
+        assert_eq!(diagnostics[1].address(), None);
+    }
+
+    #[test]
+    fn annotations_resolve_diagnostics() {
+        let (g, _) = crate::graph(
+            [("main", Some(8)), ("foo", None), ("bar", None), ("?", None)],
+            &[("main", "foo"), ("main", "bar"), ("foo", "?")],
+        );
+
+        let mut diagnostics = vec![];
+        for function in &["foo", "bar"] {
+            super::diagnose(&mut diagnostics, function, None, "oops".to_string());
+        }
+        let mut cg = CallGraph::from_graph(g);
+        cg.diagnostics = diagnostics;
+
+        // `bar` still has an unknown stack usage
+        let annotations: Annotations = toml::from_str(
+            r#"
+[functions.foo]
+calls = []
+stack = 16
+
+[functions.bar]
+calls = []
+"#,
+        )
+        .unwrap();
+        cg.annotate(&annotations);
+
+        let functions = cg
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.function())
+            .collect::<Vec<_>>();
+        assert_eq!(functions, ["bar"]);
+        assert_eq!(cg.max_stack("foo"), Some(Max::Exact(16)));
+    }
+
+    #[test]
+    fn starts() {
+        // `idle` is a root with unknown stack usage
+        let (g, _) = crate::graph(
+            [
+                ("main", Some(8)),
+                ("task", Some(32)),
+                ("idle", None),
+                ("shared", Some(16)),
+            ],
+            &[("main", "shared"), ("task", "shared")],
+        );
+        let cg = CallGraph::from_graph(g);
+
+        let maxes = |cg: &CallGraph| {
+            cg.starts_by_max()
+                .into_iter()
+                .map(|(start, max)| (start.name().to_string(), max))
+                .collect::<Vec<_>>()
+        };
+
+        // `--start-all-roots`; the shared callee counts towards both roots
+        let roots = cg.filter_roots();
+        assert_eq!(
+            maxes(&roots),
+            [
+                ("task".to_string(), Max::Exact(48)),
+                ("main".to_string(), Max::Exact(24)),
+                ("idle".to_string(), Max::LowerBound(0)),
+            ]
+        );
+
+        // `--start main --start task`
+        let filtered = cg
+            .filter_all(&[Pattern::glob("main"), Pattern::glob("task")])
+            .unwrap();
+        assert_eq!(filtered.nodes().count(), 3);
+        assert_eq!(
+            maxes(&filtered),
+            [
+                ("task".to_string(), Max::Exact(48)),
+                ("main".to_string(), Max::Exact(24))
+            ]
+        );
+    }
+
+    #[test]
+    fn tail_calls() {
+        let mut g = DiGraph::new();
+        let main = g.add_node(Node("main", Some(8), false));
+        let foo = g.add_node(Node("foo", Some(16), false));
+        let bar = g.add_node(Node("bar", Some(4), false));
+        let baz = g.add_node(Node("baz", Some(32), false));
+        g.add_edge(main, foo, Call::Normal);
+        g.add_edge(foo, bar, Call::Tail);
+        g.add_edge(bar, foo, Call::Tail);
+        g.add_edge(bar, baz, Call::Normal);
+
+        let cycles = super::compute_max(&mut g);
+
+        // the cycle is closed by tail calls so at most one of `foo` and `bar` is on the stack
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(g[foo].max, Some(Max::Exact(36)));
+        assert_eq!(g[main].max, Some(Max::Exact(44)));
+        assert_eq!(super::worst_path(&g, main), [main, foo, bar, baz]);
+
+        // a tail call to a function that uses more stack than the caller
+        let mut g = DiGraph::new();
+        let main = g.add_node(Node("main", Some(64), false));
+        let foo = g.add_node(Node("foo", Some(8), false));
+        g.add_edge(main, foo, Call::Tail);
+
+        super::compute_max(&mut g);
+
+        assert_eq!(g[main].max, Some(Max::Exact(64)));
+    }
+
+    #[test]
+    fn locations() {
+        let mut locator = Locator::default();
+        locator.nodes.insert(
+            1,
+            DebugInfo::File {
+                filename: "src/main.rs",
+                directory: "/home/user/app",
+            },
+        );
+        locator.nodes.insert(
+            2,
+            DebugInfo::File {
+                filename: "/rustc/library/core/src/fmt/mod.rs",
+                directory: "/rustc",
+            },
+        );
+        // `main` and `core::fmt::write`
+        locator.nodes.insert(
+            3,
+            DebugInfo::Scope {
+                file: Some(1),
+                line: Some(4),
+            },
+        );
+        locator.nodes.insert(
+            4,
+            DebugInfo::Scope {
+                file: Some(2),
+                line: Some(1200),
+            },
+        );
+        // a call in `core::fmt::write`, which was inlined into `main` at line 6
+        locator.nodes.insert(
+            5,
+            DebugInfo::Location {
+                line: 6,
+                column: 5,
+                scope: 3,
+                inlined_at: None,
+            },
+        );
+        locator.nodes.insert(
+            6,
+            DebugInfo::Location {
+                line: 1210,
+                column: 0,
+                scope: 4,
+                inlined_at: Some(5),
+            },
+        );
+
+        let main = locator.function(3).unwrap();
+        assert_eq!(main.to_string(), "/home/user/app/src/main.rs:4");
+        assert_eq!(main.column(), None);
+        assert_eq!(
+            locator.statement(6).unwrap().to_string(),
+            "/home/user/app/src/main.rs:6:5"
+        );
+        assert_eq!(
+            locator.function(4).unwrap().file(),
+            "/rustc/library/core/src/fmt/mod.rs"
+        );
+        assert!(locator.statement(3).is_none());
+    }
+}
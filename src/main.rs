@@ -1,44 +1,32 @@
 #![deny(warnings)]
 
-use core::{
-    cmp,
-    fmt::{self, Write as _},
-    ops, str,
-};
 use std::{
-    borrow::Cow,
-    collections::{BTreeMap, HashMap, HashSet},
-    env,
-    fs::{self, File},
-    io::{self, BufRead, BufReader, Read, Write},
-    path::PathBuf,
+    collections::HashMap,
+    env, fs,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
     process::{self, Command, Stdio},
+    slice,
     time::SystemTime,
 };
 
-use ar::Archive;
+use cargo_call_stack::{
+    annotations::Annotations,
+    baseline::{Baseline, Tolerance},
+    budget::{self, Verdict},
+    diff::{self, Analysis},
+    headroom,
+    pattern::Pattern,
+    preemption, rtic, Artifacts, CallGraph,
+};
 use cargo_project::{Artifact, Profile, Project};
-use clap::{crate_authors, crate_version, App, Arg};
+use clap::{crate_authors, crate_version, App, Arg, ArgGroup, ArgMatches, SubCommand};
 use env_logger::{Builder, Env};
 use failure::format_err;
 use filetime::FileTime;
 use log::{error, warn};
-use petgraph::{
-    algo,
-    graph::{DiGraph, NodeIndex},
-    visit::{Dfs, Reversed, Topo},
-    Direction, Graph,
-};
 use walkdir::WalkDir;
-use xmas_elf::{sections::SectionData, symbol_table::Entry, ElfFile};
 
-use crate::{
-    ir::{FnSig, Item, Stmt, Type},
-    thumb::Tag,
-};
-
-mod ir;
-mod thumb;
 mod wrapper;
 
 fn main() -> Result<(), failure::Error> {
@@ -51,9 +39,6 @@ fn main() -> Result<(), failure::Error> {
     }
 }
 
-// Font used in the dot graphs
-const FONT: &str = "monospace";
-
 #[allow(deprecated)]
 fn run() -> Result<i32, failure::Error> {
     if env::var_os("CARGO_CALL_STACK_RUSTC_WRAPPER").is_some() {
@@ -109,10 +94,286 @@ fn run() -> Result<i32, failure::Error> {
                 .takes_value(false)
                 .help("Activate all available features"),
         )
-        .arg(
-            Arg::with_name("START").help("consider only the call graph that starts from this node"),
+        .args(&analysis_args())
+        .group(start_points())
+        .subcommand(analyze_subcommand())
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compare two analyses saved with `--format json`")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .value_name("FORMAT")
+                        .possible_values(&["text", "dot"])
+                        .default_value("text")
+                        .help(
+                            "Output format; `dot` draws the new call graph with the functions \
+                             whose stack usage increased in red and decreased in green",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("OLD")
+                        .required(true)
+                        .help("The analysis to compare against"),
+                )
+                .arg(
+                    Arg::with_name("NEW")
+                        .required(true)
+                        .help("The new analysis"),
+                ),
         )
         .get_matches();
+
+    if let Some(matches) = matches.subcommand_matches("diff") {
+        let old = Analysis::load(matches.value_of("OLD").expect("UNREACHABLE").as_ref())?;
+        let new = Analysis::load(matches.value_of("NEW").expect("UNREACHABLE").as_ref())?;
+        let diff = diff::diff(&old, &new);
+
+        let stdout = io::stdout();
+        match matches.value_of("format") {
+            Some("dot") => diff.dot(&new, stdout.lock())?,
+            _ => diff.report(stdout.lock())?,
+        }
+
+        return Ok(0);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("analyze") {
+        return analyze(matches, &ArtifactPaths::from_matches(matches));
+    }
+
+    match build(&matches)? {
+        Ok(paths) => analyze(&matches, &paths),
+        Err(code) => Ok(code),
+    }
+}
+
+// arguments that control the analysis and its output; shared by the `analyze` subcommand
+fn analysis_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("format")
+            .long("format")
+            .takes_value(true)
+            .value_name("FORMAT")
+            .possible_values(&["dot", "json", "table", "tree"])
+            .default_value("dot")
+            .help("Output format"),
+        Arg::with_name("top")
+            .long("top")
+            .takes_value(true)
+            .value_name("N")
+            .default_value("10")
+            .validator(|n| n.parse::<usize>().map(drop).map_err(|e| e.to_string()))
+            .help("Number of functions listed by `--format table`"),
+        Arg::with_name("max-stack")
+            .long("max-stack")
+            .takes_value(true)
+            .value_name("BYTES")
+            .validator(|bytes| bytes.parse::<u64>().map(drop).map_err(|e| e.to_string()))
+            .help(
+                "Exit with code 2 if the maximum stack usage of any start point (or of every \
+                 root of the call graph) exceeds this many bytes or can't be bounded",
+            ),
+        Arg::with_name("allow-unknown")
+            .long("allow-unknown")
+            .requires("max-stack")
+            .help("Accept lower bounds of the maximum stack usage in the `--max-stack` check"),
+        Arg::with_name("baseline")
+            .long("baseline")
+            .takes_value(true)
+            .value_name("FILE")
+            .help(
+                "Exit with code 2 if the maximum stack usage of any function tracked in this TOML \
+                 file grew or is no longer exact",
+            ),
+        Arg::with_name("update-baseline")
+            .long("update-baseline")
+            .requires("baseline")
+            .help(
+                "Rewrite the `--baseline` file from the current analysis instead of checking \
+                 against it",
+            ),
+        Arg::with_name("tolerance")
+            .long("tolerance")
+            .takes_value(true)
+            .value_name("BYTES|PERCENT%")
+            .requires("baseline")
+            .validator(|arg| {
+                Tolerance::parse(&arg)
+                    .map(drop)
+                    .ok_or_else(|| "expected a number of bytes or a percentage".to_string())
+            })
+            .help("How much the tracked functions may grow before the `--baseline` check fails"),
+        Arg::with_name("worst-path")
+            .long("worst-path")
+            .requires("start-points")
+            .help("Print the call paths that produce the maximum stack usage of the start points"),
+        Arg::with_name("explain")
+            .long("explain")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("FUNCTION")
+            .help(
+                "Print which local variables and temporaries make up the stack frame of a \
+                 function; can be repeated",
+            ),
+        Arg::with_name("preemption")
+            .long("preemption")
+            .conflicts_with("start-points")
+            .help(
+                "Report the maximum stack usage of the whole (Cortex-M) system, including \
+                 preempting exception handlers",
+            ),
+        Arg::with_name("priority")
+            .long("priority")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("HANDLER=PRIORITY")
+            .validator(|arg| {
+                parse_priority(&arg)
+                    .map(drop)
+                    .ok_or_else(|| "expected `HANDLER=PRIORITY`".to_string())
+            })
+            .requires("preemption")
+            .help(
+                "Priority of an exception handler; handlers with the same priority can't \
+                 preempt each other",
+            ),
+        Arg::with_name("rtic")
+            .long("rtic")
+            .takes_value(true)
+            .value_name("FILE")
+            .requires("preemption")
+            .help(
+                "TOML file with the priorities of the RTIC tasks; interrupt handlers get the \
+                 priority of the tasks they run",
+            ),
+        Arg::with_name("strict").long("strict").help(
+            "Fail if the analysis runs into machine code it can't decode or other problems \
+                 instead of reporting the affected functions as having unknown stack usage",
+        ),
+        Arg::with_name("annotations")
+            .long("annotations")
+            .takes_value(true)
+            .value_name("FILE")
+            .help(
+                "TOML (or JSON) file with the callees and stack usage of functions and indirect \
+                 calls that the analysis can't figure out",
+            ),
+        Arg::with_name("start")
+            .long("start")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("FUNCTION")
+            .help(
+                "Additional start point; `*` and `?` wildcards select several functions. The \
+                 maximum stack usage of each one is reported",
+            ),
+        Arg::with_name("start-regex")
+            .long("start-regex")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("REGEX")
+            .validator(|regex| Pattern::regex(&regex).map(drop).map_err(|e| e.to_string()))
+            .help("Use every function whose name matches this regular expression as a start point"),
+        Arg::with_name("start-all-roots")
+            .long("start-all-roots")
+            .help("Use every function that's not called by other functions as a start point"),
+        Arg::with_name("hide")
+            .long("hide")
+            .visible_alias("exclude")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("PATTERN")
+            .help(
+                "Leave the functions that match this pattern, and the ones only reachable through \
+                 them, out of the output; their stack usage is still accounted for",
+            ),
+        Arg::with_name("START").help("consider only the call graph that starts from this node"),
+    ]
+}
+
+// `START`, `--start`, `--start-regex` and `--start-all-roots`
+fn start_points() -> ArgGroup<'static> {
+    ArgGroup::with_name("start-points")
+        .args(&["START", "start", "start-regex", "start-all-roots"])
+        .multiple(true)
+}
+
+// the `analyze` subcommand: the analysis of prebuilt artifacts
+fn analyze_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("analyze")
+        .about("Analyze prebuilt artifacts instead of building the program with Cargo")
+        .arg(
+            Arg::with_name("elf")
+                .long("elf")
+                .takes_value(true)
+                .value_name("PATH")
+                .required(true)
+                .help("The linked program"),
+        )
+        .arg(
+            Arg::with_name("ll")
+                .long("ll")
+                .takes_value(true)
+                .value_name("PATH")
+                .required(true)
+                .help("LLVM IR of the program, compiled with `-C lto=fat`"),
+        )
+        .arg(
+            Arg::with_name("obj")
+                .long("obj")
+                .takes_value(true)
+                .value_name("PATH")
+                .help(
+                    "Object file with stack usage information (`-Z emit-stack-sizes`); \
+                     if omitted, the `.stack_sizes` section of the ELF file is used",
+                ),
+        )
+        .arg(
+            Arg::with_name("compiler-builtins-rlib")
+                .long("compiler-builtins-rlib")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("obj")
+                .help("`compiler_builtins` rlib, for the stack usage of its functions"),
+        )
+        .arg(
+            Arg::with_name("compiler-builtins-ll")
+                .long("compiler-builtins-ll")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("LLVM IR of `compiler_builtins`"),
+        )
+        .arg(
+            Arg::with_name("target")
+                .long("target")
+                .takes_value(true)
+                .value_name("TRIPLE")
+                .required(true)
+                .help("Target triple for which the code was compiled"),
+        )
+        .args(&analysis_args())
+        .group(start_points())
+}
+
+// parses a `--priority` value: `HANDLER=PRIORITY`
+fn parse_priority(arg: &str) -> Option<(&str, i32)> {
+    let mut parts = arg.splitn(2, '=');
+    let handler = parts.next()?;
+    let priority = parts.next()?.parse().ok()?;
+    Some((handler, priority))
+}
+
+// builds the program using Cargo; returns the paths to the artifacts or, if the build failed,
+// Cargo's exit code
+fn build(matches: &ArgMatches) -> Result<Result<ArtifactPaths, i32>, failure::Error> {
     let is_example = matches.is_present("example");
     let is_binary = matches.is_present("bin");
     let verbose = matches.is_present("verbose");
@@ -222,7 +483,7 @@ fn run() -> Result<i32, failure::Error> {
     let status = child.wait()?;
 
     if !status.success() {
-        return Ok(status.code().unwrap_or(1));
+        return Ok(Err(status.code().unwrap_or(1)));
     }
 
     let compiler_builtins_rlib_path =
@@ -239,8 +500,7 @@ fn run() -> Result<i32, failure::Error> {
         project.path(Artifact::Bin(file), profile, target_flag, &host)?
     };
 
-    let elf = fs::read(&path)
-        .map_err(|e| format_err!("couldn't open ELF file `{}`: {}", path.display(), e))?;
+    let elf = path.clone();
 
     // load llvm-ir file
     let mut ll = None;
@@ -279,1209 +539,377 @@ fn run() -> Result<i32, failure::Error> {
         }
     }
 
-    let ll_path = ll.expect("unreachable");
-    let obj = ll_path.with_extension("o");
-    let ll = fs::read_to_string(&ll_path)
-        .map_err(|e| format_err!("couldn't read LLVM IR from `{}`: {}", ll_path.display(), e))?;
-    let obj = fs::read(&obj)
-        .map_err(|e| format_err!("couldn't read object file `{}`: {}", obj.display(), e))?;
-
-    let compiler_builtins_ll = fs::read_to_string(&compiler_builtins_ll_path).map_err(|e| {
-        format_err!(
-            "couldn't read `compiler_builtins` LLVM IR from `{}`: {}",
-            compiler_builtins_ll_path,
-            e
-        )
-    })?;
-
-    let items = crate::ir::parse(&ll).map_err(|e| {
-        format_err!(
-            "failed to parse application's LLVM IR from `{}`: {}",
-            ll_path.display(),
-            e
-        )
-    })?;
-    let compiler_builtins_items = crate::ir::parse(&compiler_builtins_ll).map_err(|e| {
-        format_err!(
-            "failed to parse `compiler_builtins` LLVM IR from `{}`: {}",
-            compiler_builtins_ll_path,
-            e
-        )
-    })?;
-    let mut defines = HashMap::new();
-    let mut declares = HashMap::new();
-    for item in items.into_iter().chain(compiler_builtins_items) {
-        match item {
-            Item::Define(def) => {
-                defines.insert(def.name, def);
-            }
-
-            Item::Declare(decl) => {
-                declares.insert(decl.name, decl);
-            }
-
-            _ => {}
-        }
-    }
+    let ll = ll.expect("unreachable");
+    let obj = ll.with_extension("o");
 
-    let target = project.target().or(target_flag).unwrap_or(&host);
+    Ok(Ok(ArtifactPaths {
+        elf,
+        obj: Some(obj),
+        ll,
+        compiler_builtins_rlib: Some(compiler_builtins_rlib_path.into()),
+        compiler_builtins_ll: Some(compiler_builtins_ll_path.into()),
+        target: project.target().or(target_flag).unwrap_or(&host).to_owned(),
+    }))
+}
 
-    // we know how to analyze the machine code in the ELF file for these targets thus we have more
-    // information and need less LLVM-IR hacks
-    let target_ = match target {
-        "thumbv6m-none-eabi" => Target::Thumbv6m,
-        "thumbv7m-none-eabi" | "thumbv7em-none-eabi" | "thumbv7em-none-eabihf" => Target::Thumbv7m,
-        _ => Target::Other,
-    };
+// paths to the artifacts the analysis works on
+struct ArtifactPaths {
+    elf: PathBuf,
+    // must have been compiled with `-C lto=fat` so that all the code is in a single file
+    ll: PathBuf,
+    // `None` means: use the `.stack_sizes` section of the ELF file
+    obj: Option<PathBuf>,
+    compiler_builtins_rlib: Option<PathBuf>,
+    compiler_builtins_ll: Option<PathBuf>,
+    target: String,
+}
 
-    // extract stack size information
-    // the `.o` file doesn't have address information so we just keep the stack usage information
-    let mut stack_sizes: HashMap<_, _> = stack_sizes::analyze_object(&obj)?
-        .into_iter()
-        .map(|(name, stack)| (name.to_owned(), stack))
-        .collect();
-
-    let mut ar = Archive::new(
-        File::open(&compiler_builtins_rlib_path)
-            .map_err(|e| format_err!("couldn't open `{}`: {}", compiler_builtins_rlib_path, e))?,
-    );
-
-    let mut buf = vec![];
-    while let Some(entry) = ar.next_entry() {
-        let mut entry = entry?;
-        let header = entry.header();
-
-        if str::from_utf8(header.identifier())
-            .map(|id| id.contains("compiler_builtins") && id.ends_with(".o"))
-            .unwrap_or(false)
-        {
-            buf.clear();
-            entry.read_to_end(&mut buf)?;
-            stack_sizes.extend(
-                stack_sizes::analyze_object(&buf)?
-                    .into_iter()
-                    .map(|(name, stack)| (name.to_owned(), stack)),
-            );
+impl ArtifactPaths {
+    // NOTE `matches` must come from the `analyze` subcommand
+    fn from_matches(matches: &ArgMatches) -> Self {
+        ArtifactPaths {
+            elf: matches.value_of("elf").expect("UNREACHABLE").into(),
+            ll: matches.value_of("ll").expect("UNREACHABLE").into(),
+            obj: matches.value_of("obj").map(PathBuf::from),
+            compiler_builtins_rlib: matches
+                .value_of("compiler-builtins-rlib")
+                .map(PathBuf::from),
+            compiler_builtins_ll: matches.value_of("compiler-builtins-ll").map(PathBuf::from),
+            target: matches.value_of("target").expect("UNREACHABLE").to_owned(),
         }
     }
+}
 
-    // extract list of "live" symbols (symbols that have not been GC-ed by the linker)
-    // this time we use the ELF and not the object file
-    let mut symbols = stack_sizes::analyze_executable(&elf)?;
-
-    // clear the thumb bit
-    if target_.is_thumb() {
-        symbols.defined = symbols
-            .defined
-            .into_iter()
-            .map(|(k, v)| (k & !1, v))
-            .collect();
-    }
-
-    // remove version strings from undefined symbols
-    symbols.undefined = symbols
-        .undefined
+fn analyze(matches: &ArgMatches, paths: &ArtifactPaths) -> Result<i32, failure::Error> {
+    // NOTE these values have already been validated by `clap`
+    let top = matches
+        .value_of("top")
+        .map(|n| n.parse::<usize>().expect("UNREACHABLE"))
+        .expect("UNREACHABLE");
+    let max_stack = matches
+        .value_of("max-stack")
+        .map(|bytes| bytes.parse::<u64>().expect("UNREACHABLE"));
+    let priorities = matches
+        .values_of("priority")
         .into_iter()
-        .map(|sym| {
-            if let Some(name) = sym.rsplit("@@").nth(1) {
-                name
-            } else {
-                sym
-            }
+        .flatten()
+        .map(|arg| parse_priority(arg).expect("UNREACHABLE"))
+        .collect::<HashMap<_, _>>();
+    let rtic_tasks = matches
+        .value_of("rtic")
+        .map(|path| rtic::load(path.as_ref()))
+        .transpose()?;
+    let annotations = matches
+        .value_of("annotations")
+        .map(|path| Annotations::load(path.as_ref()))
+        .transpose()?;
+
+    let elf = fs::read(&paths.elf)
+        .map_err(|e| format_err!("couldn't open ELF file `{}`: {}", paths.elf.display(), e))?;
+    let ll = fs::read_to_string(&paths.ll)
+        .map_err(|e| format_err!("couldn't read LLVM IR from `{}`: {}", paths.ll.display(), e))?;
+    let obj = paths
+        .obj
+        .as_ref()
+        .map(|obj| {
+            fs::read(obj)
+                .map_err(|e| format_err!("couldn't read object file `{}`: {}", obj.display(), e))
         })
-        .collect();
-
-    let mut g = DiGraph::<Node, ()>::new();
-    let mut indices = BTreeMap::<Cow<str>, _>::new();
-
-    let mut indirects: HashMap<FnSig, Indirect> = HashMap::new();
-    let mut dynamics: HashMap<FnSig, Dynamic> = HashMap::new();
-    // functions that could be called by `ArgumentV1.formatter`
-    let mut fmts = HashSet::new();
-
-    // Some functions may be aliased; we map aliases to a single name. For example, if `foo`,
-    // `bar` and `baz` all have the same address then this maps contains: `foo -> foo`, `bar -> foo`
-    // and `baz -> foo`.
-    let mut aliases = HashMap::new();
-    // whether a symbol name is ambiguous after removing the hash
-    let mut ambiguous = HashMap::<String, u32>::new();
-
-    // we do a first pass over all the definitions to collect methods in `impl Trait for Type`
-    let mut default_methods = HashSet::new();
-    for name in defines.keys() {
-        let demangled = rustc_demangle::demangle(name).to_string();
-
-        // `<crate::module::Type as crate::module::Trait>::method::hdeadbeef`
-        if demangled.starts_with("<") {
-            if let Some(rhs) = demangled.splitn(2, " as ").nth(1) {
-                // rhs = `crate::module::Trait>::method::hdeadbeef`
-                let mut parts = rhs.splitn(2, ">::");
-
-                if let (Some(trait_), Some(rhs)) = (parts.next(), parts.next()) {
-                    // trait_ = `crate::module::Trait`, rhs = `method::hdeadbeef`
-
-                    if let Some(method) = dehash(rhs) {
-                        default_methods.insert(format!("{}::{}", trait_, method));
-                    }
-                }
-            }
-        }
-    }
-
-    // add all real nodes
-    let mut has_stack_usage_info = false;
-    let mut has_untyped_symbols = false;
-    let mut addr2name = BTreeMap::new();
-    for (address, sym) in &symbols.defined {
-        let names = sym.names();
-
-        let canonical_name = if names.len() > 1 {
-            // if one of the aliases appears in the `stack_sizes` dictionary, use that
-            if let Some(needle) = names.iter().find(|name| stack_sizes.contains_key(&***name)) {
-                needle
-            } else {
-                // otherwise, pick the first name that's not a tag
-                names
-                    .iter()
-                    .filter_map(|&name| {
-                        if name == "$a" || name.starts_with("$a.") {
-                            None
-                        } else {
-                            Some(name)
-                        }
-                    })
-                    .next()
-                    .expect("UNREACHABLE")
-            }
-        } else {
-            names[0]
-        };
-
-        for name in names {
-            aliases.insert(name, canonical_name);
-        }
-
-        let _out = addr2name.insert(address, canonical_name);
-        debug_assert!(_out.is_none());
-
-        let stack = stack_sizes.get(canonical_name).cloned();
-        if stack.is_none() {
-            if !target_.is_thumb() {
-                warn!("no stack usage information for `{}`", canonical_name);
-            }
-        } else {
-            has_stack_usage_info = true;
-        }
-
-        let demangled = rustc_demangle::demangle(canonical_name).to_string();
-        if let Some(dehashed) = dehash(&demangled) {
-            *ambiguous.entry(dehashed.to_string()).or_insert(0) += 1;
-        }
-
-        let idx = g.add_node(Node(canonical_name, stack, false));
-        indices.insert(canonical_name.into(), idx);
-
-        // trait methods look like `<crate::module::Type as crate::module::Trait>::method::h$hash`
-        // default trait methods look like `crate::module::Trait::method::h$hash`
-        let is_trait_method = demangled.starts_with("<") && demangled.contains(" as ") || {
-            dehash(&demangled)
-                .map(|path| default_methods.contains(path))
-                .unwrap_or(false)
-        };
-
-        if let Some(def) = names.iter().filter_map(|name| defines.get(name)).next() {
-            // if the signature is `fn(&_, &mut fmt::Formatter) -> fmt::Result`
-            match (&def.sig.inputs[..], def.sig.output.as_ref()) {
-                ([Type::Pointer(..), Type::Pointer(fmt)], Some(output))
-                    if **fmt == Type::Alias("core::fmt::Formatter")
-                        && **output == Type::Integer(1) =>
-                {
-                    fmts.insert(idx);
-                }
-
-                _ => {}
-            }
-
-            let is_object_safe = is_trait_method && {
-                match def.sig.inputs.first().as_ref() {
-                    Some(Type::Pointer(ty)) => match **ty {
-                        // XXX can the receiver be a *specific* function? (e.g. `fn() {foo}`)
-                        Type::Fn(_) => false,
-
-                        _ => true,
-                    },
-                    _ => false,
-                }
-            };
-
-            if is_object_safe {
-                let mut sig = def.sig.clone();
-
-                // erase the type of the reciver
-                sig.inputs[0] = Type::erased();
-
-                dynamics.entry(sig).or_default().callees.insert(idx);
-            } else {
-                indirects
-                    .entry(def.sig.clone())
-                    .or_default()
-                    .callees
-                    .insert(idx);
-            }
-        } else if let Some(sig) = names
-            .iter()
-            .filter_map(|name| declares.get(name).and_then(|decl| decl.sig.clone()))
-            .next()
-        {
-            // sanity check (?)
-            assert!(!is_trait_method, "BUG: undefined trait method");
-
-            indirects.entry(sig).or_default().callees.insert(idx);
-        } else {
-            has_untyped_symbols = true;
-            warn!("no type information for `{}`", canonical_name);
-        }
-    }
-
-    // to avoid printing several warnings about the same thing
-    let mut fns_containing_asm = HashSet::new();
-    let mut llvm_seen = HashSet::new();
-    // add edges
-    let mut edges: HashMap<_, HashSet<_>> = HashMap::new(); // NodeIdx -> [NodeIdx]
-    let mut defined = HashSet::new(); // functions that are `define`-d in the LLVM-IR
-    for define in defines.values() {
-        let canonical_name = match aliases.get(&define.name) {
-            Some(canonical_name) => canonical_name,
-            None => {
-                // this symbol was GC-ed by the linker, skip
-                continue;
-            }
-        };
-        defined.insert(*canonical_name);
-        let caller = indices[*canonical_name];
-        let callees_seen = edges.entry(caller).or_default();
-
-        for stmt in &define.stmts {
-            match stmt {
-                Stmt::Asm(expr) => {
-                    if fns_containing_asm.insert(*canonical_name) {
-                        // NB: we only print the first inline asm statement in a function
-                        warn!(
-                            "assuming that asm!(\"{}\") does *not* use the stack in `{}`",
-                            expr, canonical_name
-                        );
-                    }
-                }
-
-                // this is basically `(mem::transmute<*const u8, fn()>(&__some_symbol))()`
-                Stmt::BitcastCall(sym) => {
-                    // XXX we have some type information for this call but it's unclear if we should
-                    // try harder -- does this ever occur in pure Rust programs?
-
-                    let sym = sym.expect("BUG? unnamed symbol is being invoked");
-                    let callee = if let Some(idx) = indices.get(sym) {
-                        *idx
-                    } else {
-                        warn!("no stack information for `{}`", sym);
-
-                        let idx = g.add_node(Node(sym, None, false));
-                        indices.insert(Cow::Borrowed(sym), idx);
-                        idx
-                    };
-
-                    g.add_edge(caller, callee, ());
-                }
-
-                Stmt::DirectCall(func) => {
-                    match *func {
-                        // no-op / debug-info
-                        "llvm.dbg.value" => continue,
-                        "llvm.dbg.declare" => continue,
-
-                        // no-op / compiler-hint
-                        "llvm.assume" => continue,
-
-                        // lowers to a single instruction
-                        "llvm.trap" => continue,
-
-                        _ => {}
-                    }
-
-                    // no-op / compiler-hint
-                    if func.starts_with("llvm.lifetime.start")
-                        || func.starts_with("llvm.lifetime.end")
-                    {
-                        continue;
-                    }
-
-                    let mut call = |callee| {
-                        if !callees_seen.contains(&callee) {
-                            g.add_edge(caller, callee, ());
-                            callees_seen.insert(callee);
-                        }
-                    };
-
-                    if target_.is_thumb() && func.starts_with("llvm.") {
-                        // we'll analyze the machine code in the ELF file to figure out what these
-                        // lower to
-                        continue;
-                    }
-
-                    // TODO? consider alignment and `value` argument to only include one edge
-                    // TODO? consider the `len` argument to elide the call to `*mem*`
-                    if func.starts_with("llvm.memcpy.") {
-                        if let Some(callee) = indices.get("memcpy") {
-                            call(*callee);
-                        }
-
-                        // ARMv7-R and the like use these
-                        if let Some(callee) = indices.get("__aeabi_memcpy") {
-                            call(*callee);
-                        }
-
-                        if let Some(callee) = indices.get("__aeabi_memcpy4") {
-                            call(*callee);
-                        }
-
-                        continue;
-                    }
-
-                    // TODO? consider alignment and `value` argument to only include one edge
-                    // TODO? consider the `len` argument to elide the call to `*mem*`
-                    if func.starts_with("llvm.memset.") || func.starts_with("llvm.memmove.") {
-                        if let Some(callee) = indices.get("memset") {
-                            call(*callee);
-                        }
-
-                        // ARMv7-R and the like use these
-                        if let Some(callee) = indices.get("__aeabi_memset") {
-                            call(*callee);
-                        }
-
-                        if let Some(callee) = indices.get("__aeabi_memset4") {
-                            call(*callee);
-                        }
-
-                        if let Some(callee) = indices.get("memclr") {
-                            call(*callee);
-                        }
-
-                        if let Some(callee) = indices.get("__aeabi_memclr") {
-                            call(*callee);
-                        }
-
-                        if let Some(callee) = indices.get("__aeabi_memclr4") {
-                            call(*callee);
-                        }
-
-                        continue;
-                    }
-
-                    // XXX unclear whether these produce library calls on some platforms or not
-                    if func.starts_with("llvm.bswap.")
-                        | func.starts_with("llvm.ctlz.")
-                        | func.starts_with("llvm.cttz.")
-                        | func.starts_with("llvm.sadd.with.overflow.")
-                        | func.starts_with("llvm.smul.with.overflow.")
-                        | func.starts_with("llvm.ssub.with.overflow.")
-                        | func.starts_with("llvm.uadd.with.overflow.")
-                        | func.starts_with("llvm.umul.with.overflow.")
-                        | func.starts_with("llvm.usub.sat.")
-                        | func.starts_with("llvm.usub.with.overflow.")
-                        | func.starts_with("llvm.x86.sse2.pmovmskb.")
-                    {
-                        if !llvm_seen.contains(func) {
-                            llvm_seen.insert(func);
-                            warn!("assuming that `{}` directly lowers to machine code", func);
-                        }
-
-                        continue;
-                    }
-
-                    assert!(
-                        !func.starts_with("llvm."),
-                        "BUG: unhandled llvm intrinsic: {}",
-                        func
-                    );
-
-                    // use canonical name
-                    let callee = if let Some(canon) = aliases.get(func) {
-                        indices[*canon]
-                    } else {
-                        assert!(
-                            symbols.undefined.contains(func),
-                            "BUG: callee `{}` is unknown",
-                            func
-                        );
-
-                        if let Some(idx) = indices.get(*func) {
-                            *idx
-                        } else {
-                            let idx = g.add_node(Node(*func, None, false));
-                            indices.insert((*func).into(), idx);
-
-                            idx
-                        }
-                    };
-
-                    if !callees_seen.contains(&callee) {
-                        callees_seen.insert(callee);
-                        g.add_edge(caller, callee, ());
-                    }
-                }
-
-                Stmt::IndirectCall(sig) => {
-                    if sig
-                        .inputs
-                        .first()
-                        .map(|ty| ty.has_been_erased())
-                        .unwrap_or(false)
-                    {
-                        // dynamic dispatch
-                        let dynamic = dynamics.entry(sig.clone()).or_default();
-
-                        dynamic.called = true;
-                        dynamic.callers.insert(caller);
-                    } else {
-                        let indirect = indirects.entry(sig.clone()).or_default();
-
-                        indirect.called = true;
-                        indirect.callers.insert(caller);
-                    }
-                }
-
-                Stmt::Label | Stmt::Comment | Stmt::Other => {}
-            }
-        }
-    }
-
-    // here we parse the machine code in the ELF file to find out edges that don't appear in the
-    // LLVM-IR (e.g. `fadd` operation, `call llvm.umul.with.overflow`, etc.) or are difficult to
-    // disambiguate from the LLVM-IR (e.g. does this `llvm.memcpy` lower to a call to
-    // `__aebi_memcpy`, a call to `__aebi_memcpy4` or machine instructions?)
-    if target_.is_thumb() {
-        let elf = ElfFile::new(&elf).map_err(failure::err_msg)?;
-        let sect = elf.find_section_by_name(".symtab").expect("UNREACHABLE");
-        let mut tags: Vec<_> = match sect.get_data(&elf).unwrap() {
-            SectionData::SymbolTable32(entries) => entries
-                .iter()
-                .filter_map(|entry| {
-                    let addr = entry.value() as u32;
-                    entry.get_name(&elf).ok().and_then(|name| {
-                        if name.starts_with("$d") {
-                            Some((addr, Tag::Data))
-                        } else if name.starts_with("$t") {
-                            Some((addr, Tag::Thumb))
-                        } else {
-                            None
-                        }
-                    })
-                })
-                .collect(),
-            _ => unreachable!(),
-        };
-
-        tags.sort_by(|a, b| a.0.cmp(&b.0));
-
-        if let Some(sect) = elf.find_section_by_name(".text") {
-            let stext = sect.address() as u32;
-            let text = sect.raw_data(&elf);
-
-            for (address, sym) in &symbols.defined {
-                let address = *address as u32;
-                let canonical_name = aliases[&sym.names()[0]];
-                let mut size = sym.size() as u32;
-
-                if size == 0 {
-                    // try harder at finding out the size of this symbol
-                    if let Ok(needle) = tags.binary_search_by(|tag| tag.0.cmp(&address)) {
-                        let start = tags[needle];
-                        if start.1 == Tag::Thumb {
-                            if let Some(end) = tags.get(needle + 1) {
-                                if end.1 == Tag::Thumb {
-                                    size = end.0 - start.0;
-                                }
-                            }
-                        }
-                    }
-                }
-
-                let start = (address - stext) as usize;
-                let end = start + size as usize;
-                let (bls, bs, indirect, modifies_sp, our_stack) = thumb::analyze(
-                    &text[start..end],
-                    address,
-                    target_ == Target::Thumbv7m,
-                    &tags,
-                );
-                let caller = indices[canonical_name];
-
-                // sanity check
-                if let Some(stack) = our_stack {
-                    assert_eq!(
-                        stack != 0,
-                        modifies_sp,
-                        "BUG: our analysis reported that `{}` both uses {} bytes of stack and \
-                         it does{} modify SP",
-                        canonical_name,
-                        stack,
-                        if !modifies_sp { " not" } else { "" }
-                    );
-                }
-
-                // check the correctness of `modifies_sp` and `our_stack`
-                // also override LLVM's results when they appear to be wrong
-                if let Local::Exact(ref mut llvm_stack) = g[caller].local {
-                    if let Some(stack) = our_stack {
-                        if *llvm_stack != stack && fns_containing_asm.contains(&canonical_name) {
-                            // LLVM's stack usage analysis ignores inline asm, so its results can
-                            // be wrong here
-
-                            warn!(
-                                "LLVM reported that `{}` uses {} bytes of stack but \
-                                 our analysis reported {} bytes; overriding LLVM's result (function \
-                                 uses inline assembly)",
-                                canonical_name, llvm_stack, stack
-                            );
-
-                            *llvm_stack = stack;
-                        } else {
-                            // in all other cases our results should match
-
-                            assert_eq!(
-                                *llvm_stack, stack,
-                                "BUG: LLVM reported that `{}` uses {} bytes of stack but \
-                                 this doesn't match our analysis",
-                                canonical_name, llvm_stack
-                            );
-                        }
-                    }
-
-                    assert_eq!(
-                        *llvm_stack != 0,
-                        modifies_sp,
-                        "BUG: LLVM reported that `{}` uses {} bytes of stack but this doesn't \
-                         match our analysis",
-                        canonical_name,
-                        *llvm_stack
-                    );
-                } else if let Some(stack) = our_stack {
-                    g[caller].local = Local::Exact(stack);
-                } else if !modifies_sp {
-                    // this happens when the function contains intra-branches and our analysis gives
-                    // up (`our_stack == None`)
-                    g[caller].local = Local::Exact(0);
-                }
-
-                if g[caller].local == Local::Unknown {
-                    warn!("no stack usage information for `{}`", canonical_name);
-                }
-
-                if !defined.contains(canonical_name) && indirect {
-                    // this function performs an indirect function call and we have no type
-                    // information to narrow down the list of callees so inject the uncertainty
-                    // in the form of a call to an unknown function with unknown stack usage
-
-                    warn!(
-                        "`{}` performs an indirect function call and there's \
-                         no type information about the operation",
-                        canonical_name,
-                    );
-                    let callee = g.add_node(Node("?", None, false));
-                    g.add_edge(caller, callee, ());
-                }
-
-                let callees_seen = edges.entry(caller).or_default();
-                for offset in bls {
-                    let addr = (address as i64 + i64::from(offset)) as u64;
-                    // address may be off by one due to the thumb bit being set
-                    let name = addr2name
-                        .get(&addr)
-                        .unwrap_or_else(|| panic!("BUG? no symbol at address {}", addr));
-
-                    let callee = indices[*name];
-                    if !callees_seen.contains(&callee) {
-                        g.add_edge(caller, callee, ());
-                        callees_seen.insert(callee);
-                    }
-                }
+        .transpose()?;
+    let compiler_builtins_rlib = paths
+        .compiler_builtins_rlib
+        .as_ref()
+        .map(|path| {
+            fs::read(path).map_err(|e| format_err!("couldn't open `{}`: {}", path.display(), e))
+        })
+        .transpose()?;
+    let compiler_builtins_ll = paths
+        .compiler_builtins_ll
+        .as_ref()
+        .map(|path| {
+            fs::read_to_string(path).map_err(|e| {
+                format_err!(
+                    "couldn't read `compiler_builtins` LLVM IR from `{}`: {}",
+                    path.display(),
+                    e
+                )
+            })
+        })
+        .transpose()?;
+
+    let mut cg = CallGraph::from_artifacts(&Artifacts {
+        elf: &elf,
+        ll: &ll,
+        obj: obj.as_deref(),
+        compiler_builtins_rlib: compiler_builtins_rlib.as_deref(),
+        compiler_builtins_ll: compiler_builtins_ll.as_deref(),
+        target: &paths.target,
+    })?;
 
-                for offset in bs {
-                    let addr = (address as i32 + offset) as u32;
-
-                    if addr >= address && addr < (address + size) {
-                        // intra-function B branches are not function calls
-                    } else {
-                        // address may be off by one due to the thumb bit being set
-                        let name = addr2name
-                            .get(&(addr as u64))
-                            .unwrap_or_else(|| panic!("BUG? no symbol at address {}", addr));
-
-                        let callee = indices[*name];
-                        if !callees_seen.contains(&callee) {
-                            g.add_edge(caller, callee, ());
-                            callees_seen.insert(callee);
-                        }
-                    }
-                }
-            }
-        } else {
-            error!(".text section not found")
-        }
+    if let Some(annotations) = &annotations {
+        cg.annotate(annotations);
     }
 
-    // add fictitious nodes for indirect function calls
-    if has_untyped_symbols {
-        warn!(
-            "the program contains untyped, external symbols (e.g. linked in from binary blobs); \
-             indirect function calls can not be bounded"
-        );
+    // NOTE annotations can resolve the problems so this check must come after them
+    let diagnostics = cg.diagnostics();
+    if matches.is_present("strict") && !diagnostics.is_empty() {
+        return Err(format_err!(
+            "the analysis ran into {} problem(s), the first one being {}",
+            diagnostics.len(),
+            diagnostics[0]
+        ));
     }
 
-    // this is a bit weird but for some reason `ArgumentV1.formatter` sometimes lowers to different
-    // LLVM types. In theory it should always be: `i1 (*%fmt::Void, *&core::fmt::Formatter)*` but
-    // sometimes the type of the first argument is `%fmt::Void`, sometimes it's `%core::fmt::Void`,
-    // sometimes is `%core::fmt::Void.12` and on occasion it's even `%SomeRandomType`
-    //
-    // To cope with this weird fact the following piece of code will try to find the right LLVM
-    // type.
-    let all_maybe_void = indirects
-        .keys()
-        .filter_map(|sig| match (&sig.inputs[..], sig.output.as_ref()) {
-            ([Type::Pointer(receiver), Type::Pointer(formatter)], Some(output))
-                if **formatter == Type::Alias("core::fmt::Formatter")
-                    && **output == Type::Integer(1) =>
-            {
-                if let Type::Alias(receiver) = **receiver {
-                    Some(receiver)
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        })
-        .collect::<Vec<_>>();
+    // the stack headroom of `cortex-m-rt` programs is that of `Reset`, whatever the start points
+    let reset = cg
+        .nodes()
+        .find(|node| node.name() == "Reset")
+        .and_then(|reset| Some((format!("`{}`", reset.demangled()), reset.max()?)));
 
-    let one_true_void = if all_maybe_void.contains(&"fmt::Void") {
-        Some("fmt::Void")
+    // the baseline can track, and `--explain` can break down, any function, not only the ones
+    // reachable from the start points
+    let unfiltered = if matches.is_present("baseline") || matches.is_present("explain") {
+        Some(cg.clone())
     } else {
-        all_maybe_void
-            .iter()
-            .filter_map(|maybe_void| {
-                // this could be `core::fmt::Void` or `core::fmt::Void.12`
-                if maybe_void.starts_with("core::fmt::Void") {
-                    Some(*maybe_void)
-                } else {
-                    None
-                }
-            })
-            .next()
-            .or_else(|| {
-                if all_maybe_void.len() == 1 {
-                    // we got a random type!
-                    Some(all_maybe_void[0])
-                } else {
-                    None
-                }
-            })
+        None
     };
 
-    for (mut sig, indirect) in indirects {
-        if !indirect.called {
-            continue;
-        }
-
-        let callees = if let Some(one_true_void) = one_true_void {
-            match (&sig.inputs[..], sig.output.as_ref()) {
-                // special case: this is `ArgumentV1.formatter` a pseudo trait object
-                ([Type::Pointer(void), Type::Pointer(fmt)], Some(output))
-                    if **void == Type::Alias(one_true_void)
-                        && **fmt == Type::Alias("core::fmt::Formatter")
-                        && **output == Type::Integer(1) =>
-                {
-                    if fmts.is_empty() {
-                        error!("BUG? no callees for `{}`", sig.to_string());
-                    }
-
-                    // canonicalize the signature
-                    if one_true_void != "fmt::Void" {
-                        sig.inputs[0] = Type::Alias("fmt::Void");
-                    }
-
-                    &fmts
-                }
-
-                _ => &indirect.callees,
-            }
-        } else {
-            &indirect.callees
-        };
-
-        let mut name = sig.to_string();
-        // append '*' to denote that this is a function pointer
-        name.push('*');
-
-        let call = g.add_node(Node(name.clone(), Some(0), true));
-
-        for caller in &indirect.callers {
-            g.add_edge(*caller, call, ());
+    // filter the call graph
+    let starts = matches
+        .value_of("START")
+        .into_iter()
+        .chain(matches.values_of("start").into_iter().flatten())
+        .map(|start| (start, Pattern::glob(start)))
+        .chain(
+            matches
+                .values_of("start-regex")
+                .into_iter()
+                .flatten()
+                .map(|regex| (regex, Pattern::regex(regex).expect("UNREACHABLE"))),
+        )
+        .collect::<Vec<_>>();
+    if matches.is_present("start-all-roots") {
+        // NOTE `clap` doesn't support conflicts between members of the same group
+        if !starts.is_empty() {
+            return Err(format_err!(
+                "`--start-all-roots` can't be combined with other start points"
+            ));
         }
 
-        if has_untyped_symbols {
-            // add an edge between this and a potential extern / untyped symbol
-            let extern_sym = g.add_node(Node("?", None, false));
-            g.add_edge(call, extern_sym, ());
-        } else {
-            if callees.is_empty() {
-                error!("BUG? no callees for `{}`", name);
+        cg = cg.filter_roots();
+    } else if !starts.is_empty() {
+        for (start, pattern) in &starts {
+            if cg.filter_all(slice::from_ref(pattern)).is_none() {
+                error!("start point `{}` not found", start);
             }
         }
 
-        for callee in callees {
-            g.add_edge(call, *callee, ());
-        }
-    }
-
-    // add fictitious nodes for dynamic dispatch
-    for (sig, dynamic) in dynamics {
-        if !dynamic.called {
-            continue;
-        }
-
-        let name = sig.to_string();
-
-        if dynamic.callees.is_empty() {
-            error!("BUG? no callees for `{}`", name);
-        }
-
-        let call = g.add_node(Node(name, Some(0), true));
-        for caller in &dynamic.callers {
-            g.add_edge(*caller, call, ());
-        }
-
-        for callee in &dynamic.callees {
-            g.add_edge(call, *callee, ());
-        }
-    }
-
-    // filter the call graph
-    if let Some(start) = matches.value_of("START") {
-        let start = indices.get(start).cloned().or_else(|| {
-            let start_ = start.to_owned() + "::h";
-            let hits = indices
-                .keys()
-                .filter_map(|key| {
-                    if rustc_demangle::demangle(key)
-                        .to_string()
-                        .starts_with(&start_)
-                    {
-                        Some(key)
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>();
-
-            if hits.len() > 1 {
-                error!("multiple matches for `{}`: {:?}", start, hits);
-                None
-            } else {
-                hits.first().map(|key| indices[*key])
-            }
-        });
-
-        if let Some(start) = start {
-            // create a new graph that only contains nodes reachable from `start`
-            let mut g2 = DiGraph::<Node, ()>::new();
-
-            // maps `g`'s `NodeIndex`-es to `g2`'s `NodeIndex`-es
-            let mut one2two = BTreeMap::new();
-
-            let mut dfs = Dfs::new(&g, start);
-            while let Some(caller1) = dfs.next(&g) {
-                let caller2 = if let Some(i2) = one2two.get(&caller1) {
-                    *i2
-                } else {
-                    let i2 = g2.add_node(g[caller1].clone());
-                    one2two.insert(caller1, i2);
-                    i2
-                };
-
-                let mut callees = g.neighbors(caller1).detach();
-                while let Some((_, callee1)) = callees.next(&g) {
-                    let callee2 = if let Some(i2) = one2two.get(&callee1) {
-                        *i2
-                    } else {
-                        let i2 = g2.add_node(g[callee1].clone());
-                        one2two.insert(callee1, i2);
-                        i2
-                    };
-
-                    g2.add_edge(caller2, callee2, ());
-                }
-            }
-
-            // replace the old graph
-            g = g2;
-
-            // invalidate `indices` to prevent misuse
-            indices.clear();
+        let starts = starts
+            .into_iter()
+            .map(|(_, pattern)| pattern)
+            .collect::<Vec<_>>();
+        if let Some(filtered) = cg.filter_all(&starts) {
+            cg = filtered;
         } else {
             error!("start point not found; the graph will not be filtered")
         }
     }
 
-    let mut cycles = vec![];
-    if !has_stack_usage_info {
-        error!("The graph has zero stack usage information; skipping max stack usage analysis");
-    } else if algo::is_cyclic_directed(&g) {
-        let sccs = algo::kosaraju_scc(&g);
-
-        // iterate over SCCs (Strongly Connected Components) in reverse topological order
-        for scc in &sccs {
-            let first = scc[0];
-
-            let is_a_cycle = scc.len() > 1
-                || g.neighbors_directed(first, Direction::Outgoing)
-                    .any(|n| n == first);
-
-            if is_a_cycle {
-                cycles.push(scc.clone());
-
-                let mut scc_local =
-                    max_of(scc.iter().map(|node| g[*node].local.into())).expect("UNREACHABLE");
-
-                // the cumulative stack usage is only exact when all nodes do *not* use the stack
-                if let Max::Exact(n) = scc_local {
-                    if n != 0 {
-                        scc_local = Max::LowerBound(n)
-                    }
-                }
-
-                let neighbors_max = max_of(scc.iter().flat_map(|inode| {
-                    g.neighbors_directed(*inode, Direction::Outgoing)
-                        .filter_map(|neighbor| {
-                            if scc.contains(&neighbor) {
-                                // we only care about the neighbors of the SCC
-                                None
-                            } else {
-                                Some(g[neighbor].max.expect("UNREACHABLE"))
-                            }
-                        })
-                }));
-
-                for inode in scc {
-                    let node = &mut g[*inode];
-                    if let Some(max) = neighbors_max {
-                        node.max = Some(max + scc_local);
-                    } else {
-                        node.max = Some(scc_local);
-                    }
-                }
-            } else {
-                let inode = first;
+    // hidden functions are only left out of the output; they still count towards the maximum stack
+    // usage of their callers
+    let hide = matches
+        .values_of("hide")
+        .into_iter()
+        .flatten()
+        .map(Pattern::glob)
+        .collect::<Vec<_>>();
+    let shown = if hide.is_empty() {
+        None
+    } else {
+        Some(cg.hide(&hide))
+    };
 
-                let neighbors_max = max_of(
-                    g.neighbors_directed(inode, Direction::Outgoing)
-                        .map(|neighbor| g[neighbor].max.expect("UNREACHABLE")),
+    let stdout = io::stdout();
+    match matches.value_of("format") {
+        Some("json") => shown.as_ref().unwrap_or(&cg).json(stdout.lock())?,
+        Some("table") => shown.as_ref().unwrap_or(&cg).table(top, stdout.lock())?,
+        Some("tree") => shown.as_ref().unwrap_or(&cg).tree(stdout.lock())?,
+        _ => shown.as_ref().unwrap_or(&cg).dot(stdout.lock())?,
+    }
+
+    // combined report of all the start points
+    if matches.is_present("start")
+        || matches.is_present("start-regex")
+        || matches.is_present("start-all-roots")
+    {
+        for (start, max) in cg.starts_by_max() {
+            eprintln!("`{}` max {}", start.demangled(), max.in_bytes());
+        }
+    }
+
+    if matches.is_present("worst-path") {
+        for start in cg.starts() {
+            if let Some(max) = start.max() {
+                eprintln!(
+                    "worst-case call path of `{}` (max {}):",
+                    start.demangled(),
+                    max.in_bytes()
                 );
-
-                let node = &mut g[inode];
-                if let Some(max) = neighbors_max {
-                    node.max = Some(max + node.local);
-                } else {
-                    node.max = Some(node.local.into());
-                }
-            }
-        }
-    } else {
-        // compute max stack usage
-        let mut topo = Topo::new(Reversed(&g));
-        while let Some(node) = topo.next(Reversed(&g)) {
-            debug_assert!(g[node].max.is_none());
-
-            let neighbors_max = max_of(
-                g.neighbors_directed(node, Direction::Outgoing)
-                    .map(|neighbor| g[neighbor].max.expect("UNREACHABLE")),
-            );
-
-            if let Some(max) = neighbors_max {
-                g[node].max = Some(max + g[node].local);
-            } else {
-                g[node].max = Some(g[node].local.into());
+                cg.print_worst_path(start.name());
             }
         }
     }
 
-    // here we try to shorten the name of the symbol if it doesn't result in ambiguity
-    for node in g.node_weights_mut() {
-        let demangled = rustc_demangle::demangle(&node.name).to_string();
-
-        if let Some(dehashed) = dehash(&demangled) {
-            if ambiguous[dehashed] == 1 {
-                node.name = Cow::Owned(dehashed.to_owned());
+    if let Some(functions) = matches.values_of("explain") {
+        let cg = unfiltered.as_ref().unwrap_or(&cg);
+        for function in functions {
+            if let Some(node) = cg.node(function) {
+                node.explain(io::stderr())?;
+            } else {
+                error!("function `{}` not found", function);
             }
         }
     }
 
-    dot(g, &cycles)?;
-
-    Ok(0)
-}
-
-fn dot(g: Graph<Node, ()>, cycles: &[Vec<NodeIndex>]) -> io::Result<()> {
-    let stdout = io::stdout();
-    let mut stdout = stdout.lock();
-
-    writeln!(stdout, "digraph {{")?;
-    writeln!(stdout, "    node [fontname={} shape=box]", FONT)?;
-
-    for (i, node) in g.raw_nodes().iter().enumerate() {
-        let node = &node.weight;
-
-        write!(stdout, "    {} [label=\"", i,)?;
-
-        let mut escaper = Escaper::new(&mut stdout);
-        write!(escaper, "{}", rustc_demangle::demangle(&node.name)).ok();
-        escaper.error?;
-
-        if let Some(max) = node.max {
-            write!(stdout, "\\nmax {}", max)?;
-        }
-
-        write!(stdout, "\\nlocal = {}\"", node.local,)?;
-
-        if node.dashed {
-            write!(stdout, " style=dashed")?;
-        }
-
-        writeln!(stdout, "]")?;
-    }
-
-    for edge in g.raw_edges() {
-        writeln!(
-            stdout,
-            "    {} -> {}",
-            edge.source().index(),
-            edge.target().index()
-        )?;
-    }
-
-    for (i, cycle) in cycles.iter().enumerate() {
-        writeln!(stdout, "\n    subgraph cluster_{} {{", i)?;
-        writeln!(stdout, "        style=dashed")?;
-        writeln!(stdout, "        fontname={}", FONT)?;
-        writeln!(stdout, "        label=\"SCC{}\"", i)?;
-
-        for node in cycle {
-            writeln!(stdout, "        {}", node.index())?;
-        }
-
-        writeln!(stdout, "    }}")?;
-    }
-
-    writeln!(stdout, "}}")
-}
-
-struct Escaper<W>
-where
-    W: io::Write,
-{
-    writer: W,
-    error: io::Result<()>,
-}
-
-impl<W> Escaper<W>
-where
-    W: io::Write,
-{
-    fn new(writer: W) -> Self {
-        Escaper {
-            writer,
-            error: Ok(()),
-        }
-    }
-}
-
-impl<W> fmt::Write for Escaper<W>
-where
-    W: io::Write,
-{
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            self.write_char(c)?;
-        }
-
-        Ok(())
-    }
-
-    fn write_char(&mut self, c: char) -> fmt::Result {
-        match (|| -> io::Result<()> {
-            match c {
-                '"' => write!(self.writer, "\\")?,
-                _ => {}
-            }
-
-            write!(self.writer, "{}", c)
-        })() {
-            Err(e) => {
-                self.error = Err(e);
+    // stack usage of the whole system: thread code plus preempting exception handlers
+    let system_max = if matches.is_present("preemption") {
+        preemption::system(&cg, &priorities, rtic_tasks.as_ref())?
+    } else {
+        None
+    };
 
-                Err(fmt::Error)
+    // report how much of the stack region `cortex-m-rt` programs use
+    if let Some((reset, max)) = &reset {
+        if let Some(region) = headroom::stack_region(&elf) {
+            if let Some(total) = system_max {
+                headroom::report("the whole system", total, region, io::stderr())?;
+            } else {
+                headroom::report(reset, *max, region, io::stderr())?;
             }
-            Ok(()) => Ok(()),
         }
     }
-}
 
-#[derive(Clone)]
-struct Node<'a> {
-    name: Cow<'a, str>,
-    local: Local,
-    max: Option<Max>,
-    dashed: bool,
-}
-
-#[allow(non_snake_case)]
-fn Node<'a, S>(name: S, stack: Option<u64>, dashed: bool) -> Node<'a>
-where
-    S: Into<Cow<'a, str>>,
-{
-    Node {
-        name: name.into(),
-        local: stack.map(Local::Exact).unwrap_or(Local::Unknown),
-        max: None,
-        dashed,
-    }
-}
-
-/// Local stack usage
-#[derive(Clone, Copy, PartialEq)]
-enum Local {
-    Exact(u64),
-    Unknown,
-}
-
-impl fmt::Display for Local {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Local::Exact(n) => write!(f, "{}", n),
-            Local::Unknown => f.write_str("?"),
-        }
-    }
-}
-
-impl Into<Max> for Local {
-    fn into(self) -> Max {
-        match self {
-            Local::Exact(n) => Max::Exact(n),
-            Local::Unknown => Max::LowerBound(0),
-        }
-    }
-}
-
-#[derive(Clone, Copy, Eq, PartialEq)]
-enum Max {
-    Exact(u64),
-    LowerBound(u64),
-}
-
-impl ops::Add<Local> for Max {
-    type Output = Max;
-
-    fn add(self, rhs: Local) -> Max {
-        match (self, rhs) {
-            (Max::Exact(lhs), Local::Exact(rhs)) => Max::Exact(lhs + rhs),
-            (Max::Exact(lhs), Local::Unknown) => Max::LowerBound(lhs),
-            (Max::LowerBound(lhs), Local::Exact(rhs)) => Max::LowerBound(lhs + rhs),
-            (Max::LowerBound(lhs), Local::Unknown) => Max::LowerBound(lhs),
-        }
-    }
-}
-
-impl ops::Add<Max> for Max {
-    type Output = Max;
-
-    fn add(self, rhs: Max) -> Max {
-        match (self, rhs) {
-            (Max::Exact(lhs), Max::Exact(rhs)) => Max::Exact(lhs + rhs),
-            (Max::Exact(lhs), Max::LowerBound(rhs)) => Max::LowerBound(lhs + rhs),
-            (Max::LowerBound(lhs), Max::Exact(rhs)) => Max::LowerBound(lhs + rhs),
-            (Max::LowerBound(lhs), Max::LowerBound(rhs)) => Max::LowerBound(lhs + rhs),
-        }
+    let mut verdict = Verdict::Pass;
+    if let Some(budget) = max_stack {
+        verdict = budget::check(&cg, budget, matches.is_present("allow-unknown"));
     }
-}
-
-fn max_of(mut iter: impl Iterator<Item = Max>) -> Option<Max> {
-    iter.next().map(|first| iter.fold(first, max))
-}
-
-fn max(lhs: Max, rhs: Max) -> Max {
-    match (lhs, rhs) {
-        (Max::Exact(lhs), Max::Exact(rhs)) => Max::Exact(cmp::max(lhs, rhs)),
-        (Max::Exact(lhs), Max::LowerBound(rhs)) => Max::LowerBound(cmp::max(lhs, rhs)),
-        (Max::LowerBound(lhs), Max::Exact(rhs)) => Max::LowerBound(cmp::max(lhs, rhs)),
-        (Max::LowerBound(lhs), Max::LowerBound(rhs)) => Max::LowerBound(cmp::max(lhs, rhs)),
-    }
-}
 
-impl fmt::Display for Max {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Max::Exact(n) => write!(f, "= {}", n),
-            Max::LowerBound(n) => write!(f, ">= {}", n),
-        }
-    }
-}
+    if let (Some(path), Some(unfiltered)) = (matches.value_of("baseline"), &unfiltered) {
+        let path = Path::new(path);
 
-// used to track indirect function calls (`fn` pointers)
-#[derive(Default)]
-struct Indirect {
-    called: bool,
-    callers: HashSet<NodeIndex>,
-    callees: HashSet<NodeIndex>,
-}
-
-// used to track dynamic dispatch (trait objects)
-#[derive(Debug, Default)]
-struct Dynamic {
-    called: bool,
-    callers: HashSet<NodeIndex>,
-    callees: HashSet<NodeIndex>,
-}
+        if matches.is_present("update-baseline") {
+            // keep tracking the same functions; a new baseline tracks the start points
+            let baseline = if path.exists() {
+                let old = Baseline::load(path)?;
+                let functions = old
+                    .functions()
+                    .filter_map(|function| {
+                        let node = unfiltered.node(function);
+                        if node.is_none() {
+                            warn!("tracked function `{}` not found; dropping it", function);
+                        }
+                        node
+                    })
+                    .collect::<Vec<_>>();
+                Baseline::record(unfiltered, functions)
+            } else if cg.starts().is_empty() {
+                let roots = unfiltered.filter_roots();
+                Baseline::record(&roots, roots.starts())
+            } else {
+                Baseline::record(&cg, cg.starts())
+            };
 
-// removes hashes like `::hfc5adc5d79855638`, if present
-fn dehash(demangled: &str) -> Option<&str> {
-    const HASH_LENGTH: usize = 19;
-
-    let len = demangled.as_bytes().len();
-    if len > HASH_LENGTH {
-        if demangled
-            .get(len - HASH_LENGTH..)
-            .map(|hash| hash.starts_with("::h"))
-            .unwrap_or(false)
-        {
-            Some(&demangled[..len - HASH_LENGTH])
+            baseline.save(path)?;
         } else {
-            None
+            let tolerance = matches
+                .value_of("tolerance")
+                .map(|arg| Tolerance::parse(arg).expect("UNREACHABLE"))
+                .unwrap_or(Tolerance::Bytes(0));
+            verdict = verdict.max(Baseline::load(path)?.check(unfiltered, tolerance));
         }
-    } else {
-        None
     }
-}
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum Target {
-    Other,
-    Thumbv6m,
-    Thumbv7m,
+    Ok(verdict.exit_code())
 }
 
-impl Target {
-    fn is_thumb(&self) -> bool {
-        match *self {
-            Target::Thumbv6m | Target::Thumbv7m => true,
-            Target::Other => false,
-        }
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use clap::{App, ErrorKind};
+
+    use super::ArtifactPaths;
+
+    fn parse(args: &[&str]) -> clap::Result<ArtifactPaths> {
+        let matches = App::new("cargo-call-stack")
+            .subcommand(super::analyze_subcommand())
+            .get_matches_from_safe(["cargo-call-stack", "analyze"].iter().chain(args))?;
+        Ok(ArtifactPaths::from_matches(
+            matches.subcommand_matches("analyze").unwrap(),
+        ))
+    }
+
+    #[test]
+    fn analyze() {
+        let paths = parse(&[
+            "--elf",
+            "app",
+            "--ll",
+            "app.ll",
+            "--target",
+            "thumbv7m-none-eabi",
+            "main",
+        ])
+        .unwrap();
+        assert_eq!(paths.elf, Path::new("app"));
+        assert_eq!(paths.ll, Path::new("app.ll"));
+        assert_eq!(paths.obj, None);
+        assert_eq!(paths.compiler_builtins_rlib, None);
+        assert_eq!(paths.compiler_builtins_ll, None);
+        assert_eq!(paths.target, "thumbv7m-none-eabi");
+
+        let paths = parse(&[
+            "--elf",
+            "app",
+            "--ll",
+            "app.ll",
+            "--obj",
+            "app.o",
+            "--compiler-builtins-rlib",
+            "libcompiler_builtins.rlib",
+            "--compiler-builtins-ll",
+            "compiler_builtins.ll",
+            "--target",
+            "thumbv6m-none-eabi",
+        ])
+        .unwrap();
+        assert_eq!(paths.obj.as_deref(), Some(Path::new("app.o")));
+        assert_eq!(
+            paths.compiler_builtins_rlib.as_deref(),
+            Some(Path::new("libcompiler_builtins.rlib"))
+        );
+        assert_eq!(
+            paths.compiler_builtins_ll.as_deref(),
+            Some(Path::new("compiler_builtins.ll"))
+        );
+
+        // the target can't be inferred from the artifacts
+        let e = parse(&["--elf", "app", "--ll", "app.ll"]).err().unwrap();
+        assert_eq!(e.kind, ErrorKind::MissingRequiredArgument);
+
+        // the stack usage of the rlib's functions is only used together with an object file
+        let e = parse(&[
+            "--elf",
+            "app",
+            "--ll",
+            "app.ll",
+            "--compiler-builtins-rlib",
+            "libcompiler_builtins.rlib",
+            "--target",
+            "thumbv7m-none-eabi",
+        ])
+        .err()
+        .unwrap();
+        assert_eq!(e.kind, ErrorKind::MissingRequiredArgument);
     }
 }
@@ -0,0 +1,100 @@
+//! Function name patterns (`--start`, `--start-regex`, `--hide`)
+//!
+//! Patterns are matched against the symbol name of a function, its demangled name and its demangled
+//! path without hashes (e.g. `app::foo`).
+
+use regex::Regex;
+
+use crate::Node;
+
+/// A pattern that selects functions by name
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// A single function; see `CallGraph::node`
+    Name(String),
+    /// All the functions whose name matches the regular expression
+    Regex(Regex),
+}
+
+impl Pattern {
+    /// Parses a glob pattern where `*` matches any sequence of characters and `?` matches any
+    /// single character
+    ///
+    /// Names without wildcards select a single function
+    pub fn glob(glob: &str) -> Self {
+        if !glob.contains(&['*', '?'][..]) {
+            return Pattern::Name(glob.to_owned());
+        }
+
+        let mut regex = String::from("^");
+        let mut literal = String::new();
+        for c in glob.chars() {
+            if c == '*' || c == '?' {
+                regex.push_str(&regex::escape(&literal));
+                literal.clear();
+                regex.push_str(if c == '*' { ".*" } else { "." });
+            } else {
+                literal.push(c);
+            }
+        }
+        regex.push_str(&regex::escape(&literal));
+        regex.push('$');
+
+        Pattern::Regex(Regex::new(&regex).expect("UNREACHABLE"))
+    }
+
+    /// Parses a regular expression; it matches if it's found anywhere in the name
+    pub fn regex(regex: &str) -> Result<Self, regex::Error> {
+        Regex::new(regex).map(Pattern::Regex)
+    }
+
+    pub(crate) fn matches(&self, node: &Node) -> bool {
+        match self {
+            Pattern::Name(name) => is(node, name),
+            Pattern::Regex(regex) => {
+                regex.is_match(&node.name)
+                    || regex.is_match(&node.demangled)
+                    || regex.is_match(&hashless(node))
+            }
+        }
+    }
+}
+
+// whether `name` refers to the function `node`
+pub(crate) fn is(node: &Node, name: &str) -> bool {
+    node.name == name
+        || node.demangled == name
+        || crate::dehash(&node.demangled) == Some(name)
+        || hashless(node) == name
+}
+
+// demangled name without hashes, e.g. `app[ebcb87bccc768a1e]::mid` -> `app::mid`
+fn hashless(node: &Node) -> String {
+    format!("{:#}", rustc_demangle::demangle(&node.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pattern;
+    use crate::Node;
+
+    #[test]
+    fn glob() {
+        let foo = Node("_ZN3app8task_foo17h0123456789abcdefE", None, false);
+        let fmt = Node("_ZN4core3fmt5write17h0123456789abcdefE", None, false);
+
+        let task = Pattern::glob("app::task_*");
+        assert!(task.matches(&foo));
+        assert!(!task.matches(&fmt));
+
+        let core = Pattern::glob("core::fmt::*");
+        assert!(core.matches(&fmt));
+
+        assert!(Pattern::glob("app::task_fo?").matches(&foo));
+        assert!(!Pattern::glob("app::task").matches(&foo));
+        assert!(Pattern::glob("app::task_foo").matches(&foo));
+
+        assert!(Pattern::regex("task_(foo|bar)").unwrap().matches(&foo));
+        assert!(Pattern::regex("(").is_err());
+    }
+}
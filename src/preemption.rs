@@ -0,0 +1,405 @@
+//! Whole-system stack usage of Cortex-M programs
+//!
+//! On Cortex-M exception handlers run on the same (main) stack as the thread code they preempt. A
+//! handler can only be preempted by handlers with a higher priority (lower number) so the deepest
+//! nesting of handlers contains at most one handler per priority level. Each preemption pushes an
+//! exception frame onto the stack.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
+
+use failure::format_err;
+use log::{error, warn};
+use petgraph::graph::{Graph, NodeIndex};
+use xmas_elf::{sections::SectionData, ElfFile};
+
+use crate::{max, rtic, Call, CallGraph, Max, Node};
+
+/// Exception frame without floating-point state, plus one word of alignment padding
+pub const BASIC_FRAME: u64 = 8 * 4 + 4;
+
+/// Exception frame with floating-point state, plus one word of alignment padding
+pub const EXTENDED_FRAME: u64 = 26 * 4 + 4;
+
+/// Size of the exception frame pushed on the `target`
+///
+/// On targets with a FPU we assume the worst: that the floating-point context is always stacked
+pub fn frame_size(target: &str) -> u64 {
+    if target.ends_with("eabihf") {
+        EXTENDED_FRAME
+    } else {
+        BASIC_FRAME
+    }
+}
+
+// exception numbers
+const NMI: usize = 2;
+const HARD_FAULT: usize = 3;
+
+/// Returns the addresses of the handlers in the vector table, indexed by exception number
+///
+/// The first entry of the vector table, the initial value of the stack pointer, is returned as
+/// `None`, as are the unused / reserved entries. The thumb bit is cleared from the addresses
+pub(crate) fn vector_table(elf: &ElfFile) -> Option<Vec<Option<u64>>> {
+    let sect = elf.find_section_by_name(".vector_table")?;
+    let data = match sect.get_data(elf).ok()? {
+        SectionData::Undefined(data) => data,
+        _ => return None,
+    };
+
+    Some(
+        data.chunks_exact(4)
+            .enumerate()
+            .map(|(i, word)| {
+                let addr = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+
+                if i == 0 || addr == 0 {
+                    None
+                } else {
+                    Some(u64::from(addr & !1))
+                }
+            })
+            .collect(),
+    )
+}
+
+/// The priority of the exception with number `exception`, if it's fixed
+pub(crate) fn fixed_priority(exception: usize) -> Option<i32> {
+    match exception {
+        NMI => Some(-2),
+        HARD_FAULT => Some(-1),
+        _ => None,
+    }
+}
+
+/// The priority of an exception handler
+///
+/// NVIC priorities and RTIC priorities use different scales (a lower number is more urgent in the
+/// former and less urgent in the latter) and the mapping between them depends on the device so
+/// handlers whose priorities are on different scales are assumed to be able to preempt each other
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) enum Priority {
+    /// A fixed priority or one set with `--priority`
+    Nvic(i32),
+    /// The (logical) priority of the RTIC tasks the handler runs
+    Rtic(u8),
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Priority::Nvic(prio) => write!(f, "{}", prio),
+            Priority::Rtic(prio) => write!(f, "rtic {}", prio),
+        }
+    }
+}
+
+// the priority of the handler of `exception`; `user` was set with `--priority` and `tasks` are the
+// RTIC tasks the handler runs
+fn priority(
+    exception: usize,
+    demangled: &str,
+    user: Option<i32>,
+    tasks: &[(&str, u8)],
+) -> Option<Priority> {
+    if let Some(fixed) = fixed_priority(exception) {
+        if user.is_some() {
+            warn!(
+                "`{}` has a fixed priority; ignoring `--priority`",
+                demangled
+            );
+        }
+
+        Some(Priority::Nvic(fixed))
+    } else if let Some(user) = user {
+        Some(Priority::Nvic(user))
+    } else {
+        // a dispatcher runs all its tasks at the same priority
+        let max = tasks.iter().map(|(_, prio)| *prio).max();
+        if tasks.iter().any(|(_, prio)| Some(*prio) != max) {
+            warn!(
+                "`{}` runs tasks with different priorities: {:?}",
+                demangled, tasks
+            );
+        }
+
+        max.map(Priority::Rtic)
+    }
+}
+
+/// Computes the maximum stack usage of the whole system and prints a report to stderr
+///
+/// `priorities` maps exception handlers to their priority and `rtic_tasks` maps RTIC tasks to
+/// their priority
+pub fn system(
+    cg: &CallGraph,
+    priorities: &HashMap<&str, i32>,
+    rtic_tasks: Option<&BTreeMap<String, u8>>,
+) -> Result<Option<Max>, failure::Error> {
+    let g = &cg.g;
+    let elf = ElfFile::new(cg.elf).map_err(failure::err_msg)?;
+    let vectors = vector_table(&elf).ok_or_else(|| {
+        format_err!("`.vector_table` section not found; is this a `cortex-m-rt` program?")
+    })?;
+
+    let node_at = |address: Option<u64>| {
+        address
+            .and_then(|address| cg.addr2name.get(&address))
+            .and_then(|name| cg.indices.get(*name).cloned())
+    };
+
+    // exception number 1 is the reset handler
+    let thread = vectors.get(1).cloned().and_then(node_at);
+    let mut seen = HashSet::new();
+    let mut used_tasks = HashSet::new();
+    let mut handlers = vec![];
+    for (exception, address) in vectors.iter().enumerate().skip(2) {
+        if let Some(node) = node_at(*address) {
+            if !seen.insert(node) {
+                // e.g. `DefaultHandler`
+                continue;
+            }
+
+            let name = &*g[node].name;
+            let demangled = &*g[node].demangled;
+            let user = priorities
+                .get(name)
+                .or_else(|| priorities.get(demangled))
+                .cloned();
+            let tasks = rtic_tasks
+                .map(|tasks| rtic::tasks_of(g, node, tasks))
+                .unwrap_or_default();
+            let priority = priority(exception, demangled, user, &tasks);
+
+            used_tasks.extend(tasks.iter().map(|(task, _)| *task));
+            handlers.push(Handler {
+                node,
+                priority,
+                tasks: tasks.iter().map(|(task, _)| task.to_string()).collect(),
+            });
+        }
+    }
+
+    for handler in priorities.keys() {
+        if !handlers
+            .iter()
+            .any(|h| *handler == g[h.node].name || *handler == g[h.node].demangled)
+        {
+            warn!("`{}` is not an exception handler", handler);
+        }
+    }
+
+    for task in rtic_tasks.iter().flat_map(|tasks| tasks.keys()) {
+        if !used_tasks.contains(&**task) {
+            warn!("task `{}` is not run by any interrupt handler", task);
+        }
+    }
+
+    match thread {
+        Some(thread) if g[thread].max.is_some() => {
+            let (total, levels) = analyze(g, thread, &handlers, frame_size(cg.target));
+            report(g, thread, total, &levels);
+            Ok(Some(total))
+        }
+
+        _ => {
+            error!("the reset handler has no stack usage information");
+            Ok(None)
+        }
+    }
+}
+
+/// An exception handler
+pub(crate) struct Handler {
+    pub node: NodeIndex,
+    /// `None` means that the priority is unknown and the handler could preempt any other handler
+    pub priority: Option<Priority>,
+    /// RTIC tasks run by this handler
+    pub tasks: Vec<String>,
+}
+
+/// A set of handlers that can't preempt each other
+pub(crate) struct Level<'a> {
+    pub priority: Option<Priority>,
+    pub handlers: Vec<&'a Handler>,
+    /// Maximum stack usage of the level, including the exception frame
+    pub max: Max,
+}
+
+/// Computes the worst-case stack usage of the whole system
+///
+/// `thread` is the entry point of the program (`Reset`) and `frame` the size of the exception
+/// frame
+pub(crate) fn analyze<'h>(
+    g: &Graph<Node, Call>,
+    thread: NodeIndex,
+    handlers: &'h [Handler],
+    frame: u64,
+) -> (Max, Vec<Level<'h>>) {
+    let mut by_priority = BTreeMap::<Priority, Vec<&Handler>>::new();
+    let mut levels = vec![];
+    for handler in handlers {
+        if let Some(priority) = handler.priority {
+            by_priority.entry(priority).or_default().push(handler);
+        } else {
+            // assume the worst: this handler is in a priority level of its own
+            levels.push((None, vec![handler]));
+        }
+    }
+    levels.extend(
+        by_priority
+            .into_iter()
+            .map(|(priority, handlers)| (Some(priority), handlers)),
+    );
+
+    let levels = levels
+        .into_iter()
+        .map(|(priority, handlers)| {
+            let max = handlers
+                .iter()
+                .map(|handler| g[handler.node].max.expect("UNREACHABLE"))
+                .fold(Max::Exact(0), max)
+                + Max::Exact(frame);
+
+            Level {
+                priority,
+                handlers,
+                max,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let total = levels
+        .iter()
+        .fold(g[thread].max.expect("UNREACHABLE"), |total, level| {
+            total + level.max
+        });
+
+    (total, levels)
+}
+
+/// Prints the contribution of each priority level to stderr
+pub(crate) fn report(g: &Graph<Node, Call>, thread: NodeIndex, total: Max, levels: &[Level]) {
+    eprintln!(
+        "whole-system max {} (including exception frames)",
+        total.in_bytes()
+    );
+    eprintln!("  {:>8}  {:>10}  handlers", "priority", "max");
+    eprintln!(
+        "  {:>8}  {:>10}  {}",
+        "thread",
+        g[thread].max.expect("UNREACHABLE").to_string(),
+        g[thread].demangled
+    );
+
+    for level in levels {
+        eprintln!(
+            "  {:>8}  {:>10}  {}",
+            level
+                .priority
+                .map(|prio| prio.to_string())
+                .unwrap_or_else(|| "?".to_string()),
+            level.max.to_string(),
+            level
+                .handlers
+                .iter()
+                .map(|handler| {
+                    let name = &g[handler.node].demangled;
+                    if handler.tasks.is_empty() {
+                        name.to_string()
+                    } else {
+                        format!("{} ({})", name, handler.tasks.join(", "))
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::graph::DiGraph;
+
+    use super::{Handler, Priority};
+    use crate::{Max, Node};
+
+    #[test]
+    fn levels() {
+        let mut g = DiGraph::new();
+        let reset = g.add_node(Node("Reset", Some(8), false));
+        let systick = g.add_node(Node("SysTick", Some(16), false));
+        let usart = g.add_node(Node("USART1", Some(32), false));
+        let exti = g.add_node(Node("EXTI0", Some(64), false));
+        g[reset].max = Some(Max::Exact(100));
+        g[systick].max = Some(Max::Exact(16));
+        g[usart].max = Some(Max::Exact(32));
+        g[exti].max = Some(Max::LowerBound(64));
+
+        // `SysTick` and `USART1` can't preempt each other
+        let handlers = [
+            Handler {
+                node: systick,
+                priority: Some(Priority::Nvic(1)),
+                tasks: vec![],
+            },
+            Handler {
+                node: usart,
+                priority: Some(Priority::Nvic(1)),
+                tasks: vec![],
+            },
+            Handler {
+                node: exti,
+                priority: None,
+                tasks: vec![],
+            },
+        ];
+
+        let (total, levels) = super::analyze(&g, reset, &handlers, 36);
+        assert_eq!(levels.len(), 2);
+        assert_eq!(total, Max::LowerBound(100 + (32 + 36) + (64 + 36)));
+    }
+
+    #[test]
+    fn scales() {
+        let mut g = DiGraph::new();
+        let reset = g.add_node(Node("Reset", Some(8), false));
+        let systick = g.add_node(Node("SysTick", Some(16), false));
+        let dispatcher = g.add_node(Node("UART0", Some(32), false));
+        g[reset].max = Some(Max::Exact(100));
+        g[systick].max = Some(Max::Exact(16));
+        g[dispatcher].max = Some(Max::Exact(32));
+
+        // `--priority SysTick=1` and `--rtic` with a task of priority 1 run by `UART0`
+        let systick_priority = super::priority(15, "SysTick", Some(1), &[]);
+        let dispatcher_priority = super::priority(16, "UART0", None, &[("foo", 1)]);
+        assert_eq!(systick_priority, Some(Priority::Nvic(1)));
+        assert_eq!(dispatcher_priority, Some(Priority::Rtic(1)));
+
+        // the priorities are on different scales so these handlers can preempt each other
+        let handlers = [
+            Handler {
+                node: systick,
+                priority: systick_priority,
+                tasks: vec![],
+            },
+            Handler {
+                node: dispatcher,
+                priority: dispatcher_priority,
+                tasks: vec!["foo".to_string()],
+            },
+        ];
+
+        let (total, levels) = super::analyze(&g, reset, &handlers, 36);
+        assert_eq!(levels.len(), 2);
+        assert_eq!(total, Max::Exact(100 + (16 + 36) + (32 + 36)));
+
+        // the fixed priorities of NMI and HardFault are on the NVIC scale
+        assert_eq!(
+            super::priority(2, "NMI", Some(3), &[]),
+            Some(Priority::Nvic(-2))
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! RTIC task priorities (`--rtic`)
+//!
+//! RTIC binds hardware tasks to interrupt handlers and runs software tasks from dispatchers, which
+//! are also interrupt handlers. All tasks with the same priority share a single stack frame "slot"
+//! because they can't preempt each other. The priorities file maps task names to their (logical)
+//! RTIC priority:
+//!
+//! ``` toml
+//! [tasks]
+//! foo = 1
+//! "app::bar" = 2
+//! ```
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use failure::format_err;
+use petgraph::graph::{Graph, NodeIndex};
+use serde::Deserialize;
+
+use crate::{Call, Node};
+
+#[derive(Deserialize)]
+struct File {
+    tasks: BTreeMap<String, u8>,
+}
+
+/// Loads a priorities file
+pub fn load(path: &Path) -> Result<BTreeMap<String, u8>, failure::Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format_err!("couldn't read `{}`: {}", path.display(), e))?;
+    let file: File = toml::from_str(&contents)
+        .map_err(|e| format_err!("couldn't parse `{}`: {}", path.display(), e))?;
+
+    Ok(file.tasks)
+}
+
+/// Returns the name of the task that `node` is, if any
+///
+/// A task name matches a function if it's the whole (dehashed) path of the function or its last
+/// components, e.g. `foo` and `app::foo` both match `app::foo`
+pub(crate) fn task_of<'t>(node: &Node, tasks: &'t BTreeMap<String, u8>) -> Option<&'t str> {
+    let path = crate::dehash(&node.demangled).unwrap_or(&node.demangled);
+
+    tasks
+        .keys()
+        .find(|task| {
+            path == *task
+                || (path.ends_with(&***task) && path[..path.len() - task.len()].ends_with("::"))
+        })
+        .map(|task| &**task)
+}
+
+/// Finds the tasks run by the interrupt `handler`
+///
+/// Tasks are searched for among the functions called by the handler; the search doesn't go into
+/// the tasks themselves
+pub(crate) fn tasks_of<'t>(
+    g: &Graph<Node, Call>,
+    handler: NodeIndex,
+    tasks: &'t BTreeMap<String, u8>,
+) -> Vec<(&'t str, u8)> {
+    if let Some(task) = task_of(&g[handler], tasks) {
+        return vec![(task, tasks[task])];
+    }
+
+    let mut found = vec![];
+    let mut seen = vec![handler];
+    let mut stack = vec![handler];
+    while let Some(node) = stack.pop() {
+        for callee in g.neighbors(node) {
+            if seen.contains(&callee) {
+                continue;
+            }
+            seen.push(callee);
+
+            if let Some(task) = task_of(&g[callee], tasks) {
+                found.push((task, tasks[task]));
+            } else {
+                stack.push(callee);
+            }
+        }
+    }
+
+    found.sort();
+    found.dedup();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use petgraph::graph::DiGraph;
+
+    use crate::{Call, Node};
+
+    #[test]
+    fn tasks_of() {
+        let mut tasks = BTreeMap::new();
+        tasks.insert("foo".to_owned(), 1);
+        tasks.insert("app::bar".to_owned(), 2);
+
+        let mut g = DiGraph::new();
+        let exti0 = g.add_node(Node("EXTI0", Some(0), false));
+        let run = g.add_node(Node(
+            "_ZN4rtic6export3run17h0123456789abcdefE",
+            Some(0),
+            false,
+        ));
+        let foo = g.add_node(Node("_ZN3app3foo17h0123456789abcdefE", Some(0), false));
+        let baz = g.add_node(Node("_ZN3app5foo_x17h0123456789abcdefE", Some(0), false));
+        let bar = g.add_node(Node("_ZN3app3bar17h0123456789abcdefE", Some(0), false));
+        g.add_edge(exti0, run, Call::Normal);
+        g.add_edge(run, foo, Call::Normal);
+        g.add_edge(run, baz, Call::Normal);
+        // NOTE not a task of `EXTI0` because it's only reachable through another task
+        g.add_edge(foo, bar, Call::Normal);
+
+        assert_eq!(super::tasks_of(&g, exti0, &tasks), vec![("foo", 1)]);
+        assert_eq!(super::tasks_of(&g, bar, &tasks), vec![("app::bar", 2)]);
+    }
+}
@@ -0,0 +1,168 @@
+//! Top stack consumers (`--format table`)
+
+use std::{
+    cmp::Reverse,
+    io::{self, Write},
+};
+
+use petgraph::graph::Graph;
+
+use crate::{Call, Local, Node};
+
+pub fn write<W>(g: &Graph<Node, Call>, top: usize, mut w: W) -> io::Result<()>
+where
+    W: Write,
+{
+    let nodes = g
+        .raw_nodes()
+        .iter()
+        .map(|node| &node.weight)
+        .collect::<Vec<_>>();
+
+    let mut by_local = nodes.clone();
+    // NOTE stable sort; ties remain in graph order and unknown stack usage goes last
+    by_local.sort_by_key(|node| match node.local {
+        Local::Exact(n) => (false, Reverse(n)),
+        Local::Unbounded => (false, Reverse(u64::MAX)),
+        Local::Unknown => (true, Reverse(0)),
+    });
+    let rows = by_local
+        .iter()
+        .take(top)
+        .map(|node| (node.local.to_string(), *node))
+        .collect::<Vec<_>>();
+    writeln!(w, "largest local stack usage:")?;
+    section(&mut w, "LOCAL", &rows)?;
+
+    let mut by_max = nodes
+        .into_iter()
+        .filter_map(|node| node.max.map(|max| (max, node)))
+        .collect::<Vec<_>>();
+    if !by_max.is_empty() {
+        by_max.sort_by_key(|(max, _)| Reverse(max.bytes()));
+        let rows = by_max
+            .iter()
+            .take(top)
+            .map(|(max, node)| (max.to_string(), *node))
+            .collect::<Vec<_>>();
+        writeln!(w, "\nlargest maximum stack usage:")?;
+        section(&mut w, "MAX", &rows)?;
+    }
+
+    Ok(())
+}
+
+// writes the rows aligned in columns
+fn section<W>(w: &mut W, value: &str, rows: &[(String, &Node)]) -> io::Result<()>
+where
+    W: Write,
+{
+    let crates = rows
+        .iter()
+        .map(|(_, node)| crate_of(node))
+        .collect::<Vec<_>>();
+    // NOTE the column is left out when the program has no debug info
+    let locations = if rows.iter().any(|(_, node)| node.location.is_some()) {
+        rows.iter()
+            .map(|(_, node)| {
+                node.location
+                    .as_ref()
+                    .map(|location| location.to_string())
+                    .unwrap_or_else(|| "-".to_string())
+            })
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+
+    let value_width = rows
+        .iter()
+        .map(|(value, _)| value.len())
+        .chain(Some(value.len()))
+        .max()
+        .unwrap_or(0);
+    let crate_width = crates
+        .iter()
+        .map(|krate| krate.len())
+        .chain(Some("CRATE".len()))
+        .max()
+        .unwrap_or(0);
+
+    let location_width = locations
+        .iter()
+        .map(|location| location.len())
+        .chain(Some("LOCATION".len()))
+        .max()
+        .unwrap_or(0);
+
+    write!(
+        w,
+        "  {:>vw$}  {:<cw$}  {:<10}  ",
+        value,
+        "CRATE",
+        "FICTITIOUS",
+        vw = value_width,
+        cw = crate_width
+    )?;
+    if !locations.is_empty() {
+        write!(w, "{:<lw$}  ", "LOCATION", lw = location_width)?;
+    }
+    writeln!(w, "FUNCTION")?;
+
+    for (i, ((value, node), krate)) in rows.iter().zip(&crates).enumerate() {
+        write!(
+            w,
+            "  {:>vw$}  {:<cw$}  {:<10}  ",
+            value,
+            krate,
+            if node.dashed { "yes" } else { "" },
+            vw = value_width,
+            cw = crate_width
+        )?;
+        if let Some(location) = locations.get(i) {
+            write!(w, "{:<lw$}  ", location, lw = location_width)?;
+        }
+        writeln!(w, "{}", node.demangled)?;
+    }
+
+    Ok(())
+}
+
+// the crate that defines the function; `-` for fictitious nodes and non-Rust functions
+fn crate_of(node: &Node) -> String {
+    if node.dashed || rustc_demangle::try_demangle(&node.name).is_err() {
+        return "-".to_string();
+    }
+
+    // for trait methods and inherent methods (`<T as Trait>::method`, `<T>::method`) this is the
+    // crate of the `Self` type
+    let path = format!("{:#}", rustc_demangle::demangle(&node.name));
+    let path = path.trim_start_matches(|c: char| !(c.is_alphanumeric() || c == '_'));
+    let path = ["dyn ", "mut ", "const "]
+        .iter()
+        .fold(path, |path, prefix| path.trim_start_matches(prefix));
+
+    path.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .next()
+        .unwrap_or("-")
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Node;
+
+    #[test]
+    fn crate_of() {
+        let krate = |name| super::crate_of(&Node(name, None, false));
+
+        assert_eq!(krate("_ZN3app3foo17h0123456789abcdefE"), "app");
+        assert_eq!(krate("_RNvCskf89lI5VpNi_3app3mid"), "app");
+        assert_eq!(
+            krate("_ZN45_$LT$app..Bar$u20$as$u20$core..fmt..Debug$GT$3fmt17h0123456789abcdefE"),
+            "app"
+        );
+        assert_eq!(krate("memcpy"), "-");
+        assert_eq!(super::crate_of(&Node("i32 ()*", Some(0), true)), "-");
+    }
+}
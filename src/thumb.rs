@@ -1,6 +1,19 @@
-/// Analyzes a subroutine and returns all the `BL` and `B` instructions in it, plus whether this
-/// function performs an indirect function call or not
-// NOTE we assume that `bytes` is always valid input so all errors are bugs
+use std::{
+    cmp,
+    collections::{hash_map::Entry, HashMap},
+};
+
+use failure::format_err;
+
+/// The `BL` and `B` instructions in a subroutine, whether it performs an indirect function call or
+/// not, whether it modifies the SP, its stack usage, if it could be computed, and whether it
+/// adjusts the SP by a run-time value
+pub type Analysis = (Vec<i32>, Vec<i32>, bool, bool, Option<u64>, bool);
+
+/// Analyzes a subroutine
+///
+/// An error is returned if the machine code can't be decoded, e.g. because the last instruction
+/// is cut short by the end of the subroutine
 // Reference: ARMv7-M Architecture Reference Manual (ARM DDI 0403E.b)
 // Reference: ARMv6-M Architecture Reference Manual (ARM DDI 0419D)
 pub fn analyze(
@@ -8,23 +21,7 @@ pub fn analyze(
     address: u32,
     v7: bool,
     tags: &[(u32, Tag)],
-) -> (Vec<i32>, Vec<i32>, bool, bool, Option<u64>) {
-    macro_rules! bug {
-        ($first:expr) => {
-            panic!(
-                "BUG: unknown instruction {:02x}{:02x}",
-                $first[1], $first[0]
-            )
-        };
-
-        ($first:expr, $second:expr) => {
-            panic!(
-                "BUG: unknown instruction {:02x}{:02x} {:02x}{:02x}",
-                $first[1], $first[0], $second[1], $second[0]
-            )
-        };
-    }
-
+) -> Result<Analysis, failure::Error> {
     // we want to know if any of the instructions modifies the SP (stack pointer). We use this
     // information to determine if the subroutine uses stack space or not. We want to detect the
     // following instructions:
@@ -35,12 +32,24 @@ pub fn analyze(
     // - f5ad 7d02       sub.w   sp, sp, #520    ; 0x208
     let mut modifies_sp = false;
 
-    // we'll try to compute the stack usage. We are mainly interested in `global_asm!` and
-    // `#[naked]` functions that only contain a single `asm!` block that only as trampolines. For
-    // that reason we'll give up the analysis if we encounter conditionals or loops, i.e.
-    // intra-branching, within the function. Analyzing those functions would be more work and won't
-    // help with our main goal of analyzing trampolines.
-    let mut stack = Some(0);
+    // we'll also compute the stack usage. To that end we record how each instruction changes the
+    // SP and the control flow, build a control flow graph from them and track the SP along all the
+    // paths through it; see `max_depth`. This gives a stack usage figure for functions that LLVM
+    // knows nothing about, like `global_asm!` routines
+    let mut insns = vec![];
+    // number of instructions that remain in the current IT block
+    let mut it = 0;
+    let mut next_literal = None;
+    let mut next_below_fp = None;
+
+    // we also want to know if the SP is adjusted by an amount held in a register, e.g. to allocate
+    // a variable-length array. The stack usage of such a function can't be bounded statically:
+    // - 4485            add     sp, r0
+    // - 4685            mov     sp, r0
+    // - ebad 0d00       sub.w   sp, sp, r0
+    // NOTE `mov sp, r7` restores the SP from the frame pointer in the epilogue so it's not
+    // considered an adjustment
+    let mut dynamic_sp = false;
 
     // we want to avoid writing a full blown decoder since we are only interested in a single type
     // of instruction. We know that instructions can be 16-bit or 32-bit so we'll only decode 16-bit
@@ -57,12 +66,12 @@ pub fn analyze(
                 // start of a data section
 
                 if let Some(tag) = tags.get(needle + 1) {
-                    assert_eq!(
-                        tag.1,
-                        Tag::Thumb,
-                        "BUG: expected a thumb tag at {:#10x} but found another data tag",
-                        tag.0
-                    );
+                    if tag.1 != Tag::Thumb {
+                        return Err(format_err!(
+                            "expected a thumb tag at {:#010x} but found another data tag",
+                            tag.0
+                        ));
+                    }
 
                     // skip the data section
                     let end = tag.0;
@@ -81,6 +90,21 @@ pub fn analyze(
             }
         }
 
+        // a word loaded from the literal pool by the previous instruction: (register, value)
+        let literal = next_literal.take();
+        // R7 minus some bytes computed by the previous instruction: (register, bytes)
+        let below_fp = next_below_fp.take();
+
+        insns.push(Insn {
+            offset: 2 * i,
+            size: 2,
+            sp: None,
+            flow: Flow::Next,
+            conditional: it != 0,
+        });
+        let insn = insns.last_mut().expect("UNREACHABLE");
+        it = if it != 0 { it - 1 } else { 0 };
+
         if matches(first, "0b010000_0101_xxx_xxx") {
             // A7.7.2 ADC (register) - T1
             continue;
@@ -95,12 +119,53 @@ pub fn analyze(
             continue;
         } else if matches(first, "0b010001_00_x_xxxx_xxx") {
             // A7.7.4 ADD (register) - T2
+            // NOTE this rule overlaps with the rules for `ADD (SP plus register)`
+            let rdn = ((first[0] >> 4) & 0b1000) | (first[0] & 0b111);
+            let rm = (first[0] >> 3) & 0b1111;
+
+            if rdn == SP {
+                // A7.7.6  ADD (SP plus register) - T2
+                match literal {
+                    // large stack frames on ARMv6-M
+                    // e.g. '4e05            ldr     r6, [pc, #20]'
+                    //      '44b5            add     sp, r6'
+                    Some((rt, value)) if rt == rm => {
+                        let bytes = -i64::from(value as i32);
+                        if bytes > 0 {
+                            modifies_sp = true;
+                        }
+                        insn.sp = Some(Sp::Adjust(bytes));
+                    }
+
+                    // e.g. '4485            add     sp, r0'
+                    _ => {
+                        modifies_sp = true;
+                        dynamic_sp = true;
+                    }
+                }
+            } else if rdn == PC {
+                // e.g. '4487            add     pc, r0'; a jump table
+                insn.flow = Flow::Unknown;
+            }
+
             continue;
         } else if matches(first, "0b1010_1_xxx_xxxxxxxx") {
             // A7.7.5  ADD (SP plus immediate) - T1
+            let rd = first[1] & 0b111;
+
+            // e.g. 'af02            add     r7, sp, #8'; sets up the frame pointer
+            if rd == FP {
+                let imm8 = first[0];
+                insn.sp = Some(Sp::SetFp(i64::from(imm8) << 2));
+            }
+
             continue;
         } else if matches(first, "0b1011_0000_0_xxxxxxx") {
             // A7.7.5  ADD (SP plus immediate) - T2
+            // e.g. 'b001            add     sp, #4'
+            let imm7 = first[0] & 0b0111_1111;
+            insn.sp = Some(Sp::Adjust(-(i64::from(imm7) << 2)));
+
             continue;
         } else if matches(first, "0b01000100_x_1101_xxx") {
             // A7.7.6  ADD (SP plus register) - T1
@@ -120,10 +185,13 @@ pub fn analyze(
         } else if matches(first, "0b010000_0100_xxx_xxx") {
             // A7.7.11  ASR (register) - T1
             continue;
-        } else if v7 && matches(first, "0b1101_1110_xxxxxxxx") {
+        } else if matches(first, "0b1101_1110_xxxxxxxx") {
             // NOTE we break the alphabetical order because the rule for `B` overlaps with the rule
             // for `UDF` but `UDF` takes precedence
-            // A7.7.191      UDF - T1
+            // A7.7.191      UDF - T1 (in ARMv7-M-ARM)
+            // A6.7.77       UDF - T1 (in ARMv6-M-ARM)
+            // execution doesn't continue after a trap
+            insn.flow = Flow::Exit;
             continue;
         } else if matches(first, "0b1101_1111_xxxxxxxx") {
             // NOTE we break the alphabetical order because the rule for `B` overlaps with the rule
@@ -133,8 +201,15 @@ pub fn analyze(
         } else if matches(first, "0b1101_xxxx_xxxxxxxx") {
             // A7.7.12  B - T1
             let cond = first[1] & 0b1111;
-            assert_ne!(cond, 0b1110); // UDF
-            assert_ne!(cond, 0b1111); // SVC
+            if cond == 0b1110 || cond == 0b1111 {
+                // these are `UDF` and `SVC`, which are decoded above
+                return Err(format_err!(
+                    "invalid condition in branch {:02x}{:02x} at {:#010x}",
+                    first[1],
+                    first[0],
+                    start
+                ));
+            }
             let imm8 = first[0] as i32;
             let mut imm32 = sign_extend(imm8 << 1, 9);
 
@@ -143,11 +218,7 @@ pub fn analyze(
             // (it's unclear to me why this needs to be `4` instead of `2` but that's what works)
             imm32 += 2 * i + 4;
 
-            if imm32 >= 0 && (imm32 as usize) < bytes.len() {
-                // this is an `if` or `loop`; give up the stack usage analysis
-                stack = None;
-            }
-
+            insn.flow = Flow::Conditional(imm32);
             bs.push(imm32);
         } else if matches(first, "0b11100_xxxxxxxxxxx") {
             // A7.7.12  B - T2
@@ -159,11 +230,7 @@ pub fn analyze(
             // (it's unclear to me why this needs to be `4` instead of `2` but that's what works)
             imm32 += 2 * i + 4;
 
-            if imm32 >= 0 && (imm32 as usize) < bytes.len() {
-                // this is an `if` or `loop`; give up the stack usage analysis
-                stack = None;
-            }
-
+            insn.flow = Flow::Jump(imm32);
             bs.push(imm32);
         } else if matches(first, "0b010000_1110_xxx_xxx") {
             // A7.7.16  BIC (register) - T1
@@ -179,11 +246,18 @@ pub fn analyze(
             let rm = (first[0] >> 3) & 0b1111;
 
             // `bx lr` is just a `return`
-            if rm != 0b1110 {
+            if rm != LR {
                 indirect = true;
             }
+
+            insn.flow = Flow::Exit;
         } else if v7 && matches(first, "0b1011_x_0_x_1_xxxxx_xxx") {
             // A7.7.21  CBNZ, CBZ - T1
+            let i_ = (first[1] >> 1) & 1;
+            let imm5 = (first[0] >> 3) & 0b1_1111;
+            let imm32 = (i32::from(i_) << 6) | (i32::from(imm5) << 1);
+
+            insn.flow = Flow::Conditional(2 * i + 4 + imm32);
             continue;
         } else if matches(first, "0b010000_1011_xxx_xxx") {
             // A7.7.26  CMN (register) - T1
@@ -203,8 +277,14 @@ pub fn analyze(
         } else if matches(first, "0b010000_0001_xxx_xxx") {
             // A7.7.35  EOR (register) - T1
             continue;
-        } else if v7 && matches(first, "0b1011_1111_xxxx_xxxx") {
+        } else if v7 && matches(first, "0b1011_1111_xxxx_xxxx") && first[0] & 0b1111 != 0 {
+            // NOTE a zero mask encodes a hint instruction (`NOP`, `YIELD`, `WFE`, `WFI`, `SEV`);
+            // those are handled below
             // A7.7.37  IT - T1
+            // the position of the lowest set bit of the mask encodes the length of the block
+            let mask = first[0] & 0b1111;
+            it = 4 - mask.trailing_zeros();
+
             continue;
         } else if matches(first, "0b1100_1_xxx_xxxxxxxx") {
             // A7.7.40  LDM, LDMIA, LDMFD - T1
@@ -217,6 +297,21 @@ pub fn analyze(
             continue;
         } else if matches(first, "0b01001_xxx_xxxxxxxx") {
             // A7.7.43  LDR (literal) - T1
+            let rt = first[1] & 0b111;
+            let imm32 = u32::from(first[0]) << 2;
+            // the base is the address of this instruction plus 4, aligned to a word boundary
+            let pos = ((start + 4) & !0b11) + imm32 - address;
+
+            if let Some(word) = bytes.get(pos as usize..pos as usize + 4) {
+                next_literal = Some((
+                    rt,
+                    u32::from(word[0])
+                        | u32::from(word[1]) << 8
+                        | u32::from(word[2]) << 16
+                        | u32::from(word[3]) << 24,
+                ));
+            }
+
             continue;
         } else if matches(first, "0b0101_100_xxx_xxx_xxx") {
             // A7.7.44  LDR (register) - T1
@@ -256,6 +351,37 @@ pub fn analyze(
             continue;
         } else if matches(first, "0b010001_10_x_xxxx_xxx") {
             // A7.7.76  MOV (register) - T1
+            let rd = ((first[0] >> 4) & 0b1000) | (first[0] & 0b111);
+            let rm = (first[0] >> 3) & 0b1111;
+
+            if rd == SP && rm == FP {
+                // e.g. '46bd            mov     sp, r7'; tears down the frame
+                insn.sp = Some(Sp::FromFp(0));
+            } else if rd == SP {
+                match below_fp {
+                    // ARMv6-M can't subtract from R7 into the SP
+                    // e.g. '1ffc            subs    r4, r7, #7'
+                    //      '3c01            subs    r4, #1'
+                    //      '46a5            mov     sp, r4'
+                    Some((rt, bytes)) if rt == rm => insn.sp = Some(Sp::FromFp(bytes)),
+
+                    // e.g. '4685            mov     sp, r0'
+                    _ => {
+                        modifies_sp = true;
+                        dynamic_sp = true;
+                    }
+                }
+            } else if rd == FP && rm == SP {
+                // e.g. '466f            mov     r7, sp'; sets up the frame pointer
+                insn.sp = Some(Sp::SetFp(0));
+            } else if rd == PC && rm == LR {
+                // `mov pc, lr` is a `return`
+                insn.flow = Flow::Exit;
+            } else if rd == PC {
+                // e.g. '4687            mov     pc, r0'; a jump table
+                insn.flow = Flow::Unknown;
+            }
+
             continue;
         } else if matches(first, "0b000_00_00000_xxx_xxx") {
             // A7.7.76  MOV (register) - T2
@@ -275,6 +401,16 @@ pub fn analyze(
             continue;
         } else if matches(first, "0b1011_1_10_x_xxxxxxxx") {
             // A7.7.98  POP - T1
+            // e.g. 'bd80            pop     {r7, pc}'
+            let p = first[1] & 1;
+            let register_list = first[0];
+            let registers = (u16::from(p) << 15) | u16::from(register_list);
+            insn.sp = Some(Sp::Adjust(-4 * i64::from(registers.count_ones())));
+
+            if p == 1 {
+                insn.flow = Flow::Exit;
+            }
+
             continue;
         } else if matches(first, "0b1011_0_10_x_xxxxxxxx") {
             // A7.7.99  PUSH - T1
@@ -284,9 +420,7 @@ pub fn analyze(
             let m = first[1] & 1;
             let register_list = first[0];
             let register = (u16::from(m) << 14) | u16::from(register_list);
-            if let Some(stack) = stack.as_mut() {
-                *stack += 4 * u64::from(register.count_ones());
-            }
+            insn.sp = Some(Sp::Adjust(4 * i64::from(register.count_ones())));
 
             continue;
         } else if matches(first, "0b1011_1010_00_xxx_xxx") {
@@ -336,9 +470,26 @@ pub fn analyze(
             continue;
         } else if matches(first, "0b000_11_1_1_xxx_xxx_xxx") {
             // A7.7.171      SUB (immediate) - T1
+            let imm3 = ((first[1] & 1) << 2) | (first[0] >> 6);
+            let rn = (first[0] >> 3) & 0b111;
+            let rd = first[0] & 0b111;
+
+            if rn == FP {
+                next_below_fp = Some((rd, i64::from(imm3)));
+            }
+
             continue;
         } else if matches(first, "0b001_11_xxx_xxxxxxxx") {
             // A7.7.171      SUB (immediate) - T2
+            let rdn = first[1] & 0b111;
+            let imm8 = first[0];
+
+            if let Some((rt, bytes)) = below_fp {
+                if rt == rdn {
+                    next_below_fp = Some((rdn, bytes + i64::from(imm8)));
+                }
+            }
+
             continue;
         } else if matches(first, "0b000_11_0_1_xxx_xxx_xxx") {
             // A7.7.172      SUB (register) - T1
@@ -350,10 +501,7 @@ pub fn analyze(
 
             let imm7 = first[0] & 0b0111_1111;
             let imm32 = u32::from(imm7) << 2;
-
-            if let Some(stack) = stack.as_mut() {
-                *stack += u64::from(imm32);
-            }
+            insn.sp = Some(Sp::Adjust(i64::from(imm32)));
 
             continue;
         } else if matches(first, "0b1011_0010_01_xxx_xxx") {
@@ -381,9 +529,17 @@ pub fn analyze(
             // A7.7.260      YIELD - T1
             continue;
         } else {
-            let second = halfwords.next().unwrap_or_else(|| bug!(first)).0;
-
-            const SP: u8 = 0b1101;
+            let second = if let Some((second, _)) = halfwords.next() {
+                second
+            } else {
+                return Err(format_err!(
+                    "truncated instruction {:02x}{:02x} at {:#010x}",
+                    first[1],
+                    first[0],
+                    start
+                ));
+            };
+            insn.size = 4;
 
             if v7
                 && matches(first, "0b11101_00_100_x_0_xxxx")
@@ -399,9 +555,25 @@ pub fn analyze(
                         ((u16::from(second[1]) & 0b0001_1111) << 8) | u16::from(second[0]);
                     let m = (second[1] >> 6) & 1;
                     let registers = (u16::from(m) << 14) | register_list;
+                    insn.sp = Some(Sp::Adjust(4 * i64::from(registers.count_ones())));
+                }
+            } else if v7
+                && matches(first, "0b11101_00_010_1_1_xxxx")
+                && matches(second, "0bxx_0_xxxxxxxxxxxxx")
+            {
+                // A7.7.41       LDM, LDMIA, LDMFD - T2
+                // e.g. 'e8bd 81f0       pop.w   {r4, r5, r6, r7, r8, pc}'
+                let rn = first[0] & 0b1111;
+                if rn == SP {
+                    let register_list =
+                        ((u16::from(second[1]) & 0b0001_1111) << 8) | u16::from(second[0]);
+                    let p = second[1] >> 7;
+                    let m = (second[1] >> 6) & 1;
+                    let registers = (u16::from(p) << 15) | (u16::from(m) << 14) | register_list;
+                    insn.sp = Some(Sp::Adjust(-4 * i64::from(registers.count_ones())));
 
-                    if let Some(stack) = stack.as_mut() {
-                        *stack += 4 * u64::from(registers.count_ones());
+                    if p == 1 {
+                        insn.flow = Flow::Exit;
                     }
                 }
             } else if v7
@@ -419,10 +591,57 @@ pub fn analyze(
                     let imm32 = thumb_expand_imm(
                         (u16::from(i) << 11) | (u16::from(imm3) << 8) | u16::from(imm8),
                     );
-
-                    if let Some(stack) = stack.as_mut() {
-                        *stack += u64::from(imm32);
-                    }
+                    insn.sp = Some(Sp::Adjust(i64::from(imm32)));
+                }
+            } else if v7
+                && matches(first, "0b11110_x_10_1010_1101")
+                && matches(second, "0b0_xxx_xxxx_xxxxxxxx")
+            {
+                // A7.7.173      SUB (SP minus immediate) - T3
+                // e.g. 'f2ad 4d04       subw    sp, sp, #1028'
+                let rd = second[1] & 0b1111;
+                if rd == SP {
+                    modifies_sp = true;
+                    insn.sp = Some(Sp::Adjust(i64::from(imm12(first, second))));
+                }
+            } else if v7
+                && matches(first, "0b11110_x_0_1000_x_1101")
+                && matches(second, "0b0_xxx_xxxx_xxxxxxxx")
+            {
+                // A7.7.5        ADD (SP plus immediate) - T3
+                // e.g. 'f50d 7d02       add.w   sp, sp, #520'
+                let rd = second[1] & 0b1111;
+                let imm32 = thumb_expand_imm(imm12(first, second));
+                if rd == SP {
+                    insn.sp = Some(Sp::Adjust(-i64::from(imm32)));
+                } else if rd == FP {
+                    insn.sp = Some(Sp::SetFp(i64::from(imm32)));
+                }
+            } else if v7
+                && matches(first, "0b11110_x_10_0000_1101")
+                && matches(second, "0b0_xxx_xxxx_xxxxxxxx")
+            {
+                // A7.7.5        ADD (SP plus immediate) - T4
+                // e.g. 'f20d 4d04       addw    sp, sp, #1028'
+                let rd = second[1] & 0b1111;
+                let imm32 = imm12(first, second);
+                if rd == SP {
+                    insn.sp = Some(Sp::Adjust(-i64::from(imm32)));
+                } else if rd == FP {
+                    insn.sp = Some(Sp::SetFp(i64::from(imm32)));
+                }
+            } else if v7
+                && (matches(first, "0b11101_01_1000_x_1101")
+                    || matches(first, "0b11101_01_1101_x_1101"))
+                && matches(second, "0b0_xxx_xxxx_xxxxxxxx")
+            {
+                // A7.7.6        ADD (SP plus register) - T3
+                // A7.7.174      SUB (SP minus register) - T1
+                // e.g. 'ebad 0d00       sub.w   sp, sp, r0'
+                let rd = second[1] & 0b1111;
+                if rd == SP {
+                    modifies_sp = true;
+                    dynamic_sp = true;
                 }
             } else if v7
                 && matches(first, "0b1110_110_1_0_x_1_0_1101")
@@ -431,12 +650,9 @@ pub fn analyze(
                 // A7.7.249      VPUSH - T1
                 modifies_sp = true;
 
-                let imm8 = second[0] & 0b1111_1111;
+                let imm8 = second[0];
                 let imm32 = u32::from(imm8) << 2;
-
-                if let Some(stack) = stack.as_mut() {
-                    *stack += u64::from(imm32);
-                }
+                insn.sp = Some(Sp::Adjust(i64::from(imm32)));
             } else if v7
                 && matches(first, "0b1110_110_1_0_x_1_0_1101")
                 && matches(second, "0bxxxx_1010_xxxxxxxx")
@@ -444,12 +660,26 @@ pub fn analyze(
                 // A7.7.249      VPUSH - T2
                 modifies_sp = true;
 
-                let imm8 = second[0] & 0b1111_1111;
+                let imm8 = second[0];
                 let imm32 = u32::from(imm8) << 2;
-
-                if let Some(stack) = stack.as_mut() {
-                    *stack += u64::from(imm32);
-                }
+                insn.sp = Some(Sp::Adjust(i64::from(imm32)));
+            } else if v7
+                && matches(first, "0b1110_110_0_1_x_1_1_1101")
+                && (matches(second, "0bxxxx_1011_xxxxxxxx")
+                    || matches(second, "0bxxxx_1010_xxxxxxxx"))
+            {
+                // A7.7.248      VPOP - T1, T2
+                // e.g. 'ecbd 8b02       vpop    {d8}'
+                let imm8 = second[0];
+                let imm32 = u32::from(imm8) << 2;
+                insn.sp = Some(Sp::Adjust(-i64::from(imm32)));
+            } else if v7
+                && matches(first, "0b11101_00_0110_1_xxxx")
+                && matches(second, "0b1111_0000_000_x_xxxx")
+            {
+                // A7.7.185      TBB, TBH - T1
+                // the targets are in a table in memory
+                insn.flow = Flow::Unknown;
             } else if v7
                 && matches(first, "0b11110_x_xxxxxxxxxx")
                 && matches(second, "0b10_x_0_x_xxxxxxxxxxx")
@@ -479,11 +709,7 @@ pub fn analyze(
                 // accordingly
                 imm32 += 2 * i + 4;
 
-                if imm32 >= 0 && (imm32 as usize) < bytes.len() {
-                    // this is an `if` or `loop`; give up the stack usage analysis
-                    stack = None;
-                }
-
+                insn.flow = Flow::Conditional(imm32);
                 bs.push(imm32);
             } else if v7
                 && matches(first, "0b11110_x_xxxxxxxxxx")
@@ -512,11 +738,7 @@ pub fn analyze(
                 // accordingly
                 imm32 += 2 * i + 4;
 
-                if imm32 >= 0 && (imm32 as usize) < bytes.len() {
-                    // this is an `if` or `loop`; give up the stack usage analysis
-                    stack = None;
-                }
-
+                insn.flow = Flow::Jump(imm32);
                 bs.push(imm32);
             } else if matches(first, "0b11110_x_xxxxxxxxxx")
                 && matches(second, "0b11_x_1_x_xxxxxxxxxxx")
@@ -554,12 +776,29 @@ pub fn analyze(
                 if rn == SP {
                     modifies_sp = true;
 
-                    let imm8 = second[0] & 0b1111_1111;
+                    let imm8 = second[0];
                     let imm32 = u32::from(imm8);
+                    insn.sp = Some(Sp::Adjust(i64::from(imm32)));
+                }
+            } else if matches(first, "0b11111_00_0_x_10_1_xxxx") {
+                // A7.7.42       LDR (immediate) - T3, T4
+                // A7.7.43       LDR (literal) - T2
+                // A7.7.44       LDR (register) - T2
+                let rn = first[0] & 0b1111;
+                let rt = second[1] >> 4;
+                let t4 = first[1] & 1 == 0 && matches(second, "0bxxxx_1xxx_xxxxxxxx");
 
-                    if let Some(stack) = stack.as_mut() {
-                        *stack += u64::from(imm32);
+                if rn == SP && t4 && matches(second, "0bxxxx_1x11_xxxxxxxx") {
+                    // e.g. 'f85d bb04       ldr     r11, [sp], #4'
+                    let imm8 = second[0];
+                    insn.sp = Some(Sp::Adjust(-i64::from(imm8)));
+
+                    if rt == PC {
+                        insn.flow = Flow::Exit;
                     }
+                } else if rt == PC {
+                    // e.g. 'f85f f020       ldr.w   pc, [pc, r0, lsl #2]'; a jump table
+                    insn.flow = Flow::Unknown;
                 }
             } else {
                 // some other 32-bit instruction
@@ -568,7 +807,209 @@ pub fn analyze(
         }
     }
 
-    (bls, bs, indirect, modifies_sp, stack)
+    let stack = if dynamic_sp {
+        None
+    } else {
+        max_depth(&insns, bytes.len())
+    };
+
+    Ok((bls, bs, indirect, modifies_sp, stack, dynamic_sp))
+}
+
+const FP: u8 = 0b0111;
+const SP: u8 = 0b1101;
+const LR: u8 = 0b1110;
+const PC: u8 = 0b1111;
+
+// a decoded instruction; only what matters to the stack usage analysis
+#[derive(Clone, Copy, Debug)]
+struct Insn {
+    // offset from the start of the subroutine, in bytes
+    offset: i32,
+    // 2 or 4 bytes
+    size: i32,
+    sp: Option<Sp>,
+    flow: Flow,
+    // inside an IT block
+    conditional: bool,
+}
+
+// how an instruction changes the SP
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sp {
+    // the stack grows (positive) or shrinks (negative) by this many bytes
+    Adjust(i64),
+    // the frame pointer, R7, is set to SP + this many bytes
+    SetFp(i64),
+    // the SP is set to the frame pointer, R7, minus this many bytes
+    FromFp(i64),
+}
+
+// where execution continues after an instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Flow {
+    // the next instruction
+    Next,
+    // the instruction at this offset, if a condition holds, or the next instruction
+    Conditional(i32),
+    // the instruction at this offset
+    Jump(i32),
+    // outside the subroutine: return, tail call or trap
+    Exit,
+    // somewhere computed at runtime, e.g. a jump table
+    Unknown,
+}
+
+// the maximum stack depth, in bytes, over all the paths through the control flow graph of the
+// subroutine; `None` if the SP can't be tracked, e.g. it differs between the incoming edges of a
+// basic block or the subroutine jumps to a computed address
+fn max_depth(insns: &[Insn], len: usize) -> Option<u64> {
+    let index = |offset: i32| insns.binary_search_by_key(&offset, |insn| insn.offset).ok();
+
+    // branches that leave the subroutine are tail calls
+    let intra = |offset: i32| offset >= 0 && (offset as usize) < len;
+
+    // for each instruction: the index of the branch target, if it's within the subroutine, and
+    // whether execution can continue with the next instruction
+    let mut successors = Vec::with_capacity(insns.len());
+    for insn in insns {
+        if insn.conditional && insn.sp.is_some() && insn.flow != Flow::Exit {
+            // the SP depends on the condition
+            return None;
+        }
+
+        let (target, next) = match insn.flow {
+            Flow::Next => (None, true),
+            Flow::Conditional(target) if intra(target) => (Some(target), true),
+            Flow::Conditional(_) => (None, true),
+            Flow::Jump(target) if intra(target) => (Some(target), insn.conditional),
+            Flow::Jump(_) | Flow::Exit | Flow::Unknown => (None, insn.conditional),
+        };
+
+        let target = match target {
+            // a branch into the middle of an instruction or into data
+            Some(target) => Some(index(target)?),
+            None => None,
+        };
+        successors.push((target, next));
+    }
+
+    // basic blocks start at the entry point, at branch targets, after branches and after data
+    let mut leaders = vec![false; insns.len()];
+    if let Some(entry) = leaders.first_mut() {
+        *entry = true;
+    }
+    for (i, (target, next)) in successors.iter().enumerate() {
+        if let Some(target) = target {
+            leaders[*target] = true;
+        }
+
+        if target.is_some() || !next || !falls_into(insns, i) {
+            if let Some(leader) = leaders.get_mut(i + 1) {
+                *leader = true;
+            }
+        }
+    }
+
+    // the targets of computed jumps are not known but, in compiler generated code, these are jump
+    // tables whose targets can't be reached by falling through so the SP at the jump is propagated
+    // to all the basic blocks that can't be reached by falling through; the join point check below
+    // catches the blocks that are reached in some other way with a different SP
+    let orphans = (1..insns.len())
+        .filter(|i| !successors[i - 1].1 || !falls_into(insns, i - 1))
+        .collect::<Vec<_>>();
+
+    // the stack depth and the frame pointer, relative to the SP on entry, at the start of each
+    // basic block that has been reached
+    let mut states: HashMap<usize, (i64, Option<i64>)> = HashMap::new();
+    let mut max = 0;
+    let mut worklist = vec![];
+    if !insns.is_empty() {
+        states.insert(0, (0, None));
+        worklist.push(0);
+    }
+    while let Some(leader) = worklist.pop() {
+        let (mut depth, mut fp) = states[&leader];
+
+        let mut i = leader;
+        let (target, next) = loop {
+            // NOTE a conditional exit, e.g. `popeq {r7, pc}`, doesn't change the SP of the
+            // instructions that follow it
+            let sp = if insns[i].conditional {
+                None
+            } else {
+                insns[i].sp
+            };
+            match sp {
+                Some(Sp::Adjust(bytes)) => depth += bytes,
+                Some(Sp::SetFp(bytes)) => fp = Some(depth - bytes),
+                Some(Sp::FromFp(bytes)) => depth = fp? + bytes,
+                None => {}
+            }
+
+            if depth < 0 {
+                // popped more than it pushed
+                return None;
+            }
+            max = cmp::max(max, depth);
+
+            let (target, next) = successors[i];
+            if target.is_some() || !next || leaders.get(i + 1).cloned().unwrap_or(true) {
+                break (target, next);
+            }
+            i += 1;
+        };
+
+        // NOTE falling through into data or past the end of the subroutine means that the last
+        // instruction was a call to a divergent function
+        let next = if next && falls_into(insns, i) {
+            Some(i + 1)
+        } else {
+            None
+        };
+        let computed = if insns[i].flow == Flow::Unknown {
+            &orphans[..]
+        } else {
+            &[]
+        };
+
+        for successor in target
+            .into_iter()
+            .chain(next)
+            .chain(computed.iter().cloned())
+        {
+            match states.entry(successor) {
+                Entry::Occupied(mut entry) => {
+                    let state = entry.get_mut();
+                    if state.0 != depth {
+                        // join point with inconsistent SP
+                        return None;
+                    }
+
+                    if state.1.is_some() && state.1 != fp {
+                        // the frame pointer is only known along some paths
+                        state.1 = None;
+                        worklist.push(successor);
+                    }
+                }
+
+                Entry::Vacant(entry) => {
+                    entry.insert((depth, fp));
+                    worklist.push(successor);
+                }
+            }
+        }
+    }
+
+    Some(max as u64)
+}
+
+// whether the instruction that follows `insns[i]` is right after it, i.e. not after some data
+fn falls_into(insns: &[Insn], i: usize) -> bool {
+    insns
+        .get(i + 1)
+        .map(|next| next.offset == insns[i].offset + insns[i].size)
+        .unwrap_or(false)
 }
 
 fn matches(bytes: &[u8], pattern: &str) -> bool {
@@ -590,6 +1031,14 @@ fn matches(bytes: &[u8], pattern: &str) -> bool {
     (first & mask1 == value1) && (second & mask2 == value2)
 }
 
+// `i:imm3:imm8` of a 32-bit instruction
+fn imm12(first: &[u8], second: &[u8]) -> u16 {
+    let i = (first[1] >> 2) & 1;
+    let imm3 = (second[1] >> 4) & 0b0111;
+    let imm8 = second[0];
+    (u16::from(i) << 11) | (u16::from(imm3) << 8) | u16::from(imm8)
+}
+
 fn sign_extend(x: i32, nbits: u32) -> i32 {
     let shift = 32 - nbits;
     x.wrapping_shl(shift).wrapping_shr(shift)
@@ -634,62 +1083,211 @@ mod tests {
     #[test]
     fn sanity() {
         assert_eq!(
-            super::analyze(&[0xff, 0xf7, 0xe4, 0xfe], 0, false, &[]).0,
+            super::analyze(&[0xff, 0xf7, 0xe4, 0xfe], 0, false, &[])
+                .unwrap()
+                .0,
             vec![-568 + 4]
         );
 
         assert_eq!(
-            super::analyze(&[0x00, 0xf0, 0x2a, 0xfa], 0, false, &[]).0,
+            super::analyze(&[0x00, 0xf0, 0x2a, 0xfa], 0, false, &[])
+                .unwrap()
+                .0,
             vec![1108 + 4]
         );
 
         assert_eq!(
-            super::analyze(&[0x03, 0xe2], 0, false, &[]).1,
+            super::analyze(&[0x03, 0xe2], 0, false, &[]).unwrap().1,
             vec![1030 + 4]
         );
 
         // UDF
         assert_eq!(
-            super::analyze(&[0xfe, 0xde], 0, true, &[]),
-            (vec![], vec![], false, false, Some(0))
+            super::analyze(&[0xfe, 0xde], 0, true, &[]).unwrap(),
+            (vec![], vec![], false, false, Some(0), false)
         );
+
+        // UDF on ARMv6-M
+        assert_eq!(
+            super::analyze(&[0xfe, 0xde], 0, false, &[]).unwrap(),
+            (vec![], vec![], false, false, Some(0), false)
+        );
+
+        // first half of a `BL`
+        assert!(super::analyze(&[0xff, 0xf7], 0, false, &[]).is_err());
+
+        // data tag followed by another data tag
+        let tags = [(0, super::Tag::Data), (4, super::Tag::Data)];
+        assert!(super::analyze(&[0; 8], 0, false, &tags).is_err());
     }
 
     #[test]
     fn modifies_sp() {
         // bf00            nop
-        let nop = super::analyze(&[0x00, 0xbf], 0, false, &[]);
+        let nop = super::analyze(&[0x00, 0xbf], 0, false, &[]).unwrap();
         assert!(!nop.3);
         assert_eq!(nop.4, Some(0));
 
         // b081            sub     sp, #4
-        let sub = super::analyze(&[0x81, 0xb0], 0, false, &[]);
+        let sub = super::analyze(&[0x81, 0xb0], 0, false, &[]).unwrap();
         assert!(sub.3);
         assert_eq!(sub.4, Some(4));
 
         // b580            push    {r7, lr}
-        let push = super::analyze(&[0x80, 0xb5], 0, false, &[]);
+        let push = super::analyze(&[0x80, 0xb5], 0, false, &[]).unwrap();
         assert!(push.3);
         assert_eq!(push.4, Some(8));
 
         // e92d 41f0       stmdb   sp!, {r4, r5, r6, r7, r8, lr}
-        let stmdb = super::analyze(&[0x2d, 0xe9, 0xf0, 0x41], 0, true, &[]);
+        let stmdb = super::analyze(&[0x2d, 0xe9, 0xf0, 0x41], 0, true, &[]).unwrap();
         assert!(stmdb.3);
         assert_eq!(stmdb.4, Some(24));
 
         // ed2d 8b02       vpush   {d8}
-        let vpush = super::analyze(&[0x2d, 0xed, 0x02, 0x8b], 0, true, &[]);
+        let vpush = super::analyze(&[0x2d, 0xed, 0x02, 0x8b], 0, true, &[]).unwrap();
         assert!(vpush.3);
         assert_eq!(vpush.4, Some(8));
 
         // f5ad 7d02       sub.w   sp, sp, #520    ; 0x208
-        let subw = super::analyze(&[0xad, 0xf5, 0x02, 0x7d], 0, true, &[]);
+        let subw = super::analyze(&[0xad, 0xf5, 0x02, 0x7d], 0, true, &[]).unwrap();
         assert!(subw.3);
         assert_eq!(subw.4, Some(520));
 
         // f84d bd04       str     r11, [sp, #-4]!
-        let str = super::analyze(&[0x4d, 0xf8, 0x04, 0xbd], 0, true, &[]);
+        let str = super::analyze(&[0x4d, 0xf8, 0x04, 0xbd], 0, true, &[]).unwrap();
         assert!(str.3);
         assert_eq!(str.4, Some(4));
     }
+
+    #[test]
+    fn dynamic_sp() {
+        // 4485            add     sp, r0
+        let add = super::analyze(&[0x85, 0x44], 0, false, &[]).unwrap();
+        assert!(add.3);
+        assert!(add.5);
+        assert_eq!(add.4, None);
+
+        // 4685            mov     sp, r0
+        let mov = super::analyze(&[0x85, 0x46], 0, false, &[]).unwrap();
+        assert!(mov.5);
+        assert_eq!(mov.4, None);
+
+        // 46bd            mov     sp, r7
+        let restore = super::analyze(&[0xbd, 0x46], 0, false, &[]).unwrap();
+        assert!(!restore.5);
+
+        // ebad 0d00       sub.w   sp, sp, r0
+        let subw = super::analyze(&[0xad, 0xeb, 0x00, 0x0d], 0, true, &[]).unwrap();
+        assert!(subw.3);
+        assert!(subw.5);
+        assert_eq!(subw.4, None);
+
+        // b081            sub     sp, #4
+        assert!(!super::analyze(&[0x81, 0xb0], 0, false, &[]).unwrap().5);
+    }
+
+    #[test]
+    fn control_flow() {
+        // 0: b510            push    {r4, lr}
+        // 2: b082            sub     sp, #8
+        // 4: 3801            subs    r0, #1
+        // 6: d1fd            bne     0x4
+        // 8: b002            add     sp, #8
+        // a: bd10            pop     {r4, pc}
+        let code = [
+            0x10, 0xb5, 0x82, 0xb0, 0x01, 0x38, 0xfd, 0xd1, 0x02, 0xb0, 0x10, 0xbd,
+        ];
+        assert_eq!(super::analyze(&code, 0, false, &[]).unwrap().4, Some(16));
+        assert_eq!(super::analyze(&code, 0, true, &[]).unwrap().4, Some(16));
+
+        // shrink wrapping
+        // 0: b108            cbz     r0, 0x6
+        // 2: b580            push    {r7, lr}
+        // 4: bd80            pop     {r7, pc}
+        // 6: 4770            bx      lr
+        let code = [0x08, 0xb1, 0x80, 0xb5, 0x80, 0xbd, 0x70, 0x47];
+        assert_eq!(super::analyze(&code, 0, true, &[]).unwrap().4, Some(8));
+
+        // conditional return
+        // 0: b580            push    {r7, lr}
+        // 2: 2800            cmp     r0, #0
+        // 4: bf08            it      eq
+        // 6: bd80            popeq   {r7, pc}
+        // 8: b081            sub     sp, #4
+        // a: b001            add     sp, #4
+        // c: bd80            pop     {r7, pc}
+        let code = [
+            0x80, 0xb5, 0x00, 0x28, 0x08, 0xbf, 0x80, 0xbd, 0x81, 0xb0, 0x01, 0xb0, 0x80, 0xbd,
+        ];
+        assert_eq!(super::analyze(&code, 0, true, &[]).unwrap().4, Some(12));
+
+        // hints are not `IT` instructions
+        // 0: b580            push    {r7, lr}
+        // 2: bf00            nop
+        // 4: bd80            pop     {r7, pc}
+        let code = [0x80, 0xb5, 0x00, 0xbf, 0x80, 0xbd];
+        assert_eq!(super::analyze(&code, 0, true, &[]).unwrap().4, Some(8));
+
+        // 0: bf30            wfi
+        // 2: b580            push    {r7, lr}
+        // 4: bd80            pop     {r7, pc}
+        let code = [0x30, 0xbf, 0x80, 0xb5, 0x80, 0xbd];
+        assert_eq!(super::analyze(&code, 0, true, &[]).unwrap().4, Some(8));
+
+        // frame pointer
+        // 0: b580            push    {r7, lr}
+        // 2: 466f            mov     r7, sp
+        // 4: b084            sub     sp, #16
+        // 6: 46bd            mov     sp, r7
+        // 8: bd80            pop     {r7, pc}
+        let code = [0x80, 0xb5, 0x6f, 0x46, 0x84, 0xb0, 0xbd, 0x46, 0x80, 0xbd];
+        assert_eq!(super::analyze(&code, 0, false, &[]).unwrap().4, Some(24));
+
+        //  0: b5d0           push    {r4, r6, r7, lr}
+        //  2: af02           add     r7, sp, #8
+        //  4: b084           sub     sp, #16
+        //  6: 1ffc           subs    r4, r7, #7
+        //  8: 3c01           subs    r4, #1
+        //  a: 46a5           mov     sp, r4
+        //  c: bdd0           pop     {r4, r6, r7, pc}
+        let code = [
+            0xd0, 0xb5, 0x02, 0xaf, 0x84, 0xb0, 0xfc, 0x1f, 0x01, 0x3c, 0xa5, 0x46, 0xd0, 0xbd,
+        ];
+        let frame_pointer = super::analyze(&code, 0, false, &[]).unwrap();
+        assert!(!frame_pointer.5);
+        assert_eq!(frame_pointer.4, Some(32));
+
+        // the SP differs between the incoming edges of `0x6`
+        // 0: 2800            cmp     r0, #0
+        // 2: d000            beq     0x6
+        // 4: b580            push    {r7, lr}
+        // 6: bd80            pop     {r7, pc}
+        let code = [0x00, 0x28, 0x00, 0xd0, 0x80, 0xb5, 0x80, 0xbd];
+        assert_eq!(super::analyze(&code, 0, false, &[]).unwrap().4, None);
+
+        // jump table; the targets are the blocks that can't be reached by falling through
+        // 0: b580            push    {r7, lr}
+        // 2: e8df f000       tbb     [pc, r0]
+        // 6: bd80            pop     {r7, pc}
+        let code = [0x80, 0xb5, 0xdf, 0xe8, 0x00, 0xf0, 0x80, 0xbd];
+        assert_eq!(super::analyze(&code, 0, true, &[]).unwrap().4, Some(8));
+
+        // large stack frame on ARMv6-M
+        // 0: b570            push    {r4, r5, r6, lr}
+        // 2: 4e02            ldr     r6, [pc, #8]
+        // 4: 44b5            add     sp, r6
+        // 6: 4e02            ldr     r6, [pc, #8]
+        // 8: 44b5            add     sp, r6
+        // a: bd70            pop     {r4, r5, r6, pc}
+        //  c: ffffec70       .word   0xffffec70
+        // 10: 00001390       .word   0x00001390
+        let code = [
+            0x70, 0xb5, 0x02, 0x4e, 0xb5, 0x44, 0x02, 0x4e, 0xb5, 0x44, 0x70, 0xbd, 0x70, 0xec,
+            0xff, 0xff, 0x90, 0x13, 0x00, 0x00,
+        ];
+        let tags = [(0, super::Tag::Thumb), (0xc, super::Tag::Data)];
+        let bigframe = super::analyze(&code, 0, false, &tags).unwrap();
+        assert!(!bigframe.5);
+        assert_eq!(bigframe.4, Some(16 + 5008));
+    }
 }
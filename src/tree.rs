@@ -0,0 +1,194 @@
+//! Indented call tree (`--format tree`)
+
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    io::{self, Write},
+};
+
+use petgraph::{
+    graph::{Graph, NodeIndex},
+    visit::EdgeRef,
+};
+
+use crate::{Call, Node};
+
+pub fn write<W>(g: &Graph<Node, Call>, roots: &[NodeIndex], mut w: W) -> io::Result<()>
+where
+    W: Write,
+{
+    // NOTE shared by all the trees; a function is only expanded the first time it's printed
+    let mut printed = HashSet::new();
+
+    for (i, root) in roots.iter().enumerate() {
+        if i != 0 {
+            writeln!(w)?;
+        }
+
+        let mut path = vec![];
+        subtree(
+            g,
+            *root,
+            Call::Normal,
+            &mut String::new(),
+            &mut path,
+            &mut printed,
+            &mut w,
+        )?;
+    }
+
+    Ok(())
+}
+
+// writes `node`, after the branch that leads to it, and its callees, each one after `prefix`;
+// `call` is how `node` is called
+fn subtree<W>(
+    g: &Graph<Node, Call>,
+    node: NodeIndex,
+    call: Call,
+    prefix: &mut String,
+    path: &mut Vec<NodeIndex>,
+    printed: &mut HashSet<NodeIndex>,
+    w: &mut W,
+) -> io::Result<()>
+where
+    W: Write,
+{
+    write!(w, "{}", line(&g[node], call))?;
+
+    // NOTE `edges` yields the calls in reverse order of insertion
+    let mut callees = Vec::<(NodeIndex, Call)>::new();
+    for edge in g.edges(node).collect::<Vec<_>>().into_iter().rev() {
+        if let Some(callee) = callees
+            .iter_mut()
+            .find(|(callee, _)| *callee == edge.target())
+        {
+            // a normal call grows the stack more than a tail call
+            if *edge.weight() == Call::Normal {
+                callee.1 = Call::Normal;
+            }
+        } else {
+            callees.push((edge.target(), *edge.weight()));
+        }
+    }
+    if callees.is_empty() {
+        return writeln!(w);
+    }
+
+    if !printed.insert(node) {
+        // the callees have already been listed
+        return writeln!(w, " (*)");
+    }
+    writeln!(w)?;
+
+    // the callee that contributes the most to the maximum stack usage goes first
+    callees.sort_by_key(|(callee, _)| Reverse(g[*callee].max.map(|max| max.bytes())));
+
+    path.push(node);
+    for (i, (callee, call)) in callees.iter().enumerate() {
+        let last = i == callees.len() - 1;
+        write!(w, "{}{}", prefix, if last { "└── " } else { "├── " })?;
+
+        if path.contains(callee) {
+            writeln!(w, "{} (cycle)", line(&g[*callee], *call))?;
+            continue;
+        }
+
+        let len = prefix.len();
+        prefix.push_str(if last { "    " } else { "│   " });
+        subtree(g, *callee, *call, prefix, path, printed, w)?;
+        prefix.truncate(len);
+    }
+    path.pop();
+
+    Ok(())
+}
+
+fn line(node: &Node, call: Call) -> String {
+    let mut line = if let Some(max) = node.max {
+        format!("{} [local = {}, max {}]", node.demangled, node.local, max)
+    } else {
+        format!("{} [local = {}]", node.demangled, node.local)
+    };
+
+    if let Some(location) = &node.location {
+        line.push_str(&format!(" {}", location));
+    }
+
+    if call == Call::Tail {
+        line.push_str(" (tail call)");
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::graph::DiGraph;
+
+    use crate::{Call, Max, Node};
+
+    #[test]
+    fn tree() {
+        let mut g = DiGraph::new();
+        let mut node = |name, local, max| {
+            let mut node = Node(name, Some(local), false);
+            node.max = Some(max);
+            g.add_node(node)
+        };
+        let main = node("main", 8, Max::LowerBound(56));
+        let foo = node("foo", 16, Max::LowerBound(48));
+        let bar = node("bar", 32, Max::LowerBound(32));
+        let baz = node("baz", 0, Max::Exact(0));
+        g.add_edge(main, foo, Call::Normal);
+        g.add_edge(main, bar, Call::Normal);
+        g.add_edge(foo, bar, Call::Normal);
+        g.add_edge(bar, foo, Call::Normal);
+        g.add_edge(bar, baz, Call::Normal);
+        // a second call that's not next to the first one in the list of edges
+        g.add_edge(main, foo, Call::Normal);
+
+        let mut out = vec![];
+        super::write(&g, &[main], &mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+main [local = 8, max >= 56]
+├── foo [local = 16, max >= 48]
+│   └── bar [local = 32, max >= 32]
+│       ├── foo [local = 16, max >= 48] (cycle)
+│       └── baz [local = 0, max = 0]
+└── bar [local = 32, max >= 32] (*)
+"
+        );
+    }
+
+    #[test]
+    fn tail_calls() {
+        let mut g = DiGraph::new();
+        let mut node = |name, local, max| {
+            let mut node = Node(name, Some(local), false);
+            node.max = Some(max);
+            g.add_node(node)
+        };
+        let main = node("main", 8, Max::Exact(24));
+        let foo = node("foo", 16, Max::Exact(16));
+        let bar = node("bar", 4, Max::Exact(4));
+        g.add_edge(main, foo, Call::Normal);
+        g.add_edge(main, bar, Call::Tail);
+        g.add_edge(main, foo, Call::Tail);
+
+        let mut out = vec![];
+        super::write(&g, &[main], &mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+main [local = 8, max = 24]
+├── foo [local = 16, max = 16]
+└── bar [local = 4, max = 4] (tail call)
+"
+        );
+    }
+}
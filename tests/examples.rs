@@ -163,6 +163,36 @@ fn to() {
     }
 }
 
+#[test]
+fn json() {
+    if channel_is_nightly() {
+        let json = call_stack_with("fmul", &["--format", "json"]);
+        let cg: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(cg["version"], 2);
+
+        let id = |demangled: &str| {
+            cg["nodes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|node| node["demangled"] == demangled)
+                .map(|node| node["id"].as_u64().unwrap())
+                .unwrap()
+        };
+
+        let main = id("main");
+        let fmul = id("__aeabi_fmul");
+
+        // there must be an edge between `main` and `__aeabi_fmul`
+        assert!(cg["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|edge| edge["source"] == main && edge["target"] == fmul));
+    }
+}
+
 #[test]
 fn defmt() {
     if channel_is_nightly() {
@@ -200,8 +230,13 @@ fn channel_is_nightly() -> bool {
 }
 
 fn call_stack(ex: &str) -> String {
+    call_stack_with(ex, &[])
+}
+
+fn call_stack_with(ex: &str, args: &[&str]) -> String {
     let output = Command::new("cargo")
         .args(&["call-stack", "--example", ex])
+        .args(args)
         .current_dir(env::current_dir().unwrap().join("cortex-m-examples"))
         .output()
         .unwrap();